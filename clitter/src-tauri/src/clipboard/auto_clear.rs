@@ -0,0 +1,121 @@
+//! Taking secrets off the system clipboard some time after Clitter put them there
+
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use crate::clipboard::backend::{system_backend, ClipboardBackend};
+use crate::clipboard::monitor;
+use crate::types::{Category, ClipboardContent};
+use crate::APP_STATE;
+
+/// Settings key holding the policy as JSON
+pub const AUTO_CLEAR_SETTING: &str = "secure_auto_clear";
+
+/// What happens after a Secure item was copied or pasted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AutoClearPolicy {
+    /// Seconds the secret stays on the clipboard, None to leave it there
+    pub clear_after_secs: Option<u32>,
+    /// Put back what was on the clipboard before, unless that was a secret too
+    pub restore_previous: bool,
+}
+
+impl Default for AutoClearPolicy {
+    fn default() -> Self {
+        Self {
+            clear_after_secs: Some(30),
+            restore_previous: false,
+        }
+    }
+}
+
+// Incremented for every secret written, so only the latest timer acts
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+
+/// Put content on the clipboard like `monitor::set_clipboard_content`, scheduling
+/// Secure content to be cleared according to the policy
+pub async fn set_clipboard_content(
+    backend: &mut dyn ClipboardBackend,
+    content: &ClipboardContent,
+) -> Result<(), String> {
+    let policy = match APP_STATE.get() {
+        Some(state) => *state.auto_clear.read().await,
+        None => AutoClearPolicy::default(),
+    };
+    let delay = match policy.clear_after_secs {
+        Some(secs) if content.category == Category::Secure => Duration::from_secs(secs as u64),
+        _ => return monitor::set_clipboard_content(backend, content),
+    };
+
+    let previous = if policy.restore_previous {
+        monitor::read_clipboard_content(backend).and_then(|previous| {
+            if previous.category == Category::Secure {
+                previous.wipe();
+                None
+            } else {
+                Some(previous)
+            }
+        })
+    } else {
+        None
+    };
+
+    monitor::set_clipboard_content(backend, content)?;
+    schedule_clear(delay, content.content_hash(), previous);
+    Ok(())
+}
+
+fn schedule_clear(delay: Duration, hash: String, previous: Option<ClipboardContent>) {
+    let generation = GENERATION.fetch_add(1, Ordering::SeqCst) + 1;
+    tauri::async_runtime::spawn(async move {
+        tokio::time::sleep(delay).await;
+        // A newer secret has its own timer
+        if GENERATION.load(Ordering::SeqCst) != generation {
+            return;
+        }
+
+        let result = system_backend().and_then(|mut backend| {
+            monitor::clear_clipboard_content(backend.as_mut(), &hash, previous.as_ref())
+        });
+        if let Err(e) = result {
+            eprintln!("Failed to clear secret from clipboard: {}", e);
+        }
+    });
+}
+
+/// Load the saved policy from storage
+pub async fn load_saved_policy() {
+    if let Some(state) = APP_STATE.get() {
+        let storage_guard = state.persistent_storage.read().await;
+        if let Some(storage) = storage_guard.as_ref() {
+            match storage.get_setting(AUTO_CLEAR_SETTING).await {
+                Ok(Some(json)) => match serde_json::from_str::<AutoClearPolicy>(&json) {
+                    Ok(policy) => *state.auto_clear.write().await = policy,
+                    Err(e) => eprintln!("Invalid auto-clear setting: {}", e),
+                },
+                Ok(None) => {}
+                Err(e) => {
+                    eprintln!("Failed to read auto-clear setting: {}", e);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_policy_json() {
+        let json = serde_json::to_value(AutoClearPolicy::default()).unwrap();
+        assert_eq!(json, serde_json::json!({ "clearAfterSecs": 30, "restorePrevious": false }));
+
+        let never: AutoClearPolicy =
+            serde_json::from_value(serde_json::json!({ "clearAfterSecs": null, "restorePrevious": true }))
+                .unwrap();
+        assert_eq!(never.clear_after_secs, None);
+    }
+}
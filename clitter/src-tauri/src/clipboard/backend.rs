@@ -0,0 +1,228 @@
+//! Clipboard access abstraction
+//! The monitor and commands only talk to `ClipboardBackend`, so capture logic can run
+//! against the in-memory implementation without a display server
+
+use arboard::Clipboard;
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc::UnboundedSender;
+
+/// One representation of the clipboard contents
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClipboardFormat {
+    Text(String),
+    Html(String),
+    Rtf(String),
+    /// `file://` URIs
+    Files(Vec<String>),
+    Image {
+        width: u32,
+        height: u32,
+        rgba: Vec<u8>,
+    },
+}
+
+pub trait ClipboardBackend: Send {
+    /// Read every format currently available on the clipboard
+    fn read_formats(&mut self) -> Result<Vec<ClipboardFormat>, String>;
+
+    /// Replace the clipboard contents with the given formats
+    fn write_formats(&mut self, formats: &[ClipboardFormat]) -> Result<(), String>;
+
+    /// Send `()` on `tx` whenever the clipboard changes.
+    /// Returns false if the backend cannot notify and has to be polled instead.
+    fn subscribe(&mut self, _tx: UnboundedSender<()>) -> bool {
+        false
+    }
+
+    /// Whether the clipboard owner asked clipboard managers not to record the content,
+    /// as password managers do
+    fn has_exclusion_hint(&mut self) -> bool {
+        false
+    }
+}
+
+/// Target set by KeePassXC and other password managers on X11/Wayland
+#[cfg(target_os = "linux")]
+const KDE_PASSWORD_MANAGER_HINT: &str = "x-kde-passwordManagerHint";
+
+/// Create the backend for the current platform
+pub fn system_backend() -> Result<Box<dyn ClipboardBackend>, String> {
+    #[cfg(target_os = "windows")]
+    {
+        Ok(Box::new(crate::clipboard::windows_clipboard::WindowsBackend::new()?))
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    {
+        Ok(Box::new(ArboardBackend::new()?))
+    }
+}
+
+/// Cross-platform backend built on arboard
+pub struct ArboardBackend {
+    clipboard: Clipboard,
+}
+
+impl ArboardBackend {
+    pub fn new() -> Result<Self, String> {
+        let clipboard = Clipboard::new().map_err(|e| format!("Failed to create clipboard: {}", e))?;
+        Ok(Self { clipboard })
+    }
+}
+
+impl ClipboardBackend for ArboardBackend {
+    fn read_formats(&mut self) -> Result<Vec<ClipboardFormat>, String> {
+        let mut formats = Vec::new();
+
+        if let Ok(text) = self.clipboard.get_text() {
+            if !text.is_empty() {
+                formats.push(ClipboardFormat::Text(text));
+            }
+        }
+
+        if let Ok(html) = self.clipboard.get().html() {
+            if !html.is_empty() {
+                formats.push(ClipboardFormat::Html(html));
+            }
+        }
+
+        if let Ok(paths) = self.clipboard.get().file_list() {
+            let uris: Vec<String> = paths
+                .iter()
+                .filter_map(|path| url::Url::from_file_path(path).ok())
+                .map(|url| url.to_string())
+                .collect();
+            if !uris.is_empty() {
+                formats.push(ClipboardFormat::Files(uris));
+            }
+        }
+
+        if let Ok(img) = self.clipboard.get_image() {
+            formats.push(ClipboardFormat::Image {
+                width: img.width as u32,
+                height: img.height as u32,
+                rgba: img.bytes.into_owned(),
+            });
+        }
+
+        Ok(formats)
+    }
+
+    fn write_formats(&mut self, formats: &[ClipboardFormat]) -> Result<(), String> {
+        let text = formats.iter().find_map(|f| match f {
+            ClipboardFormat::Text(text) => Some(text.as_str()),
+            _ => None,
+        });
+
+        // arboard has no RTF support, such content is written as its plain text
+        for format in formats {
+            match format {
+                ClipboardFormat::Files(uris) => {
+                    let paths: Vec<std::path::PathBuf> = uris
+                        .iter()
+                        .filter_map(|uri| url::Url::parse(uri).ok()?.to_file_path().ok())
+                        .collect();
+                    return self.clipboard.set().file_list(&paths).map_err(|e| e.to_string());
+                }
+                ClipboardFormat::Html(html) => {
+                    return self.clipboard.set().html(html.as_str(), text).map_err(|e| e.to_string());
+                }
+                _ => {}
+            }
+        }
+
+        if let Some(text) = text {
+            return self.clipboard.set_text(text).map_err(|e| e.to_string());
+        }
+
+        for format in formats {
+            if let ClipboardFormat::Image { width, height, rgba } = format {
+                let img_data = arboard::ImageData {
+                    width: *width as usize,
+                    height: *height as usize,
+                    bytes: rgba.as_slice().into(),
+                };
+                return self.clipboard.set_image(img_data).map_err(|e| e.to_string());
+            }
+        }
+
+        self.clipboard.clear().map_err(|e| e.to_string())
+    }
+
+    #[cfg(target_os = "linux")]
+    fn subscribe(&mut self, tx: UnboundedSender<()>) -> bool {
+        match crate::clipboard::linux_watcher::spawn_listener(tx) {
+            Ok(source) => {
+                println!("Clipboard monitor using {} notifications", source);
+                true
+            }
+            Err(e) => {
+                eprintln!("Clipboard change notifications unavailable, polling instead: {}", e);
+                false
+            }
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    fn has_exclusion_hint(&mut self) -> bool {
+        crate::clipboard::linux_watcher::selection_has_target(KDE_PASSWORD_MANAGER_HINT)
+    }
+}
+
+#[derive(Default)]
+struct MemoryClipboard {
+    formats: Vec<ClipboardFormat>,
+    exclusion_hint: bool,
+    subscribers: Vec<UnboundedSender<()>>,
+}
+
+/// In-memory clipboard for headless testing.
+/// Clones share the same contents, so one clone can play the role of another application.
+#[derive(Clone, Default)]
+pub struct MemoryBackend {
+    inner: Arc<Mutex<MemoryClipboard>>,
+}
+
+impl MemoryBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Write content flagged the way password managers flag secrets
+    pub fn write_hinted(&mut self, formats: &[ClipboardFormat]) -> Result<(), String> {
+        self.write_formats(formats)?;
+        let mut inner = self.inner.lock().map_err(|e| e.to_string())?;
+        inner.exclusion_hint = true;
+        Ok(())
+    }
+}
+
+impl ClipboardBackend for MemoryBackend {
+    fn read_formats(&mut self) -> Result<Vec<ClipboardFormat>, String> {
+        let inner = self.inner.lock().map_err(|e| e.to_string())?;
+        Ok(inner.formats.clone())
+    }
+
+    fn write_formats(&mut self, formats: &[ClipboardFormat]) -> Result<(), String> {
+        let mut inner = self.inner.lock().map_err(|e| e.to_string())?;
+        inner.formats = formats.to_vec();
+        inner.exclusion_hint = false;
+        // Drop subscribers whose receiver is gone
+        inner.subscribers.retain(|tx| tx.send(()).is_ok());
+        Ok(())
+    }
+
+    fn subscribe(&mut self, tx: UnboundedSender<()>) -> bool {
+        match self.inner.lock() {
+            Ok(mut inner) => {
+                inner.subscribers.push(tx);
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    fn has_exclusion_hint(&mut self) -> bool {
+        self.inner.lock().map(|inner| inner.exclusion_hint).unwrap_or(false)
+    }
+}
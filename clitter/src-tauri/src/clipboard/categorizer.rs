@@ -47,7 +47,10 @@ impl Categorizer {
     pub fn categorize(data: &ClipboardData) -> Category {
         match data {
             ClipboardData::Image { .. } => Category::Image,
-            ClipboardData::Text { text, .. } => Self::categorize_text(text),
+            ClipboardData::Text { text, .. }
+            | ClipboardData::Html { text, .. }
+            | ClipboardData::Rtf { text, .. } => Self::categorize_text(text),
+            ClipboardData::Files { .. } => Category::Text,
         }
     }
 
@@ -125,7 +128,32 @@ mod tests {
             width: 100,
             height: 100,
             format: "png".to_string(),
+            thumbnail: None,
+            preview: None,
         };
         assert_eq!(Categorizer::categorize(&data), Category::Image);
     }
+
+    #[test]
+    fn test_categorize_rich_text_by_plain_text() {
+        let data = ClipboardData::Html {
+            html: "<b>password: hunter2</b>".to_string(),
+            text: "password: hunter2".to_string(),
+            preview: "password: hunter2".to_string(),
+        };
+        assert_eq!(Categorizer::categorize(&data), Category::Secure);
+
+        let data = ClipboardData::Rtf {
+            rtf: r"{\rtf1 https://example.com}".to_string(),
+            text: "https://example.com".to_string(),
+            preview: "https://example.com".to_string(),
+        };
+        assert_eq!(Categorizer::categorize(&data), Category::Url);
+    }
+
+    #[test]
+    fn test_categorize_files() {
+        let data = ClipboardData::files(vec!["file:///tmp/report.pdf".to_string()]);
+        assert_eq!(Categorizer::categorize(&data), Category::Text);
+    }
 }
@@ -0,0 +1,119 @@
+//! Applications whose clipboard content is never recorded
+
+use tokio::sync::RwLock;
+
+use crate::APP_STATE;
+
+/// Settings key holding the ignore list as a JSON array
+pub const EXCLUDED_APPS_SETTING: &str = "excluded_apps";
+
+/// Password managers ignored until the user edits the list
+const DEFAULT_EXCLUDED_APPS: &[&str] = &[
+    "KeePassXC",
+    "KeePass",
+    "1Password",
+    "Bitwarden",
+    "Enpass",
+    "LastPass",
+    "Dashlane",
+];
+
+pub struct ExclusionList {
+    apps: RwLock<Vec<String>>,
+}
+
+impl ExclusionList {
+    pub fn new() -> Self {
+        Self {
+            apps: RwLock::new(DEFAULT_EXCLUDED_APPS.iter().map(|s| s.to_string()).collect()),
+        }
+    }
+
+    pub async fn apps(&self) -> Vec<String> {
+        self.apps.read().await.clone()
+    }
+
+    pub async fn set_apps(&self, apps: Vec<String>) {
+        *self.apps.write().await = apps;
+    }
+
+    /// Whether the application name (as reported by `window_focus::foreground_app`) is ignored
+    pub async fn is_excluded(&self, app: &str) -> bool {
+        let app = normalize(app);
+        self.apps.read().await.iter().any(|a| normalize(a) == app)
+    }
+}
+
+impl Default for ExclusionList {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Compare names case-insensitively and without the Windows executable extension
+fn normalize(name: &str) -> String {
+    let name = name.trim().to_lowercase();
+    match name.strip_suffix(".exe") {
+        Some(stem) => stem.to_string(),
+        None => name,
+    }
+}
+
+/// Clean up a user-supplied list: trim names, drop blanks and duplicates
+pub fn sanitize(apps: Vec<String>) -> Vec<String> {
+    let mut result: Vec<String> = Vec::new();
+    for app in apps {
+        let app = app.trim().to_string();
+        if !app.is_empty() && !result.iter().any(|a| normalize(a) == normalize(&app)) {
+            result.push(app);
+        }
+    }
+    result
+}
+
+/// Load the saved ignore list from storage
+pub async fn load_saved_exclusions() {
+    if let Some(state) = APP_STATE.get() {
+        let storage_guard = state.persistent_storage.read().await;
+        if let Some(storage) = storage_guard.as_ref() {
+            match storage.get_setting(EXCLUDED_APPS_SETTING).await {
+                Ok(Some(json)) => match serde_json::from_str::<Vec<String>>(&json) {
+                    Ok(apps) => state.exclusions.set_apps(apps).await,
+                    Err(e) => eprintln!("Invalid excluded apps setting: {}", e),
+                },
+                Ok(None) => {}
+                Err(e) => {
+                    eprintln!("Failed to read excluded apps setting: {}", e);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_matching_ignores_case_and_extension() {
+        let list = ExclusionList::new();
+        assert!(list.is_excluded("keepassxc").await);
+        assert!(list.is_excluded("1Password.exe").await);
+        assert!(!list.is_excluded("firefox").await);
+
+        list.set_apps(vec!["Code.exe".to_string()]).await;
+        assert!(list.is_excluded("code").await);
+        assert!(!list.is_excluded("KeePassXC").await);
+    }
+
+    #[test]
+    fn test_sanitize() {
+        let apps = vec![
+            " KeePassXC ".to_string(),
+            "".to_string(),
+            "keepassxc.exe".to_string(),
+            "Bitwarden".to_string(),
+        ];
+        assert_eq!(sanitize(apps), vec!["KeePassXC", "Bitwarden"]);
+    }
+}
@@ -0,0 +1,392 @@
+//! Linux clipboard change notifications
+//! Uses the ext/wlr data-control protocols on Wayland and XFixes selection events on X11
+//! so the monitor only reads the clipboard when its owner actually changes
+
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc::UnboundedSender;
+use wayland_client::{
+    backend::ObjectId,
+    delegate_noop, event_created_child,
+    globals::{registry_queue_init, GlobalListContents},
+    protocol::{wl_registry, wl_seat::WlSeat},
+    Connection as WaylandConnection, Dispatch, Proxy, QueueHandle,
+};
+use wayland_protocols::ext::data_control::v1::client::{
+    ext_data_control_device_v1::{self, ExtDataControlDeviceV1},
+    ext_data_control_manager_v1::ExtDataControlManagerV1,
+    ext_data_control_offer_v1::{self, ExtDataControlOfferV1},
+};
+use wayland_protocols_wlr::data_control::v1::client::{
+    zwlr_data_control_device_v1::{self, ZwlrDataControlDeviceV1},
+    zwlr_data_control_manager_v1::ZwlrDataControlManagerV1,
+    zwlr_data_control_offer_v1::{self, ZwlrDataControlOfferV1},
+};
+use x11rb::connection::Connection as X11Connection;
+use x11rb::protocol::xfixes::{ConnectionExt as _, SelectionEventMask};
+use x11rb::protocol::xproto::{AtomEnum, ConnectionExt as _, CreateWindowAux, WindowClass};
+use x11rb::protocol::Event;
+
+// MIME types of the current selection, as announced over data-control or asked
+// for by the XFixes listener. None when no listener is running.
+static SELECTION_TARGETS: Lazy<Mutex<Option<Vec<String>>>> = Lazy::new(|| Mutex::new(None));
+
+/// Start a background listener that sends `()` every time the clipboard selection changes.
+/// Returns an error if no event source is available, in which case the caller should poll.
+pub fn spawn_listener(tx: UnboundedSender<()>) -> Result<&'static str, String> {
+    if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+        match start_wayland(tx.clone()) {
+            Ok(name) => return Ok(name),
+            Err(e) => eprintln!("Wayland clipboard listener unavailable: {}", e),
+        }
+    }
+
+    if std::env::var_os("DISPLAY").is_some() {
+        return start_x11(tx).map(|_| "xfixes");
+    }
+
+    Err("No supported display server found".to_string())
+}
+
+/// Whether the current clipboard owner offers the given target (MIME type).
+/// Only asks the X11 owner directly when no listener records the targets.
+pub fn selection_has_target(target: &str) -> bool {
+    if let Ok(mimes) = SELECTION_TARGETS.lock() {
+        if let Some(mimes) = mimes.as_ref() {
+            return mimes.iter().any(|m| m == target);
+        }
+    }
+
+    if std::env::var_os("DISPLAY").is_some() {
+        return x11_selection_targets()
+            .map(|targets| targets.iter().any(|t| t == target))
+            .unwrap_or(false);
+    }
+
+    false
+}
+
+/// Ask the X11 clipboard owner for its TARGETS list. Waits up to 200ms for the
+/// reply, so it is called from the listener thread.
+fn x11_selection_targets() -> Option<Vec<String>> {
+    let (conn, screen_num) = x11rb::connect(None).ok()?;
+    let root = conn.setup().roots[screen_num].root;
+
+    let atom = |name: &[u8]| Some(conn.intern_atom(false, name).ok()?.reply().ok()?.atom);
+    let clipboard = atom(b"CLIPBOARD")?;
+    let targets = atom(b"TARGETS")?;
+    let property = atom(b"CLITTER_TARGETS")?;
+
+    // The owner delivers the conversion result to a window of ours
+    let window = conn.generate_id().ok()?;
+    conn.create_window(
+        x11rb::COPY_DEPTH_FROM_PARENT,
+        window,
+        root,
+        0,
+        0,
+        1,
+        1,
+        0,
+        WindowClass::INPUT_ONLY,
+        x11rb::COPY_FROM_PARENT,
+        &CreateWindowAux::new(),
+    )
+    .ok()?;
+    conn.convert_selection(window, clipboard, targets, property, x11rb::CURRENT_TIME)
+        .ok()?;
+    conn.flush().ok()?;
+
+    let deadline = Instant::now() + Duration::from_millis(200);
+    loop {
+        match conn.poll_for_event().ok()? {
+            Some(Event::SelectionNotify(event)) if event.requestor == window => {
+                if event.property == x11rb::NONE {
+                    return Some(Vec::new());
+                }
+                let reply = conn
+                    .get_property(true, window, property, AtomEnum::ATOM, 0, 1024)
+                    .ok()?
+                    .reply()
+                    .ok()?;
+                let names = reply
+                    .value32()?
+                    .filter_map(|a| conn.get_atom_name(a).ok()?.reply().ok())
+                    .map(|r| String::from_utf8_lossy(&r.name).into_owned())
+                    .collect();
+                return Some(names);
+            }
+            Some(_) => {}
+            None if Instant::now() >= deadline => return None,
+            None => std::thread::sleep(Duration::from_millis(5)),
+        }
+    }
+}
+
+fn start_x11(tx: UnboundedSender<()>) -> Result<(), String> {
+    let (conn, screen_num) =
+        x11rb::connect(None).map_err(|e| format!("Failed to connect to X server: {}", e))?;
+
+    // XFixes has to be initialized before its requests can be used
+    conn.xfixes_query_version(5, 0)
+        .map_err(|e| e.to_string())?
+        .reply()
+        .map_err(|e| format!("XFixes not available: {}", e))?;
+
+    let root = conn.setup().roots[screen_num].root;
+    let clipboard_atom = conn
+        .intern_atom(false, b"CLIPBOARD")
+        .map_err(|e| e.to_string())?
+        .reply()
+        .map_err(|e| e.to_string())?
+        .atom;
+
+    conn.xfixes_select_selection_input(
+        root,
+        clipboard_atom,
+        SelectionEventMask::SET_SELECTION_OWNER
+            | SelectionEventMask::SELECTION_WINDOW_DESTROY
+            | SelectionEventMask::SELECTION_CLIENT_CLOSE,
+    )
+    .map_err(|e| e.to_string())?;
+    conn.flush().map_err(|e| e.to_string())?;
+
+    std::thread::Builder::new()
+        .name("clipboard-xfixes".to_string())
+        .spawn(move || {
+            loop {
+                match conn.wait_for_event() {
+                    Ok(Event::XfixesSelectionNotify(event)) if event.selection == clipboard_atom => {
+                        // Recorded before the monitor is told, so it never waits for the owner
+                        let targets = x11_selection_targets().unwrap_or_default();
+                        if let Ok(mut current) = SELECTION_TARGETS.lock() {
+                            *current = Some(targets);
+                        }
+                        // Receiver gone means the monitor has shut down
+                        if tx.send(()).is_err() {
+                            break;
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(e) => {
+                        eprintln!("X11 clipboard listener stopped: {}", e);
+                        break;
+                    }
+                }
+            }
+            if let Ok(mut current) = SELECTION_TARGETS.lock() {
+                *current = None;
+            }
+        })
+        .map_err(|e| e.to_string())?;
+
+    Ok(())
+}
+
+struct WaylandState {
+    tx: UnboundedSender<()>,
+    // MIME types announced by offers that have not become a selection yet
+    offers: HashMap<ObjectId, Vec<String>>,
+    finished: bool,
+}
+
+impl WaylandState {
+    fn set_selection(&mut self, offer: Option<ObjectId>) {
+        let mimes = offer
+            .and_then(|id| self.offers.remove(&id))
+            .unwrap_or_default();
+        if let Ok(mut current) = SELECTION_TARGETS.lock() {
+            *current = Some(mimes);
+        }
+
+        if self.tx.send(()).is_err() {
+            self.finished = true;
+        }
+    }
+
+    fn add_offered_mime(&mut self, offer: ObjectId, mime_type: String) {
+        self.offers.entry(offer).or_default().push(mime_type);
+    }
+}
+
+fn start_wayland(tx: UnboundedSender<()>) -> Result<&'static str, String> {
+    let conn = WaylandConnection::connect_to_env()
+        .map_err(|e| format!("Failed to connect to Wayland compositor: {}", e))?;
+    let (globals, mut queue) =
+        registry_queue_init::<WaylandState>(&conn).map_err(|e| e.to_string())?;
+    let qh = queue.handle();
+
+    let seat: WlSeat = globals
+        .bind(&qh, 1..=1, ())
+        .map_err(|e| format!("No seat available: {}", e))?;
+
+    // Prefer the standardized ext protocol, fall back to the wlroots one
+    let protocol = if let Ok(manager) = globals.bind::<ExtDataControlManagerV1, _, _>(&qh, 1..=1, ()) {
+        manager.get_data_device(&seat, &qh, ());
+        "ext-data-control"
+    } else {
+        let manager: ZwlrDataControlManagerV1 = globals
+            .bind(&qh, 1..=1, ())
+            .map_err(|_| "Compositor does not support data-control".to_string())?;
+        manager.get_data_device(&seat, &qh, ());
+        "wlr-data-control"
+    };
+
+    let mut state = WaylandState {
+        tx,
+        offers: HashMap::new(),
+        finished: false,
+    };
+
+    std::thread::Builder::new()
+        .name("clipboard-data-control".to_string())
+        .spawn(move || {
+            while !state.finished {
+                if let Err(e) = queue.blocking_dispatch(&mut state) {
+                    eprintln!("Wayland clipboard listener stopped: {}", e);
+                    break;
+                }
+            }
+            // Selection metadata is stale from here on
+            if let Ok(mut current) = SELECTION_TARGETS.lock() {
+                *current = None;
+            }
+        })
+        .map_err(|e| e.to_string())?;
+
+    Ok(protocol)
+}
+
+impl Dispatch<wl_registry::WlRegistry, GlobalListContents> for WaylandState {
+    fn event(
+        _state: &mut Self,
+        _registry: &wl_registry::WlRegistry,
+        _event: wl_registry::Event,
+        _data: &GlobalListContents,
+        _conn: &WaylandConnection,
+        _qh: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+delegate_noop!(WaylandState: ignore WlSeat);
+delegate_noop!(WaylandState: ExtDataControlManagerV1);
+delegate_noop!(WaylandState: ZwlrDataControlManagerV1);
+
+impl Dispatch<ExtDataControlOfferV1, ()> for WaylandState {
+    fn event(
+        state: &mut Self,
+        offer: &ExtDataControlOfferV1,
+        event: ext_data_control_offer_v1::Event,
+        _data: &(),
+        _conn: &WaylandConnection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        if let ext_data_control_offer_v1::Event::Offer { mime_type } = event {
+            state.add_offered_mime(offer.id(), mime_type);
+        }
+    }
+}
+
+impl Dispatch<ZwlrDataControlOfferV1, ()> for WaylandState {
+    fn event(
+        state: &mut Self,
+        offer: &ZwlrDataControlOfferV1,
+        event: zwlr_data_control_offer_v1::Event,
+        _data: &(),
+        _conn: &WaylandConnection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        if let zwlr_data_control_offer_v1::Event::Offer { mime_type } = event {
+            state.add_offered_mime(offer.id(), mime_type);
+        }
+    }
+}
+
+impl Dispatch<ExtDataControlDeviceV1, ()> for WaylandState {
+    fn event(
+        state: &mut Self,
+        _device: &ExtDataControlDeviceV1,
+        event: ext_data_control_device_v1::Event,
+        _data: &(),
+        _conn: &WaylandConnection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        match event {
+            ext_data_control_device_v1::Event::Selection { id } => {
+                // Content is read through the regular clipboard API, the offer is not needed
+                let offer_id = id.map(|offer| {
+                    offer.destroy();
+                    offer.id()
+                });
+                state.set_selection(offer_id);
+            }
+            ext_data_control_device_v1::Event::PrimarySelection { id: Some(offer) } => {
+                state.offers.remove(&offer.id());
+                offer.destroy();
+            }
+            ext_data_control_device_v1::Event::Finished => {
+                state.finished = true;
+            }
+            _ => {}
+        }
+    }
+
+    event_created_child!(WaylandState, ExtDataControlDeviceV1, [
+        ext_data_control_device_v1::EVT_DATA_OFFER_OPCODE => (ExtDataControlOfferV1, ()),
+    ]);
+}
+
+impl Dispatch<ZwlrDataControlDeviceV1, ()> for WaylandState {
+    fn event(
+        state: &mut Self,
+        _device: &ZwlrDataControlDeviceV1,
+        event: zwlr_data_control_device_v1::Event,
+        _data: &(),
+        _conn: &WaylandConnection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        match event {
+            zwlr_data_control_device_v1::Event::Selection { id } => {
+                let offer_id = id.map(|offer| {
+                    offer.destroy();
+                    offer.id()
+                });
+                state.set_selection(offer_id);
+            }
+            zwlr_data_control_device_v1::Event::PrimarySelection { id: Some(offer) } => {
+                state.offers.remove(&offer.id());
+                offer.destroy();
+            }
+            zwlr_data_control_device_v1::Event::Finished => {
+                state.finished = true;
+            }
+            _ => {}
+        }
+    }
+
+    event_created_child!(WaylandState, ZwlrDataControlDeviceV1, [
+        zwlr_data_control_device_v1::EVT_DATA_OFFER_OPCODE => (ZwlrDataControlOfferV1, ()),
+    ]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    #[ignore = "requires an X server, run with `xvfb-run cargo test -- --ignored`"]
+    async fn test_xfixes_reports_selection_change() {
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        start_x11(tx).expect("XFixes listener should start");
+
+        let mut clipboard = arboard::Clipboard::new().unwrap();
+        clipboard.set_text("clitter xfixes test").unwrap();
+
+        let notified = tokio::time::timeout(Duration::from_secs(2), rx.recv()).await;
+        assert_eq!(notified.ok().flatten(), Some(()));
+    }
+}
@@ -1,4 +1,10 @@
+pub mod auto_clear;
+pub mod backend;
 pub mod categorizer;
+pub mod exclusion;
+#[cfg(target_os = "linux")]
+pub mod linux_watcher;
 pub mod monitor;
+pub mod thumbnail;
 #[cfg(target_os = "windows")]
 pub mod windows_clipboard;
@@ -1,151 +1,461 @@
-use arboard::Clipboard;
 use base64::{engine::general_purpose::STANDARD, Engine};
-use std::sync::atomic::{AtomicU64, Ordering};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::sync::Mutex;
 use std::time::Duration;
 use tauri::{AppHandle, Emitter};
-use tokio::sync::watch;
+use tokio::sync::{mpsc, watch};
 
+use crate::clipboard::backend::{system_backend, ClipboardBackend, ClipboardFormat};
 use crate::clipboard::categorizer::Categorizer;
+use crate::storage::history;
+use crate::tray;
 use crate::types::{ClipboardContent, ClipboardData};
+use crate::window_focus;
 use crate::APP_STATE;
 
-static LAST_CONTENT_HASH: AtomicU64 = AtomicU64::new(0);
-// Hash of content that was copied by Clitter itself (should be skipped)
-static SELF_COPIED_HASH: AtomicU64 = AtomicU64::new(0);
+/// What the monitor loop should be doing
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(tag = "state", rename_all = "camelCase")]
+pub enum MonitorState {
+    Running,
+    /// Clipboard changes are ignored; resumes by itself at `until` if set
+    Paused { until: Option<DateTime<Utc>> },
+    Shutdown,
+}
+
+/// Remembers the last clipboard content seen and the content Clitter wrote itself
+pub struct ChangeTracker {
+    last_hash: Mutex<String>,
+    // Hash of content that was copied by Clitter itself (should be skipped)
+    self_copied_hash: Mutex<String>,
+}
+
+impl ChangeTracker {
+    pub const fn new() -> Self {
+        Self {
+            last_hash: Mutex::new(String::new()),
+            self_copied_hash: Mutex::new(String::new()),
+        }
+    }
+
+    pub fn mark_as_self_copied(&self, hash: String) {
+        if let Ok(mut self_copied) = self.self_copied_hash.lock() {
+            *self_copied = hash;
+        }
+    }
+
+    /// Forget the last seen content, so copying it again counts as a change
+    fn forget_last(&self) {
+        if let Ok(mut last) = self.last_hash.lock() {
+            last.clear();
+        }
+    }
+
+    /// Returns true if the hash differs from the last seen content and was not copied by Clitter
+    fn observe(&self, hash: String) -> bool {
+        {
+            let Ok(mut last) = self.last_hash.lock() else {
+                return false;
+            };
+            if *last == hash {
+                return false;
+            }
+            last.clone_from(&hash);
+        }
+
+        // Skip if this was copied by Clitter itself
+        if let Ok(mut self_copied) = self.self_copied_hash.lock() {
+            if *self_copied == hash {
+                self_copied.clear(); // Reset
+                return false;
+            }
+        }
+        true
+    }
+}
+
+impl Default for ChangeTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+static TRACKER: ChangeTracker = ChangeTracker::new();
 
-// Shutdown signal sender (stored globally to allow shutdown from anywhere)
-static SHUTDOWN_TX: once_cell::sync::OnceCell<watch::Sender<bool>> = once_cell::sync::OnceCell::new();
+// Monitor control sender (stored globally to allow pause/shutdown from anywhere)
+static CONTROL_TX: once_cell::sync::OnceCell<watch::Sender<MonitorState>> =
+    once_cell::sync::OnceCell::new();
 
 /// Mark a content hash as self-copied (will be skipped by monitor)
-pub fn mark_as_self_copied(hash: u64) {
-    SELF_COPIED_HASH.store(hash, Ordering::Relaxed);
+pub fn mark_as_self_copied(hash: String) {
+    TRACKER.mark_as_self_copied(hash);
+}
+
+/// Put content on the clipboard without it being captured again by the monitor
+pub fn set_clipboard_content(
+    backend: &mut dyn ClipboardBackend,
+    content: &ClipboardContent,
+) -> Result<(), String> {
+    write_content(&TRACKER, backend, content)
+}
+
+/// What is on the clipboard now, read the same way the monitor captures it
+pub fn read_clipboard_content(backend: &mut dyn ClipboardBackend) -> Option<ClipboardContent> {
+    let formats = backend.read_formats().ok()?;
+    text_content(&formats).or_else(|| image_content(&formats))
+}
+
+/// Clear the clipboard, or put `previous` back, if it still holds the content with
+/// `hash`. Returns false if something else has been copied since.
+pub fn clear_clipboard_content(
+    backend: &mut dyn ClipboardBackend,
+    hash: &str,
+    previous: Option<&ClipboardContent>,
+) -> Result<bool, String> {
+    clear_content(&TRACKER, backend, hash, previous)
 }
 
 pub fn start_monitoring(app_handle: AppHandle) {
-    // Create shutdown channel
-    let (shutdown_tx, mut shutdown_rx) = watch::channel(false);
-    let _ = SHUTDOWN_TX.set(shutdown_tx);
+    // Create control channel
+    let (control_tx, mut control_rx) = watch::channel(MonitorState::Running);
+    let _ = CONTROL_TX.set(control_tx);
 
     tauri::async_runtime::spawn(async move {
-        let mut clipboard = match Clipboard::new() {
-            Ok(cb) => cb,
+        let mut backend = match system_backend() {
+            Ok(backend) => backend,
             Err(e) => {
                 eprintln!("Failed to create clipboard: {}", e);
                 return;
             }
         };
 
+        // Selection change notifications; polling is only used when none are available
+        let (change_tx, mut change_rx) = mpsc::unbounded_channel::<()>();
+        let mut event_driven = backend.subscribe(change_tx);
+
         let mut interval = tokio::time::interval(Duration::from_secs(1));
+        let mut resume_at: Option<tokio::time::Instant> = None;
 
         loop {
+            let paused = matches!(*control_rx.borrow(), MonitorState::Paused { .. });
+
             tokio::select! {
-                _ = interval.tick() => {
-                    if let Some(content) = check_clipboard_change(&mut clipboard) {
-                        // Add to volatile storage
-                        if let Some(state) = APP_STATE.get() {
-                            state.volatile_storage.add(content.clone()).await;
+                change = change_rx.recv(), if event_driven => {
+                    match change {
+                        Some(()) => handle_clipboard_change(&app_handle, backend.as_mut(), paused).await,
+                        None => {
+                            eprintln!("Clipboard change listener stopped, falling back to polling");
+                            event_driven = false;
                         }
-
-                        // Emit event to frontend
-                        let _ = app_handle.emit("clipboard-changed", &content);
                     }
                 }
-                _ = shutdown_rx.changed() => {
-                    if *shutdown_rx.borrow() {
-                        println!("Clipboard monitor shutting down gracefully");
+                _ = interval.tick(), if !event_driven => {
+                    handle_clipboard_change(&app_handle, backend.as_mut(), paused).await;
+                }
+                _ = tokio::time::sleep_until(resume_at.unwrap_or_else(tokio::time::Instant::now)), if resume_at.is_some() => {
+                    resume_at = None;
+                    resume_monitoring();
+                }
+                changed = control_rx.changed() => {
+                    if changed.is_err() {
                         break;
                     }
+                    let state = *control_rx.borrow_and_update();
+                    resume_at = match state {
+                        MonitorState::Shutdown => {
+                            println!("Clipboard monitor shutting down gracefully");
+                            break;
+                        }
+                        MonitorState::Running => None,
+                        MonitorState::Paused { until } => until.map(|until| {
+                            let remaining = (until - Utc::now()).to_std().unwrap_or_default();
+                            tokio::time::Instant::now() + remaining
+                        }),
+                    };
+
+                    tray::update_monitor_state(&app_handle, state);
+                    let _ = app_handle.emit("monitor-state-changed", state);
                 }
             }
         }
     });
 }
 
-/// Stop the clipboard monitoring gracefully
-pub fn stop_monitoring() {
-    if let Some(tx) = SHUTDOWN_TX.get() {
-        let _ = tx.send(true);
+async fn handle_clipboard_change(
+    app_handle: &AppHandle,
+    backend: &mut dyn ClipboardBackend,
+    paused: bool,
+) {
+    let content = check_clipboard_change(&TRACKER, backend);
+    // Still tracked while paused, so content copied in the meantime is not picked up on resume
+    if paused {
+        return;
     }
-}
-
-fn check_clipboard_change(clipboard: &mut Clipboard) -> Option<ClipboardContent> {
-    // Try to get text first
-    if let Ok(text) = clipboard.get_text() {
-        if !text.is_empty() {
-            let preview = text.chars().take(100).collect();
-            let data = ClipboardData::Text {
-                text: text.clone(),
-                preview,
-            };
-            let category = Categorizer::categorize(&data);
-            let content = ClipboardContent {
-                id: uuid::Uuid::new_v4(),
-                category,
-                data,
-                copied_at: chrono::Utc::now(),
-                source: None,
-            };
 
-            let hash = content.content_hash();
-            let last_hash = LAST_CONTENT_HASH.load(Ordering::Relaxed);
-            let self_copied_hash = SELF_COPIED_HASH.load(Ordering::Relaxed);
+    if let Some(mut content) = content {
+        // The foreground window at notification time is the one that copied. Looking
+        // it up queries the window system synchronously, so it runs on a blocking thread.
+        let app = tokio::task::spawn_blocking(window_focus::foreground_app)
+            .await
+            .ok()
+            .flatten();
+        if let Some(app) = app {
+            content.source = Some(app.name);
+            content.source_title = app.title;
+        }
 
-            if hash != last_hash {
-                LAST_CONTENT_HASH.store(hash, Ordering::Relaxed);
-                // Skip if this was copied by Clitter itself
-                if hash == self_copied_hash {
-                    SELF_COPIED_HASH.store(0, Ordering::Relaxed); // Reset
-                    return None;
+        if let Some(state) = APP_STATE.get() {
+            if let Some(source) = content.source.as_deref() {
+                if state.exclusions.is_excluded(source).await {
+                    return;
                 }
-                return Some(content);
+            }
+
+            // Re-copied content comes back as the existing entry, moved to the front
+            history::merge_persisted(&mut content).await;
+            let Some(added) = state.volatile_storage.add(content).await else {
+                return; // Over the single-item size limit
+            };
+            content = added.content;
+            history::persist(&content).await;
+            if !added.evicted.is_empty() {
+                let _ = app_handle.emit("clipboard-evicted", &added.evicted);
             }
         }
+
+        // Emit event to frontend
+        let _ = app_handle.emit("clipboard-changed", &content.masked());
+        content.wipe();
     }
+}
 
-    // Try to get image
-    if let Ok(img) = clipboard.get_image() {
-        // Convert RGBA pixel data to PNG format
-        let png_bytes = match encode_rgba_to_png(&img.bytes, img.width as u32, img.height as u32) {
-            Ok(bytes) => bytes,
-            Err(e) => {
-                eprintln!("Failed to encode image to PNG: {}", e);
-                return None;
-            }
-        };
+/// Current monitor state (Running before the monitor has started)
+pub fn monitor_state() -> MonitorState {
+    CONTROL_TX
+        .get()
+        .map(|tx| *tx.borrow())
+        .unwrap_or(MonitorState::Running)
+}
 
-        let base64 = STANDARD.encode(&png_bytes);
-        let hash = {
-            use std::collections::hash_map::DefaultHasher;
-            use std::hash::{Hash, Hasher};
-            let mut hasher = DefaultHasher::new();
-            base64.hash(&mut hasher);
-            hasher.finish()
-        };
+/// Stop capturing, optionally resuming automatically after `duration`
+pub fn pause_monitoring(duration: Option<Duration>) -> MonitorState {
+    // Durations beyond what a timestamp holds pause until resumed
+    let until = duration
+        .and_then(|d| chrono::Duration::from_std(d).ok())
+        .and_then(|d| Utc::now().checked_add_signed(d));
+    send_state(MonitorState::Paused { until })
+}
 
-        let last_hash = LAST_CONTENT_HASH.load(Ordering::Relaxed);
-        let self_copied_hash = SELF_COPIED_HASH.load(Ordering::Relaxed);
+pub fn resume_monitoring() -> MonitorState {
+    send_state(MonitorState::Running)
+}
+
+/// Pause indefinitely when running, resume when paused
+pub fn toggle_pause() -> MonitorState {
+    match monitor_state() {
+        MonitorState::Paused { .. } => resume_monitoring(),
+        _ => pause_monitoring(None),
+    }
+}
 
-        if hash != last_hash {
-            LAST_CONTENT_HASH.store(hash, Ordering::Relaxed);
-            // Skip if this was copied by Clitter itself
-            if hash == self_copied_hash {
-                SELF_COPIED_HASH.store(0, Ordering::Relaxed); // Reset
-                return None;
+fn send_state(state: MonitorState) -> MonitorState {
+    if let Some(tx) = CONTROL_TX.get() {
+        // A stopped monitor stays stopped
+        tx.send_if_modified(|current| {
+            if *current == MonitorState::Shutdown || *current == state {
+                return false;
             }
+            *current = state;
+            true
+        });
+    }
+    monitor_state()
+}
+
+/// Stop the clipboard monitoring gracefully
+pub fn stop_monitoring() {
+    if let Some(tx) = CONTROL_TX.get() {
+        let _ = tx.send(MonitorState::Shutdown);
+    }
+}
+
+fn check_clipboard_change(
+    tracker: &ChangeTracker,
+    backend: &mut dyn ClipboardBackend,
+) -> Option<ClipboardContent> {
+    // Checked before anything is read, so flagged secrets are never decoded
+    if backend.has_exclusion_hint() {
+        return None;
+    }
+    read_new_content(tracker, backend)
+}
+
+fn read_new_content(
+    tracker: &ChangeTracker,
+    backend: &mut dyn ClipboardBackend,
+) -> Option<ClipboardContent> {
+    let formats = match backend.read_formats() {
+        Ok(formats) => formats,
+        Err(e) => {
+            eprintln!("Failed to read clipboard: {}", e);
+            return None;
+        }
+    };
 
-            let content = ClipboardContent::new_image(
-                base64,
-                img.width as u32,
-                img.height as u32,
-                "png".to_string(),
-            );
+    if let Some(content) = text_content(&formats) {
+        if tracker.observe(content.content_hash()) {
             return Some(content);
         }
     }
 
+    let content = image_content(&formats)?;
+    if tracker.observe(content.content_hash()) {
+        return Some(content);
+    }
+
     None
 }
 
+/// File list, or text with its rich alternatives
+fn text_content(formats: &[ClipboardFormat]) -> Option<ClipboardContent> {
+    // Files first: file managers also offer the paths as plain text
+    let files = formats.iter().find_map(|f| match f {
+        ClipboardFormat::Files(uris) if !uris.is_empty() => Some(uris),
+        _ => None,
+    });
+    let text = formats.iter().find_map(|f| match f {
+        ClipboardFormat::Text(text) if !text.is_empty() => Some(text),
+        _ => None,
+    });
+
+    let data = if let Some(uris) = files {
+        Some(ClipboardData::files(uris.clone()))
+    } else if let Some(text) = text {
+        // Rich formats are only kept alongside their plain-text alternative
+        let text = text.clone();
+        let preview: String = text.chars().take(100).collect();
+        let rich = formats.iter().find_map(|f| match f {
+            ClipboardFormat::Html(html) => Some(ClipboardData::Html {
+                html: html.clone(),
+                text: text.clone(),
+                preview: preview.clone(),
+            }),
+            ClipboardFormat::Rtf(rtf) => Some(ClipboardData::Rtf {
+                rtf: rtf.clone(),
+                text: text.clone(),
+                preview: preview.clone(),
+            }),
+            _ => None,
+        });
+        Some(rich.unwrap_or(ClipboardData::Text { text, preview }))
+    } else {
+        None
+    };
+
+    let data = data?;
+    let category = Categorizer::categorize(&data);
+    let now = chrono::Utc::now();
+    Some(ClipboardContent {
+        id: uuid::Uuid::new_v4(),
+        category,
+        data,
+        copied_at: now,
+        source: None,
+        source_title: None,
+        copy_count: 1,
+        last_copied_at: now,
+    })
+}
+
+fn image_content(formats: &[ClipboardFormat]) -> Option<ClipboardContent> {
+    let (width, height, rgba) = formats.iter().find_map(|f| match f {
+        ClipboardFormat::Image { width, height, rgba } => Some((*width, *height, rgba)),
+        _ => None,
+    })?;
+
+    // Convert RGBA pixel data to PNG format
+    let png_bytes = match encode_rgba_to_png(rgba, width, height) {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            eprintln!("Failed to encode image to PNG: {}", e);
+            return None;
+        }
+    };
+
+    let base64 = STANDARD.encode(&png_bytes);
+    Some(ClipboardContent::new_image(base64, width, height, "png".to_string()))
+}
+
+fn write_content(
+    tracker: &ChangeTracker,
+    backend: &mut dyn ClipboardBackend,
+    content: &ClipboardContent,
+) -> Result<(), String> {
+    let formats = content_to_formats(content)?;
+
+    // Mark this content as self-copied so monitor will skip it
+    tracker.mark_as_self_copied(content.content_hash());
+
+    backend.write_formats(&formats)
+}
+
+fn clear_content(
+    tracker: &ChangeTracker,
+    backend: &mut dyn ClipboardBackend,
+    hash: &str,
+    previous: Option<&ClipboardContent>,
+) -> Result<bool, String> {
+    let formats = backend.read_formats()?;
+    let Some(current) = text_content(&formats).or_else(|| image_content(&formats)) else {
+        return Ok(false);
+    };
+    let unchanged = current.content_hash() == hash;
+    current.wipe();
+    if !unchanged {
+        return Ok(false);
+    }
+
+    match previous {
+        // Marked as self-copied, so the monitor does not capture it again
+        Some(previous) => write_content(tracker, backend, previous)?,
+        None => {
+            backend.write_formats(&[])?;
+            tracker.forget_last();
+        }
+    }
+    Ok(true)
+}
+
+fn content_to_formats(content: &ClipboardContent) -> Result<Vec<ClipboardFormat>, String> {
+    match &content.data {
+        ClipboardData::Text { text, .. } => Ok(vec![ClipboardFormat::Text(text.clone())]),
+        ClipboardData::Html { html, text, .. } => Ok(vec![
+            ClipboardFormat::Html(html.clone()),
+            ClipboardFormat::Text(text.clone()),
+        ]),
+        ClipboardData::Rtf { rtf, text, .. } => Ok(vec![
+            ClipboardFormat::Rtf(rtf.clone()),
+            ClipboardFormat::Text(text.clone()),
+        ]),
+        ClipboardData::Files { uris, .. } => Ok(vec![ClipboardFormat::Files(uris.clone())]),
+        ClipboardData::Image { base64, .. } => {
+            let png_bytes = STANDARD.decode(base64).map_err(|e| e.to_string())?;
+
+            // Decode PNG to RGBA
+            let img = image::load_from_memory(&png_bytes)
+                .map_err(|e| format!("Failed to decode PNG: {}", e))?;
+            let rgba = img.to_rgba8();
+
+            Ok(vec![ClipboardFormat::Image {
+                width: rgba.width(),
+                height: rgba.height(),
+                rgba: rgba.into_raw(),
+            }])
+        }
+    }
+}
+
 fn encode_rgba_to_png(rgba_bytes: &[u8], width: u32, height: u32) -> Result<Vec<u8>, String> {
     use image::{ImageBuffer, Rgba};
     use std::io::Cursor;
@@ -165,3 +475,239 @@ fn encode_rgba_to_png(rgba_bytes: &[u8], width: u32, height: u32) -> Result<Vec<
 
     Ok(png_bytes)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clipboard::backend::MemoryBackend;
+    use crate::types::Category;
+
+    fn external_copy(backend: &MemoryBackend, text: &str) {
+        // A clone acts as another application writing to the shared clipboard
+        backend
+            .clone()
+            .write_formats(&[ClipboardFormat::Text(text.to_string())])
+            .unwrap();
+    }
+
+    fn captured_text(content: Option<ClipboardContent>) -> Option<String> {
+        content.and_then(|c| match c.data {
+            ClipboardData::Text { text, .. } => Some(text),
+            _ => None,
+        })
+    }
+
+    #[test]
+    fn test_copy_sequence_is_captured() {
+        let tracker = ChangeTracker::new();
+        let mut backend = MemoryBackend::new();
+
+        assert!(check_clipboard_change(&tracker, &mut backend).is_none());
+
+        for text in ["first", "second", "first"] {
+            external_copy(&backend, text);
+            let captured = check_clipboard_change(&tracker, &mut backend);
+            assert_eq!(captured_text(captured).as_deref(), Some(text));
+        }
+    }
+
+    #[test]
+    fn test_unchanged_clipboard_is_captured_once() {
+        let tracker = ChangeTracker::new();
+        let mut backend = MemoryBackend::new();
+
+        external_copy(&backend, "hello");
+        assert!(check_clipboard_change(&tracker, &mut backend).is_some());
+        assert!(check_clipboard_change(&tracker, &mut backend).is_none());
+    }
+
+    #[test]
+    fn test_self_copy_is_skipped() {
+        let tracker = ChangeTracker::new();
+        let mut backend = MemoryBackend::new();
+
+        external_copy(&backend, "from another app");
+        assert!(check_clipboard_change(&tracker, &mut backend).is_some());
+
+        let content = ClipboardContent::new_text("from history".to_string(), Category::Text);
+        write_content(&tracker, &mut backend, &content).unwrap();
+        assert!(check_clipboard_change(&tracker, &mut backend).is_none());
+
+        external_copy(&backend, "next copy");
+        let captured = check_clipboard_change(&tracker, &mut backend);
+        assert_eq!(captured_text(captured).as_deref(), Some("next copy"));
+    }
+
+    #[test]
+    fn test_secret_is_cleared_only_while_unchanged() {
+        let tracker = ChangeTracker::new();
+        let mut backend = MemoryBackend::new();
+        let secret = ClipboardContent::new_text("hunter2 password".to_string(), Category::Secure);
+        let hash = secret.content_hash();
+
+        external_copy(&backend, "notes");
+        let previous = check_clipboard_change(&tracker, &mut backend).unwrap();
+
+        // Restoring the previous content is not captured as a new copy
+        write_content(&tracker, &mut backend, &secret).unwrap();
+        assert!(check_clipboard_change(&tracker, &mut backend).is_none());
+        assert!(clear_content(&tracker, &mut backend, &hash, Some(&previous)).unwrap());
+        assert!(check_clipboard_change(&tracker, &mut backend).is_none());
+        assert_eq!(captured_text(Some(previous)).as_deref(), Some("notes"));
+        assert_eq!(
+            backend.read_formats().unwrap(),
+            vec![ClipboardFormat::Text("notes".to_string())]
+        );
+
+        // Without previous content the clipboard is emptied
+        write_content(&tracker, &mut backend, &secret).unwrap();
+        assert!(clear_content(&tracker, &mut backend, &hash, None).unwrap());
+        assert!(backend.read_formats().unwrap().is_empty());
+
+        // Something copied in the meantime is left alone
+        write_content(&tracker, &mut backend, &secret).unwrap();
+        external_copy(&backend, "meeting at 3");
+        assert!(!clear_content(&tracker, &mut backend, &hash, None).unwrap());
+        assert_eq!(
+            captured_text(check_clipboard_change(&tracker, &mut backend)).as_deref(),
+            Some("meeting at 3")
+        );
+    }
+
+    #[test]
+    fn test_image_round_trip() {
+        let tracker = ChangeTracker::new();
+        let mut backend = MemoryBackend::new();
+
+        backend
+            .clone()
+            .write_formats(&[ClipboardFormat::Image {
+                width: 2,
+                height: 1,
+                rgba: vec![255, 0, 0, 255, 0, 0, 255, 255],
+            }])
+            .unwrap();
+
+        let content = check_clipboard_change(&tracker, &mut backend).expect("image captured");
+        assert_eq!(content.category, Category::Image);
+        assert!(matches!(content.data, ClipboardData::Image { width: 2, height: 1, .. }));
+
+        // Pasting it back restores the same pixels and is not captured again
+        write_content(&tracker, &mut backend, &content).unwrap();
+        assert!(check_clipboard_change(&tracker, &mut backend).is_none());
+        assert_eq!(
+            backend.read_formats().unwrap(),
+            vec![ClipboardFormat::Image {
+                width: 2,
+                height: 1,
+                rgba: vec![255, 0, 0, 255, 0, 0, 255, 255],
+            }]
+        );
+    }
+
+    #[test]
+    fn test_large_image_gets_previews_and_pastes_original() {
+        let tracker = ChangeTracker::new();
+        let mut backend = MemoryBackend::new();
+        let (width, height) = (600, 300);
+        let rgba = vec![200u8; (width * height * 4) as usize];
+
+        backend
+            .clone()
+            .write_formats(&[ClipboardFormat::Image { width, height, rgba: rgba.clone() }])
+            .unwrap();
+
+        let content = check_clipboard_change(&tracker, &mut backend).expect("image captured");
+        match &content.data {
+            ClipboardData::Image { thumbnail, preview, .. } => {
+                assert!(thumbnail.is_some());
+                assert!(preview.is_some());
+            }
+            _ => panic!("expected image"),
+        }
+
+        write_content(&tracker, &mut backend, &content).unwrap();
+        assert_eq!(
+            backend.read_formats().unwrap(),
+            vec![ClipboardFormat::Image { width, height, rgba }]
+        );
+    }
+
+    #[test]
+    fn test_rich_formats_are_captured_and_restored() {
+        let tracker = ChangeTracker::new();
+        let mut backend = MemoryBackend::new();
+
+        let html_copy = vec![
+            ClipboardFormat::Text("Hello".to_string()),
+            ClipboardFormat::Html("<b>Hello</b>".to_string()),
+        ];
+        backend.clone().write_formats(&html_copy).unwrap();
+
+        let content = check_clipboard_change(&tracker, &mut backend).expect("html captured");
+        assert!(matches!(&content.data, ClipboardData::Html { html, text, .. }
+            if html == "<b>Hello</b>" && text == "Hello"));
+
+        // Pasting it back writes both the HTML and its plain-text alternative
+        external_copy(&backend, "something else");
+        check_clipboard_change(&tracker, &mut backend);
+        write_content(&tracker, &mut backend, &content).unwrap();
+        assert!(check_clipboard_change(&tracker, &mut backend).is_none());
+
+        let mut formats = backend.read_formats().unwrap();
+        formats.sort_by_key(|f| matches!(f, ClipboardFormat::Text(_)));
+        assert_eq!(formats, html_copy.into_iter().rev().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_file_list_takes_priority_over_text() {
+        let tracker = ChangeTracker::new();
+        let mut backend = MemoryBackend::new();
+
+        backend
+            .clone()
+            .write_formats(&[
+                ClipboardFormat::Text("/home/user/report.pdf".to_string()),
+                ClipboardFormat::Files(vec!["file:///home/user/report.pdf".to_string()]),
+            ])
+            .unwrap();
+
+        let content = check_clipboard_change(&tracker, &mut backend).expect("files captured");
+        match content.data {
+            ClipboardData::Files { uris, preview } => {
+                assert_eq!(uris, vec!["file:///home/user/report.pdf".to_string()]);
+                assert_eq!(preview, "report.pdf");
+            }
+            other => panic!("unexpected data: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_password_manager_hint_is_skipped() {
+        let tracker = ChangeTracker::new();
+        let mut backend = MemoryBackend::new();
+
+        backend
+            .clone()
+            .write_hinted(&[ClipboardFormat::Text("hunter2".to_string())])
+            .unwrap();
+        assert!(check_clipboard_change(&tracker, &mut backend).is_none());
+
+        // Ordinary copies afterwards are captured again
+        external_copy(&backend, "hello");
+        assert_eq!(
+            captured_text(check_clipboard_change(&tracker, &mut backend)).as_deref(),
+            Some("hello")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_subscribers_are_notified() {
+        let mut backend = MemoryBackend::new();
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        assert!(backend.subscribe(tx));
+
+        external_copy(&backend, "notify me");
+        assert_eq!(rx.recv().await, Some(()));
+    }
+}
@@ -0,0 +1,59 @@
+//! Downscaled copies of captured images for list views
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use std::io::Cursor;
+
+/// Longest side of a list thumbnail in pixels
+pub const THUMBNAIL_SIZE: u32 = 96;
+
+/// Longest side of the medium preview used by larger views
+pub const PREVIEW_SIZE: u32 = 480;
+
+/// Thumbnail and medium preview for a captured image.
+/// The preview is skipped when the original already fits within `PREVIEW_SIZE`.
+pub fn make_previews(base64: &str, width: u32, height: u32) -> (Option<String>, Option<String>) {
+    let thumbnail = make_thumbnail(base64, THUMBNAIL_SIZE);
+    let preview = if width.max(height) > PREVIEW_SIZE {
+        make_thumbnail(base64, PREVIEW_SIZE)
+    } else {
+        None
+    };
+    (thumbnail, preview)
+}
+
+/// PNG thumbnail (base64) of a base64-encoded image, fitting within `max_size`
+pub fn make_thumbnail(base64: &str, max_size: u32) -> Option<String> {
+    let bytes = STANDARD.decode(base64).ok()?;
+    let img = image::load_from_memory(&bytes).ok()?;
+
+    let thumbnail = img.thumbnail(max_size, max_size);
+    let mut png_bytes = Vec::new();
+    thumbnail
+        .write_to(&mut Cursor::new(&mut png_bytes), image::ImageFormat::Png)
+        .ok()?;
+
+    Some(STANDARD.encode(&png_bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{ImageBuffer, Rgba};
+
+    #[test]
+    fn test_thumbnail_keeps_aspect_ratio() {
+        let img: ImageBuffer<Rgba<u8>, Vec<u8>> = ImageBuffer::from_pixel(400, 200, Rgba([1, 2, 3, 255]));
+        let mut png_bytes = Vec::new();
+        img.write_to(&mut Cursor::new(&mut png_bytes), image::ImageFormat::Png)
+            .unwrap();
+
+        let thumbnail = make_thumbnail(&STANDARD.encode(&png_bytes), THUMBNAIL_SIZE).unwrap();
+        let decoded = image::load_from_memory(&STANDARD.decode(thumbnail).unwrap()).unwrap();
+        assert_eq!((decoded.width(), decoded.height()), (96, 48));
+
+        // Small originals need no medium preview
+        let (thumbnail, preview) = make_previews(&STANDARD.encode(&png_bytes), 400, 200);
+        assert!(thumbnail.is_some());
+        assert!(preview.is_none());
+    }
+}
@@ -9,6 +9,9 @@ use windows::{
     Win32::Graphics::Gdi::*,
 };
 
+#[cfg(target_os = "windows")]
+use crate::clipboard::backend::{ArboardBackend, ClipboardBackend, ClipboardFormat};
+
 #[cfg(target_os = "windows")]
 use std::ffi::OsStr;
 #[cfg(target_os = "windows")]
@@ -17,6 +20,8 @@ use std::os::windows::ffi::OsStrExt;
 #[cfg(target_os = "windows")]
 const CF_DIB: u32 = 8;    // Device Independent Bitmap
 #[cfg(target_os = "windows")]
+const CF_UNICODETEXT: u32 = 13;
+#[cfg(target_os = "windows")]
 const CF_HDROP: u32 = 15; // File drop format
 
 /// DROPFILES structure for CF_HDROP
@@ -195,6 +200,188 @@ unsafe fn set_hdrop_data(file_path: &str) -> Result<(), String> {
     Ok(())
 }
 
+#[cfg(target_os = "windows")]
+fn rtf_format() -> u32 {
+    unsafe { RegisterClipboardFormatW(windows::core::w!("Rich Text Format")) }
+}
+
+/// Read the "Rich Text Format" clipboard format, if present
+#[cfg(target_os = "windows")]
+pub fn get_rtf_from_clipboard() -> Option<String> {
+    let format = rtf_format();
+    if format == 0 {
+        return None;
+    }
+
+    unsafe {
+        IsClipboardFormatAvailable(format).ok()?;
+        OpenClipboard(Some(HWND::default())).ok()?;
+
+        let rtf = GetClipboardData(format).ok().and_then(|handle| {
+            let hglobal = HGLOBAL(handle.0);
+            let ptr = GlobalLock(hglobal) as *const u8;
+            if ptr.is_null() {
+                return None;
+            }
+
+            // RTF is 7-bit text terminated by NUL inside the allocation
+            let bytes = std::slice::from_raw_parts(ptr, GlobalSize(hglobal));
+            let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+            let rtf = String::from_utf8_lossy(&bytes[..end]).into_owned();
+
+            let _ = GlobalUnlock(hglobal);
+            Some(rtf)
+        });
+
+        let _ = CloseClipboard();
+        rtf
+    }
+}
+
+/// Set RTF together with its plain-text alternative
+#[cfg(target_os = "windows")]
+pub fn set_rtf_to_clipboard(rtf: &str, text: &str) -> Result<(), String> {
+    let wide_text: Vec<u16> = text.encode_utf16().chain(std::iter::once(0)).collect();
+    let mut rtf_bytes = rtf.as_bytes().to_vec();
+    rtf_bytes.push(0);
+
+    unsafe {
+        OpenClipboard(Some(HWND::default())).map_err(|e| format!("Failed to open clipboard: {}", e))?;
+
+        if let Err(e) = EmptyClipboard() {
+            let _ = CloseClipboard();
+            return Err(format!("Failed to empty clipboard: {}", e));
+        }
+
+        // Plain text first so every application has a fallback
+        let text_bytes =
+            std::slice::from_raw_parts(wide_text.as_ptr() as *const u8, wide_text.len() * 2);
+        if let Err(e) = set_global_data(CF_UNICODETEXT, text_bytes) {
+            eprintln!("[windows_clipboard] Failed to set text: {}", e);
+        }
+
+        if let Err(e) = set_global_data(rtf_format(), &rtf_bytes) {
+            eprintln!("[windows_clipboard] Failed to set RTF: {}", e);
+        }
+
+        let _ = CloseClipboard();
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "windows")]
+unsafe fn set_global_data(format: u32, bytes: &[u8]) -> Result<(), String> {
+    let hglobal = GlobalAlloc(GMEM_MOVEABLE, bytes.len())
+        .map_err(|e| format!("Failed to allocate memory: {}", e))?;
+
+    let ptr = GlobalLock(hglobal);
+    if ptr.is_null() {
+        let _ = GlobalFree(Some(hglobal));
+        return Err("Failed to lock memory".to_string());
+    }
+
+    std::ptr::copy_nonoverlapping(bytes.as_ptr(), ptr as *mut u8, bytes.len());
+    let _ = GlobalUnlock(hglobal);
+
+    SetClipboardData(format, Some(HANDLE(hglobal.0 as *mut std::ffi::c_void)))
+        .map_err(|_| format!("Failed to set clipboard format {}", format))?;
+
+    Ok(())
+}
+
+/// Whether the clipboard owner excluded the content from clipboard history.
+/// Checks the formats documented for clipboard monitors and the history opt-out.
+#[cfg(target_os = "windows")]
+pub fn has_exclusion_hint() -> bool {
+    unsafe {
+        let excluded = [
+            RegisterClipboardFormatW(windows::core::w!("ExcludeClipboardContentFromMonitorProcessing")),
+            RegisterClipboardFormatW(windows::core::w!("Clipboard Viewer Ignore")),
+        ];
+        if excluded
+            .iter()
+            .any(|&format| format != 0 && IsClipboardFormatAvailable(format).is_ok())
+        {
+            return true;
+        }
+
+        // A DWORD of 0 opts the content out of clipboard history
+        let history = RegisterClipboardFormatW(windows::core::w!("CanIncludeInClipboardHistory"));
+        if history == 0 || IsClipboardFormatAvailable(history).is_err() {
+            return false;
+        }
+        if OpenClipboard(Some(HWND::default())).is_err() {
+            return false;
+        }
+
+        let opted_out = GetClipboardData(history)
+            .ok()
+            .map(|handle| {
+                let hglobal = HGLOBAL(handle.0);
+                if GlobalSize(hglobal) < std::mem::size_of::<u32>() {
+                    return false;
+                }
+                let ptr = GlobalLock(hglobal) as *const u32;
+                if ptr.is_null() {
+                    return false;
+                }
+                let value = std::ptr::read_unaligned(ptr);
+                let _ = GlobalUnlock(hglobal);
+                value == 0
+            })
+            .unwrap_or(false);
+
+        let _ = CloseClipboard();
+        opted_out
+    }
+}
+
+/// Clipboard backend that reads through arboard and writes images with the native API
+#[cfg(target_os = "windows")]
+pub struct WindowsBackend {
+    inner: ArboardBackend,
+}
+
+#[cfg(target_os = "windows")]
+impl WindowsBackend {
+    pub fn new() -> Result<Self, String> {
+        Ok(Self {
+            inner: ArboardBackend::new()?,
+        })
+    }
+}
+
+#[cfg(target_os = "windows")]
+impl ClipboardBackend for WindowsBackend {
+    fn read_formats(&mut self) -> Result<Vec<ClipboardFormat>, String> {
+        let mut formats = self.inner.read_formats()?;
+        if let Some(rtf) = get_rtf_from_clipboard() {
+            formats.push(ClipboardFormat::Rtf(rtf));
+        }
+        Ok(formats)
+    }
+
+    fn write_formats(&mut self, formats: &[ClipboardFormat]) -> Result<(), String> {
+        let text = formats.iter().find_map(|f| match f {
+            ClipboardFormat::Text(text) => Some(text.as_str()),
+            _ => None,
+        });
+
+        match formats.first() {
+            Some(ClipboardFormat::Rtf(rtf)) => set_rtf_to_clipboard(rtf, text.unwrap_or_default()),
+            // Use native DIB + file drop formats for better compatibility
+            Some(ClipboardFormat::Image { width, height, rgba }) => {
+                set_image_to_clipboard(rgba, *width, *height)
+            }
+            _ => self.inner.write_formats(formats),
+        }
+    }
+
+    fn has_exclusion_hint(&mut self) -> bool {
+        has_exclusion_hint()
+    }
+}
+
 #[cfg(not(target_os = "windows"))]
 pub fn set_image_to_clipboard(_rgba_bytes: &[u8], _width: u32, _height: u32) -> Result<(), String> {
     Err("Windows-specific clipboard not available on this platform".to_string())
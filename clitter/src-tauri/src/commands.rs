@@ -1,7 +1,23 @@
-use arboard::Clipboard;
+use std::future::Future;
+use tauri::Emitter;
 use uuid::Uuid;
-
-use crate::clipboard::monitor::mark_as_self_copied;
+use zeroize::Zeroizing;
+
+use crate::clipboard::backend::system_backend;
+use crate::crypto::KdfParams;
+use crate::clipboard::auto_clear::{self, AutoClearPolicy};
+use crate::clipboard::monitor::{self, MonitorState};
+use crate::lock::{self, AutoLockPolicy};
+use crate::search::{SearchQuery, SearchResult};
+use crate::storage::history::{self, HistoryPolicy};
+use crate::storage::journal::UndoState;
+use crate::storage::snapshot::{SnapshotData, SnapshotDiff, SnapshotInfo, SnapshotPolicy, SNAPSHOT_POLICY_SETTING};
+use crate::storage::persistent::{
+    DeleteMode, GroupChanges, ItemChanges, LockState, PersistentStorage, RekeyProgress, TrashEntry, TRASH_RETENTION_SETTING,
+};
+use crate::storage::volatile::{
+    DedupeMode, HistoryLimits, HistoryPage, SourceGroup, DEDUPE_MODE_SETTING, HISTORY_LIMITS_SETTING,
+};
 use crate::types::{Category, ClipboardContent, ClipboardData, Group, Position, Size, WhiteboardItem, WhiteboardState};
 use crate::window_focus;
 use crate::APP_STATE;
@@ -26,141 +42,219 @@ pub struct ImportedGroup {
 }
 
 #[tauri::command]
-pub async fn get_clipboard_history(category: Option<String>) -> Result<Vec<ClipboardContent>, String> {
+pub async fn get_clipboard_history(
+    category: Option<String>,
+    source: Option<String>,
+) -> Result<Vec<ClipboardContent>, String> {
     let state = APP_STATE.get().ok_or("App state not initialized")?;
 
-    match category {
-        Some(cat) => {
-            let category: Category = cat.parse().map_err(|e: String| e)?;
-            Ok(state.volatile_storage.get_by_category(category).await)
-        }
-        None => Ok(state.volatile_storage.get_all().await),
+    let mut history = match source {
+        Some(source) => state.volatile_storage.get_by_source(&source).await,
+        None => state.volatile_storage.get_all().await,
+    };
+
+    if let Some(cat) = category {
+        let category: Category = cat.parse().map_err(|e: String| e)?;
+        history.retain(|c| c.category == category);
+    }
+    if lock::is_locked().await {
+        history.retain(|c| c.category != Category::Secure);
     }
+
+    Ok(history)
 }
 
+/// Page through history summaries; full payloads come from `get_clipboard_content`
 #[tauri::command]
-pub async fn get_recent_items(count: usize) -> Result<Vec<ClipboardContent>, String> {
+pub async fn get_clipboard_history_page(
+    cursor: Option<String>,
+    limit: Option<usize>,
+    category: Option<String>,
+    source: Option<String>,
+) -> Result<HistoryPage, String> {
     let state = APP_STATE.get().ok_or("App state not initialized")?;
-    Ok(state.volatile_storage.get_recent(count).await)
+
+    let cursor = cursor
+        .map(|c| Uuid::parse_str(&c).map_err(|e| e.to_string()))
+        .transpose()?;
+    let category: Option<Category> = category
+        .map(|c| c.parse().map_err(|e: String| e))
+        .transpose()?;
+    let hide_secure = lock::is_locked().await;
+
+    state
+        .volatile_storage
+        .get_page(cursor, limit.unwrap_or(50), |c| {
+            !(hide_secure && c.category == Category::Secure)
+                && category.as_ref().is_none_or(|cat| c.category == *cat)
+                && source.as_ref().is_none_or(|s| c.source.as_ref() == Some(s))
+        })
+        .await
+        .ok_or_else(|| "Cursor is no longer in the history".to_string())
 }
 
+/// Full content by id, from the in-memory history or the database
 #[tauri::command]
-pub async fn copy_to_clipboard(content: ClipboardContent) -> Result<(), String> {
-    // Mark this content as self-copied so monitor will skip it
-    mark_as_self_copied(content.content_hash());
+pub async fn get_clipboard_content(id: String) -> Result<ClipboardContent, String> {
+    let state = APP_STATE.get().ok_or("App state not initialized")?;
+    let id = Uuid::parse_str(&id).map_err(|e| e.to_string())?;
 
-    match &content.data {
-        ClipboardData::Text { text, .. } => {
-            let mut clipboard = Clipboard::new().map_err(|e| e.to_string())?;
-            clipboard.set_text(text).map_err(|e| e.to_string())?;
+    if let Some(content) = state.volatile_storage.get_by_id(id).await {
+        if content.category != Category::Secure {
+            return Ok(content);
         }
-        ClipboardData::Image { base64, width: stored_w, height: stored_h, .. } => {
-            use base64::{engine::general_purpose::STANDARD, Engine};
-            let png_bytes = STANDARD.decode(base64).map_err(|e| e.to_string())?;
-            eprintln!("[copy_to_clipboard] PNG bytes: {}, stored size: {}x{}", png_bytes.len(), stored_w, stored_h);
-
-            // Decode PNG to RGBA
-            let img = image::load_from_memory(&png_bytes).map_err(|e| format!("Failed to decode PNG: {}", e))?;
-            let rgba = img.to_rgba8();
-
-            let width = rgba.width();
-            let height = rgba.height();
-            let raw_bytes = rgba.into_raw();
-            eprintln!("[copy_to_clipboard] RGBA size: {}x{}, bytes: {}", width, height, raw_bytes.len());
-
-            // Use Windows native clipboard API for better compatibility
-            #[cfg(target_os = "windows")]
-            {
-                crate::clipboard::windows_clipboard::set_image_to_clipboard(&raw_bytes, width, height)?;
-                eprintln!("[copy_to_clipboard] Image set successfully via Windows API");
-            }
+        // Secure entries are kept encrypted in memory and only decrypted here
+        lock::ensure_unlocked().await?;
+        if let Some(content) = state.volatile_storage.reveal(id).await {
+            return Ok(content);
+        }
+    }
 
-            #[cfg(not(target_os = "windows"))]
-            {
-                let mut clipboard = Clipboard::new().map_err(|e| e.to_string())?;
-                let img_data = arboard::ImageData {
-                    width: width as usize,
-                    height: height as usize,
-                    bytes: raw_bytes.into(),
-                };
-                clipboard.set_image(img_data).map_err(|e| e.to_string())?;
-                eprintln!("[copy_to_clipboard] Image set successfully via arboard");
-            }
+    let storage = state.persistent_storage.read().await;
+    if let Some(storage) = storage.as_ref() {
+        if let Some(content) = storage
+            .load_clipboard_content(id)
+            .await
+            .map_err(|e| e.to_string())?
+        {
+            return Ok(content);
         }
     }
 
-    Ok(())
+    Err(format!("Clipboard content not found: {}", id))
 }
 
 #[tauri::command]
-pub async fn paste_to_previous_window(content: ClipboardContent) -> Result<(), String> {
-    // Mark this content as self-copied so monitor will skip it
-    mark_as_self_copied(content.content_hash());
-
-    // First copy to clipboard
-    match &content.data {
-        ClipboardData::Text { text, .. } => {
-            let mut clipboard = Clipboard::new().map_err(|e| e.to_string())?;
-            clipboard.set_text(text).map_err(|e| e.to_string())?;
+pub async fn get_clipboard_history_by_source() -> Result<Vec<SourceGroup>, String> {
+    let state = APP_STATE.get().ok_or("App state not initialized")?;
+    let mut groups = state.volatile_storage.group_by_source().await;
+    if lock::is_locked().await {
+        for group in &mut groups {
+            group.items.retain(|c| c.category != Category::Secure);
         }
-        ClipboardData::Image { base64, width: stored_w, height: stored_h, .. } => {
-            use base64::{engine::general_purpose::STANDARD, Engine};
-            let png_bytes = STANDARD.decode(base64).map_err(|e| e.to_string())?;
-            eprintln!("[paste_to_previous_window] PNG bytes: {}, stored size: {}x{}", png_bytes.len(), stored_w, stored_h);
-
-            // Decode PNG to RGBA
-            let img = image::load_from_memory(&png_bytes).map_err(|e| format!("Failed to decode PNG: {}", e))?;
-            let rgba = img.to_rgba8();
-
-            let width = rgba.width();
-            let height = rgba.height();
-            let raw_bytes = rgba.into_raw();
-            eprintln!("[paste_to_previous_window] RGBA size: {}x{}, bytes: {}", width, height, raw_bytes.len());
-
-            // Use Windows native clipboard API for better compatibility
-            #[cfg(target_os = "windows")]
-            {
-                crate::clipboard::windows_clipboard::set_image_to_clipboard(&raw_bytes, width, height)?;
-                eprintln!("[paste_to_previous_window] Image set successfully via Windows API");
-            }
+        groups.retain(|g| !g.items.is_empty());
+    }
+    Ok(groups)
+}
 
-            #[cfg(not(target_os = "windows"))]
-            {
-                let mut clipboard = Clipboard::new().map_err(|e| e.to_string())?;
-                let img_data = arboard::ImageData {
-                    width: width as usize,
-                    height: height as usize,
-                    bytes: raw_bytes.into(),
-                };
-                clipboard.set_image(img_data).map_err(|e| e.to_string())?;
-                eprintln!("[paste_to_previous_window] Image set successfully via arboard");
-            }
-        }
+#[tauri::command]
+pub async fn search(query: SearchQuery) -> Result<Vec<SearchResult>, String> {
+    crate::search::search(&query).await
+}
+
+#[tauri::command]
+pub async fn get_recent_items(count: usize) -> Result<Vec<ClipboardContent>, String> {
+    let state = APP_STATE.get().ok_or("App state not initialized")?;
+    let mut items = state.volatile_storage.get_recent(count).await;
+    if lock::is_locked().await {
+        items.retain(|c| c.category != Category::Secure);
     }
+    Ok(items)
+}
+
+#[tauri::command]
+pub async fn copy_to_clipboard(content: ClipboardContent) -> Result<(), String> {
+    let content = with_full_payload(content).await?;
+    let mut backend = system_backend()?;
+    let result = auto_clear::set_clipboard_content(backend.as_mut(), &content).await;
+    content.wipe();
+    result
+}
+
+#[tauri::command]
+pub async fn paste_to_previous_window(content: ClipboardContent) -> Result<(), String> {
+    let content = with_full_payload(content).await?;
+
+    // First copy to clipboard
+    let mut backend = system_backend()?;
+    let result = auto_clear::set_clipboard_content(backend.as_mut(), &content).await;
+    content.wipe();
+    result?;
 
     // Then restore focus and paste
     window_focus::restore_and_paste()
 }
 
+/// Whiteboard images come without the original and Secure history entries come
+/// masked; fetch the full content before it is put on the clipboard
+async fn with_full_payload(content: ClipboardContent) -> Result<ClipboardContent, String> {
+    if content.category == Category::Secure {
+        lock::ensure_unlocked().await?;
+        let state = APP_STATE.get().ok_or("App state not initialized")?;
+        if let Some(full) = state.volatile_storage.reveal(content.id).await {
+            return Ok(full);
+        }
+    }
+    match &content.data {
+        ClipboardData::Image { base64, .. } if base64.is_empty() => {
+            get_clipboard_content(content.id.to_string()).await
+        }
+        _ => Ok(content),
+    }
+}
+
 #[tauri::command]
 pub async fn get_whiteboard() -> Result<WhiteboardState, String> {
+    lock::ensure_unlocked().await?;
     let state = APP_STATE.get().ok_or("App state not initialized")?;
     let storage = state.persistent_storage.read().await;
 
     match storage.as_ref() {
-        Some(storage) => storage.load_whiteboard().await.map_err(|e| e.to_string()),
+        Some(storage) => state.whiteboard.get(storage).await.map_err(|e| e.to_string()),
         None => Ok(WhiteboardState::default()),
     }
 }
 
+/// Load the whiteboard again after changes too broad to apply to the cache
+async fn reload_whiteboard(storage: &PersistentStorage) -> Result<WhiteboardState, String> {
+    let state = APP_STATE.get().ok_or("App state not initialized")?;
+    state.whiteboard.invalidate().await;
+    state.whiteboard.get(storage).await.map_err(|e| e.to_string())
+}
+
+/// Run a whiteboard change as one undo step. Whatever it changed is recorded,
+/// even if it fails halfway.
+async fn journaled<T>(
+    storage: &PersistentStorage,
+    label: &str,
+    change: impl Future<Output = Result<T, String>>,
+) -> Result<T, String> {
+    let step = storage.begin_step(label).await.map_err(|e| e.to_string())?;
+    let result = change.await;
+    step.commit().await.map_err(|e| e.to_string())?;
+    // The cache may not match what was written before the failure
+    if result.is_err() {
+        if let Some(state) = APP_STATE.get() {
+            state.whiteboard.invalidate().await;
+        }
+    }
+    result
+}
+
 #[tauri::command]
 pub async fn add_to_whiteboard(
     content: ClipboardContent,
     position: Position,
 ) -> Result<WhiteboardItem, String> {
+    lock::ensure_unlocked().await?;
+    let content = with_full_payload(content).await?;
     let state = APP_STATE.get().ok_or("App state not initialized")?;
     let storage = state.persistent_storage.read().await;
 
+    // The same content is only placed on the whiteboard once
+    if let Some(storage) = storage.as_ref() {
+        let existing = storage
+            .find_whiteboard_item_by_hash(&content.content_hash())
+            .await
+            .map_err(|e| e.to_string())?;
+        if let Some(id) = existing {
+            let mut whiteboard = state.whiteboard.get(storage).await.map_err(|e| e.to_string())?;
+            if let Some(item) = whiteboard.items.remove(&id) {
+                return Ok(item);
+            }
+        }
+    }
+
     let mut item = WhiteboardItem::new(content, position);
 
     // Auto-generate sequential shortcut
@@ -168,10 +262,14 @@ pub async fn add_to_whiteboard(
         let next_num = storage.get_next_shortcut_number().await.map_err(|e| e.to_string())?;
         item.shortcut = Some(format!("{}", next_num));
 
-        storage
-            .save_whiteboard_item(&item)
-            .await
-            .map_err(|e| e.to_string())?;
+        journaled(storage, "Add item", async {
+            storage
+                .save_whiteboard_item(&item)
+                .await
+                .map_err(|e| e.to_string())
+        })
+        .await?;
+        state.whiteboard.update(|w| w.insert_item(item.clone())).await;
     }
 
     Ok(item)
@@ -184,6 +282,7 @@ pub async fn add_text_to_whiteboard(
     parent_group: Option<String>,
     label: Option<String>,
 ) -> Result<WhiteboardItem, String> {
+    lock::ensure_unlocked().await?;
     let state = APP_STATE.get().ok_or("App state not initialized")?;
     let storage = state.persistent_storage.read().await;
 
@@ -203,10 +302,14 @@ pub async fn add_text_to_whiteboard(
         let next_num = storage.get_next_shortcut_number().await.map_err(|e| e.to_string())?;
         item.shortcut = Some(format!("{}", next_num));
 
-        storage
-            .save_whiteboard_item(&item)
-            .await
-            .map_err(|e| e.to_string())?;
+        journaled(storage, "Add item", async {
+            storage
+                .save_whiteboard_item(&item)
+                .await
+                .map_err(|e| e.to_string())
+        })
+        .await?;
+        state.whiteboard.update(|w| w.insert_item(item.clone())).await;
     }
 
     Ok(item)
@@ -219,63 +322,96 @@ pub async fn update_whiteboard_item(
     size: Option<Size>,
     parent_group: Option<String>,
     shortcut: Option<String>,
+    label: Option<String>,
 ) -> Result<(), String> {
+    lock::ensure_unlocked().await?;
     let state = APP_STATE.get().ok_or("App state not initialized")?;
     let storage = state.persistent_storage.read().await;
 
     let id = Uuid::parse_str(&id).map_err(|e| e.to_string())?;
+    // An empty string moves the item to the top level or removes the label
+    let parent_group = match parent_group {
+        Some(pg) if pg.is_empty() => Some(None),
+        Some(pg) => Some(Some(Uuid::parse_str(&pg).map_err(|e| e.to_string())?)),
+        None => None,
+    };
+    let label = label.map(|l| Some(l).filter(|l| !l.is_empty()));
 
     if let Some(storage) = storage.as_ref() {
-        let mut whiteboard = storage.load_whiteboard().await.map_err(|e| e.to_string())?;
-
-        if let Some(item) = whiteboard.items.get_mut(&id) {
-            if let Some(pos) = position {
-                item.position = pos;
-            }
-            if let Some(s) = size {
-                item.size = s;
-            }
-            if let Some(pg) = parent_group {
-                item.parent_group = if pg.is_empty() {
-                    None
-                } else {
-                    Some(Uuid::parse_str(&pg).map_err(|e| e.to_string())?)
-                };
-            }
-            if shortcut.is_some() {
-                item.shortcut = shortcut;
-            }
-            item.updated_at = chrono::Utc::now();
-
-            storage
-                .save_whiteboard_item(item)
-                .await
-                .map_err(|e| e.to_string())?;
-        }
+        let changes = ItemChanges {
+            position: position.clone(),
+            size: size.clone(),
+            parent_group,
+            shortcut: shortcut.clone().map(Some),
+            label: label.clone(),
+        };
+        journaled(storage, "Edit item", async {
+            storage.update_item(id, &changes).await.map_err(|e| e.to_string())
+        })
+        .await?;
+
+        state
+            .whiteboard
+            .update(|w| {
+                if let Some(mut item) = w.remove_item(id) {
+                    if let Some(pos) = position {
+                        item.position = pos;
+                    }
+                    if let Some(s) = size {
+                        item.size = s;
+                    }
+                    if let Some(pg) = parent_group {
+                        item.parent_group = pg;
+                    }
+                    if shortcut.is_some() {
+                        item.shortcut = shortcut;
+                    }
+                    if let Some(l) = label {
+                        item.label = l;
+                    }
+                    item.updated_at = chrono::Utc::now();
+                    w.insert_item(item);
+                }
+            })
+            .await;
     }
 
     Ok(())
 }
 
+/// Move a whiteboard item to the trash, returning the trash entry
 #[tauri::command]
-pub async fn remove_from_whiteboard(id: String) -> Result<(), String> {
+pub async fn remove_from_whiteboard(id: String) -> Result<Option<Uuid>, String> {
+    lock::ensure_unlocked().await?;
     let state = APP_STATE.get().ok_or("App state not initialized")?;
     let storage = state.persistent_storage.read().await;
 
     let id = Uuid::parse_str(&id).map_err(|e| e.to_string())?;
 
-    if let Some(storage) = storage.as_ref() {
-        storage
-            .delete_whiteboard_item(id)
-            .await
-            .map_err(|e| e.to_string())?;
+    match storage.as_ref() {
+        Some(storage) => {
+            let trash_id = journaled(storage, "Delete item", async {
+                storage
+                    .delete_whiteboard_item(id)
+                    .await
+                    .map_err(|e| e.to_string())
+            })
+            .await?;
+            state
+                .whiteboard
+                .update(|w| {
+                    w.remove_item(id);
+                })
+                .await;
+            Ok(trash_id)
+        }
+        None => Ok(None),
     }
-
-    Ok(())
 }
 
 #[tauri::command]
 pub async fn create_group(name: String, position: Position, parent_group: Option<String>) -> Result<Group, String> {
+    lock::ensure_unlocked().await?;
     let state = APP_STATE.get().ok_or("App state not initialized")?;
     let storage = state.persistent_storage.read().await;
 
@@ -291,7 +427,14 @@ pub async fn create_group(name: String, position: Position, parent_group: Option
         let next_num = storage.get_next_group_shortcut_number().await.map_err(|e| e.to_string())?;
         group.shortcut = Some(format!("g{}", next_num));
 
-        storage.save_group(&group).await.map_err(|e| e.to_string())?;
+        journaled(storage, "Create group", async {
+            storage.save_group(&group).await.map_err(|e| e.to_string())
+        })
+        .await?;
+        state
+            .whiteboard
+            .update(|w| w.insert_group(group.clone()))
+            .await;
     }
 
     Ok(group)
@@ -304,59 +447,222 @@ pub async fn update_group(
     collapsed: Option<bool>,
     position: Option<Position>,
 ) -> Result<(), String> {
+    lock::ensure_unlocked().await?;
     let state = APP_STATE.get().ok_or("App state not initialized")?;
     let storage = state.persistent_storage.read().await;
 
     let id = Uuid::parse_str(&id).map_err(|e| e.to_string())?;
 
     if let Some(storage) = storage.as_ref() {
-        let mut whiteboard = storage.load_whiteboard().await.map_err(|e| e.to_string())?;
+        let changes = GroupChanges {
+            name: name.clone(),
+            position: position.clone(),
+            collapsed,
+            ..Default::default()
+        };
+        journaled(storage, "Edit group", async {
+            storage.update_group(id, &changes).await.map_err(|e| e.to_string())
+        })
+        .await?;
+
+        state
+            .whiteboard
+            .update(|w| {
+                if let Some(group) = w.groups.get_mut(&id) {
+                    if let Some(n) = name {
+                        group.name = n;
+                    }
+                    if let Some(c) = collapsed {
+                        group.collapsed = c;
+                    }
+                    if let Some(pos) = position {
+                        group.position = pos;
+                    }
+                    group.updated_at = chrono::Utc::now();
+                }
+            })
+            .await;
+    }
 
-        if let Some(group) = whiteboard.groups.get_mut(&id) {
-            if let Some(n) = name {
-                group.name = n;
-            }
-            if let Some(c) = collapsed {
-                group.collapsed = c;
-            }
-            if let Some(pos) = position {
-                group.position = pos;
+    Ok(())
+}
+
+/// Move a group, with everything in it, into another group or to the top level
+/// (no `new_parent`). Returns the updated whiteboard.
+#[tauri::command]
+pub async fn move_group(id: String, new_parent: Option<String>) -> Result<WhiteboardState, String> {
+    lock::ensure_unlocked().await?;
+    let state = APP_STATE.get().ok_or("App state not initialized")?;
+    let storage = state.persistent_storage.read().await;
+
+    let id = Uuid::parse_str(&id).map_err(|e| e.to_string())?;
+    let new_parent = new_parent
+        .map(|p| Uuid::parse_str(&p))
+        .transpose()
+        .map_err(|e| e.to_string())?;
+
+    let Some(storage) = storage.as_ref() else {
+        return Ok(WhiteboardState::default());
+    };
+    let shortcut = journaled(storage, "Move group", async {
+        storage.move_group(id, new_parent).await.map_err(|e| e.to_string())
+    })
+    .await?;
+
+    state
+        .whiteboard
+        .update(|w| {
+            if let Some(group) = w.groups.get_mut(&id) {
+                group.parent_group = new_parent;
+                group.shortcut = shortcut;
+                group.updated_at = chrono::Utc::now();
             }
-            group.updated_at = chrono::Utc::now();
+            w.rebuild_children();
+        })
+        .await;
+    state.whiteboard.get(storage).await.map_err(|e| e.to_string())
+}
+
+/// Delete a group; by default it goes to the trash with everything in it.
+/// Returns the trash entry in that case.
+#[tauri::command]
+pub async fn delete_group(id: String, mode: Option<DeleteMode>) -> Result<Option<Uuid>, String> {
+    lock::ensure_unlocked().await?;
+    let state = APP_STATE.get().ok_or("App state not initialized")?;
+    let storage = state.persistent_storage.read().await;
 
-            storage.save_group(group).await.map_err(|e| e.to_string())?;
+    let id = Uuid::parse_str(&id).map_err(|e| e.to_string())?;
+
+    match storage.as_ref() {
+        Some(storage) => {
+            let trash_id = journaled(storage, "Delete group", async {
+                storage
+                    .delete_group(id, mode.unwrap_or(DeleteMode::Trash))
+                    .await
+                    .map_err(|e| e.to_string())
+            })
+            .await?;
+            state.whiteboard.invalidate().await;
+            Ok(trash_id)
         }
+        None => Ok(None),
     }
+}
 
-    Ok(())
+#[tauri::command]
+pub async fn list_trash() -> Result<Vec<TrashEntry>, String> {
+    lock::ensure_unlocked().await?;
+    let state = APP_STATE.get().ok_or("App state not initialized")?;
+    let storage = state.persistent_storage.read().await;
+
+    match storage.as_ref() {
+        Some(storage) => storage.list_trash().await.map_err(|e| e.to_string()),
+        None => Ok(Vec::new()),
+    }
 }
 
+/// Put a trash entry back and return the updated whiteboard
 #[tauri::command]
-pub async fn delete_group(id: String) -> Result<(), String> {
+pub async fn restore_from_trash(id: String) -> Result<WhiteboardState, String> {
+    lock::ensure_unlocked().await?;
     let state = APP_STATE.get().ok_or("App state not initialized")?;
     let storage = state.persistent_storage.read().await;
+    let storage = storage.as_ref().ok_or("Storage not initialized")?;
 
     let id = Uuid::parse_str(&id).map_err(|e| e.to_string())?;
+    let restored = journaled(storage, "Restore from trash", async {
+        storage.restore_from_trash(id).await.map_err(|e| e.to_string())
+    })
+    .await?;
+    if !restored {
+        return Err(format!("Trash entry not found: {}", id));
+    }
+    reload_whiteboard(storage).await
+}
 
+#[tauri::command]
+pub async fn delete_from_trash(id: String) -> Result<(), String> {
+    lock::ensure_unlocked().await?;
+    let state = APP_STATE.get().ok_or("App state not initialized")?;
+    let storage = state.persistent_storage.read().await;
+
+    let id = Uuid::parse_str(&id).map_err(|e| e.to_string())?;
     if let Some(storage) = storage.as_ref() {
-        storage.delete_group(id).await.map_err(|e| e.to_string())?;
+        journaled(storage, "Delete from trash", async {
+            storage.delete_from_trash(id).await.map_err(|e| e.to_string())
+        })
+        .await?;
     }
-
     Ok(())
 }
 
+#[tauri::command]
+pub async fn empty_trash() -> Result<u64, String> {
+    lock::ensure_unlocked().await?;
+    let state = APP_STATE.get().ok_or("App state not initialized")?;
+    let storage = state.persistent_storage.read().await;
+
+    match storage.as_ref() {
+        Some(storage) => {
+            journaled(storage, "Empty trash", async {
+                storage
+                    .purge_trash(chrono::Utc::now())
+                    .await
+                    .map_err(|e| e.to_string())
+            })
+            .await
+        }
+        None => Ok(0),
+    }
+}
+
+#[tauri::command]
+pub async fn get_trash_retention_days() -> Result<u32, String> {
+    let state = APP_STATE.get().ok_or("App state not initialized")?;
+    let storage = state.persistent_storage.read().await;
+    let storage = storage.as_ref().ok_or("Storage not initialized")?;
+    storage.trash_retention_days().await.map_err(|e| e.to_string())
+}
+
+/// Days deleted items and groups stay restorable; older ones are purged right away
+#[tauri::command]
+pub async fn set_trash_retention_days(days: u32) -> Result<u64, String> {
+    let state = APP_STATE.get().ok_or("App state not initialized")?;
+    let storage = state.persistent_storage.read().await;
+    let storage = storage.as_ref().ok_or("Storage not initialized")?;
+    storage
+        .set_setting(TRASH_RETENTION_SETTING, &days.max(1).to_string())
+        .await
+        .map_err(|e| e.to_string())?;
+    storage.purge_expired_trash().await.map_err(|e| e.to_string())
+}
+
 #[tauri::command]
 pub async fn set_group_color(id: String, color: Option<String>) -> Result<(), String> {
+    lock::ensure_unlocked().await?;
     let state = APP_STATE.get().ok_or("App state not initialized")?;
     let storage = state.persistent_storage.read().await;
 
     let id = Uuid::parse_str(&id).map_err(|e| e.to_string())?;
 
     if let Some(storage) = storage.as_ref() {
-        storage
-            .update_group_color(id, color)
-            .await
-            .map_err(|e| e.to_string())?;
+        journaled(storage, "Change group color", async {
+            let changes = GroupChanges { color: Some(color.clone()), ..Default::default() };
+            storage
+                .update_group(id, &changes)
+                .await
+                .map_err(|e| e.to_string())
+        })
+        .await?;
+        state
+            .whiteboard
+            .update(|w| {
+                if let Some(group) = w.groups.get_mut(&id) {
+                    group.color = color;
+                    group.updated_at = chrono::Utc::now();
+                }
+            })
+            .await;
     }
 
     Ok(())
@@ -364,16 +670,30 @@ pub async fn set_group_color(id: String, color: Option<String>) -> Result<(), St
 
 #[tauri::command]
 pub async fn set_item_shortcut(id: String, shortcut: Option<String>) -> Result<(), String> {
+    lock::ensure_unlocked().await?;
     let state = APP_STATE.get().ok_or("App state not initialized")?;
     let storage = state.persistent_storage.read().await;
 
     let id = Uuid::parse_str(&id).map_err(|e| e.to_string())?;
 
     if let Some(storage) = storage.as_ref() {
-        storage
-            .update_item_shortcut(id, shortcut)
-            .await
-            .map_err(|e| e.to_string())?;
+        journaled(storage, "Change shortcut", async {
+            let changes = ItemChanges { shortcut: Some(shortcut.clone()), ..Default::default() };
+            storage
+                .update_item(id, &changes)
+                .await
+                .map_err(|e| e.to_string())
+        })
+        .await?;
+        state
+            .whiteboard
+            .update(|w| {
+                if let Some(item) = w.items.get_mut(&id) {
+                    item.shortcut = shortcut;
+                    item.updated_at = chrono::Utc::now();
+                }
+            })
+            .await;
     }
 
     Ok(())
@@ -381,16 +701,30 @@ pub async fn set_item_shortcut(id: String, shortcut: Option<String>) -> Result<(
 
 #[tauri::command]
 pub async fn set_group_shortcut(id: String, shortcut: Option<String>) -> Result<(), String> {
+    lock::ensure_unlocked().await?;
     let state = APP_STATE.get().ok_or("App state not initialized")?;
     let storage = state.persistent_storage.read().await;
 
     let id = Uuid::parse_str(&id).map_err(|e| e.to_string())?;
 
     if let Some(storage) = storage.as_ref() {
-        storage
-            .update_group_shortcut(id, shortcut)
-            .await
-            .map_err(|e| e.to_string())?;
+        journaled(storage, "Change shortcut", async {
+            let changes = GroupChanges { shortcut: Some(shortcut.clone()), ..Default::default() };
+            storage
+                .update_group(id, &changes)
+                .await
+                .map_err(|e| e.to_string())
+        })
+        .await?;
+        state
+            .whiteboard
+            .update(|w| {
+                if let Some(group) = w.groups.get_mut(&id) {
+                    group.shortcut = shortcut;
+                    group.updated_at = chrono::Utc::now();
+                }
+            })
+            .await;
     }
 
     Ok(())
@@ -398,6 +732,7 @@ pub async fn set_group_shortcut(id: String, shortcut: Option<String>) -> Result<
 
 #[tauri::command]
 pub async fn get_items_with_shortcuts() -> Result<Vec<(String, String)>, String> {
+    lock::ensure_unlocked().await?;
     let state = APP_STATE.get().ok_or("App state not initialized")?;
     let storage = state.persistent_storage.read().await;
 
@@ -418,6 +753,7 @@ pub async fn get_items_with_shortcuts() -> Result<Vec<(String, String)>, String>
 
 #[tauri::command]
 pub async fn import_whiteboard_json(json: String) -> Result<WhiteboardState, String> {
+    lock::ensure_unlocked().await?;
     let state = APP_STATE.get().ok_or("App state not initialized")?;
     let storage = state.persistent_storage.read().await;
 
@@ -426,20 +762,24 @@ pub async fn import_whiteboard_json(json: String) -> Result<WhiteboardState, Str
         .map_err(|e| format!("Invalid JSON: {}", e))?;
 
     if let Some(storage) = storage.as_ref() {
-        // Process each top-level group recursively
-        for group in imported_groups {
-            import_group_recursive(storage, group, None).await?;
-        }
+        // Process each top-level group recursively; undone as a whole
+        journaled(storage, "Import", async {
+            for group in imported_groups {
+                import_group_recursive(storage, group, None).await?;
+            }
+            Ok(())
+        })
+        .await?;
 
         // Return the updated whiteboard state
-        storage.load_whiteboard().await.map_err(|e| e.to_string())
+        reload_whiteboard(storage).await
     } else {
         Err("Storage not available".to_string())
     }
 }
 
 async fn import_group_recursive(
-    storage: &crate::storage::persistent::PersistentStorage,
+    storage: &PersistentStorage,
     imported: ImportedGroup,
     parent_id: Option<Uuid>,
 ) -> Result<(), String> {
@@ -475,6 +815,170 @@ async fn import_group_recursive(
     Ok(())
 }
 
+/// Revert the latest whiteboard change and return the updated whiteboard
+#[tauri::command]
+pub async fn undo() -> Result<WhiteboardState, String> {
+    lock::ensure_unlocked().await?;
+    let state = APP_STATE.get().ok_or("App state not initialized")?;
+    let storage = state.persistent_storage.read().await;
+    let storage = storage.as_ref().ok_or("Storage not initialized")?;
+
+    if !storage.undo().await.map_err(|e| e.to_string())? {
+        return Err("Nothing to undo".to_string());
+    }
+    reload_whiteboard(storage).await
+}
+
+/// Apply the last undone whiteboard change again and return the updated whiteboard
+#[tauri::command]
+pub async fn redo() -> Result<WhiteboardState, String> {
+    lock::ensure_unlocked().await?;
+    let state = APP_STATE.get().ok_or("App state not initialized")?;
+    let storage = state.persistent_storage.read().await;
+    let storage = storage.as_ref().ok_or("Storage not initialized")?;
+
+    if !storage.redo().await.map_err(|e| e.to_string())? {
+        return Err("Nothing to redo".to_string());
+    }
+    reload_whiteboard(storage).await
+}
+
+#[tauri::command]
+pub async fn get_undo_state() -> Result<UndoState, String> {
+    let state = APP_STATE.get().ok_or("App state not initialized")?;
+    let storage = state.persistent_storage.read().await;
+
+    match storage.as_ref() {
+        Some(storage) => storage.undo_state().await.map_err(|e| e.to_string()),
+        None => Ok(UndoState::default()),
+    }
+}
+
+#[tauri::command]
+pub async fn get_undo_depth() -> Result<u32, String> {
+    let state = APP_STATE.get().ok_or("App state not initialized")?;
+    let storage = state.persistent_storage.read().await;
+    let storage = storage.as_ref().ok_or("Storage not initialized")?;
+    storage.undo_depth().await.map_err(|e| e.to_string())
+}
+
+/// Number of whiteboard changes that can be undone; 0 turns undo off
+#[tauri::command]
+pub async fn set_undo_depth(depth: u32) -> Result<(), String> {
+    let state = APP_STATE.get().ok_or("App state not initialized")?;
+    let storage = state.persistent_storage.read().await;
+    let storage = storage.as_ref().ok_or("Storage not initialized")?;
+    storage.set_undo_depth(depth).await.map_err(|e| e.to_string())
+}
+
+/// Take a snapshot of the whiteboard now
+#[tauri::command]
+pub async fn create_snapshot(label: Option<String>) -> Result<SnapshotInfo, String> {
+    lock::ensure_unlocked().await?;
+    let state = APP_STATE.get().ok_or("App state not initialized")?;
+    let storage = state.persistent_storage.read().await;
+    let storage = storage.as_ref().ok_or("Storage not initialized")?;
+
+    storage
+        .create_snapshot(label.as_deref().filter(|l| !l.is_empty()), false)
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| "Snapshot not created".to_string())
+}
+
+#[tauri::command]
+pub async fn list_snapshots() -> Result<Vec<SnapshotInfo>, String> {
+    lock::ensure_unlocked().await?;
+    let state = APP_STATE.get().ok_or("App state not initialized")?;
+    let storage = state.persistent_storage.read().await;
+
+    match storage.as_ref() {
+        Some(storage) => storage.list_snapshots().await.map_err(|e| e.to_string()),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// What changed on the whiteboard since a snapshot
+#[tauri::command]
+pub async fn diff_snapshot(id: String) -> Result<SnapshotDiff, String> {
+    lock::ensure_unlocked().await?;
+    let state = APP_STATE.get().ok_or("App state not initialized")?;
+    let storage = state.persistent_storage.read().await;
+    let storage = storage.as_ref().ok_or("Storage not initialized")?;
+
+    let id = Uuid::parse_str(&id).map_err(|e| e.to_string())?;
+    let snapshot = storage
+        .load_snapshot(id)
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Snapshot not found: {}", id))?;
+    let current = state.whiteboard.get(storage).await.map_err(|e| e.to_string())?;
+    Ok(snapshot.diff(&SnapshotData::from_whiteboard(&current)))
+}
+
+/// Restore the whiteboard, or only one group, from a snapshot and return the
+/// updated whiteboard. Can be undone.
+#[tauri::command]
+pub async fn restore_snapshot(id: String, group: Option<String>) -> Result<WhiteboardState, String> {
+    lock::ensure_unlocked().await?;
+    let state = APP_STATE.get().ok_or("App state not initialized")?;
+    let storage = state.persistent_storage.read().await;
+    let storage = storage.as_ref().ok_or("Storage not initialized")?;
+
+    let id = Uuid::parse_str(&id).map_err(|e| e.to_string())?;
+    let group = group
+        .map(|g| Uuid::parse_str(&g))
+        .transpose()
+        .map_err(|e| e.to_string())?;
+    let restored = journaled(storage, "Restore snapshot", async {
+        storage.restore_snapshot(id, group).await.map_err(|e| e.to_string())
+    })
+    .await?;
+    if !restored {
+        return Err(format!("Snapshot or group not found: {}", id));
+    }
+    reload_whiteboard(storage).await
+}
+
+#[tauri::command]
+pub async fn delete_snapshot(id: String) -> Result<(), String> {
+    let state = APP_STATE.get().ok_or("App state not initialized")?;
+    let storage = state.persistent_storage.read().await;
+
+    let id = Uuid::parse_str(&id).map_err(|e| e.to_string())?;
+    if let Some(storage) = storage.as_ref() {
+        storage.delete_snapshot(id).await.map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn get_snapshot_policy() -> Result<SnapshotPolicy, String> {
+    let state = APP_STATE.get().ok_or("App state not initialized")?;
+    let storage = state.persistent_storage.read().await;
+    let storage = storage.as_ref().ok_or("Storage not initialized")?;
+    storage.snapshot_policy().await.map_err(|e| e.to_string())
+}
+
+/// Change how often automatic snapshots are taken and how many are kept
+#[tauri::command]
+pub async fn set_snapshot_policy(policy: SnapshotPolicy) -> Result<(), String> {
+    let state = APP_STATE.get().ok_or("App state not initialized")?;
+    let storage = state.persistent_storage.read().await;
+    let storage = storage.as_ref().ok_or("Storage not initialized")?;
+
+    let json = serde_json::to_string(&policy).map_err(|e| e.to_string())?;
+    storage
+        .set_setting(SNAPSHOT_POLICY_SETTING, &json)
+        .await
+        .map_err(|e| e.to_string())?;
+    storage
+        .prune_snapshots(policy.keep)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
 #[derive(serde::Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ExportedItem {
@@ -495,11 +999,12 @@ pub struct ExportedGroup {
 
 #[tauri::command]
 pub async fn export_whiteboard_json() -> Result<String, String> {
+    lock::ensure_unlocked().await?;
     let state = APP_STATE.get().ok_or("App state not initialized")?;
     let storage = state.persistent_storage.read().await;
 
     if let Some(storage) = storage.as_ref() {
-        let whiteboard = storage.load_whiteboard().await.map_err(|e| e.to_string())?;
+        let whiteboard = state.whiteboard.get(storage).await.map_err(|e| e.to_string())?;
 
         // Build export structure from root groups
         let mut root_groups: Vec<ExportedGroup> = Vec::new();
@@ -528,8 +1033,9 @@ fn export_group_recursive(whiteboard: &WhiteboardState, group_id: Uuid) -> Expor
         .filter(|item| item.parent_group == Some(group_id))
         .map(|item| {
             let value = match &item.content.data {
-                ClipboardData::Text { text, .. } => text.clone(),
                 ClipboardData::Image { .. } => "[image]".to_string(),
+                ClipboardData::Files { uris, .. } => uris.join("\n"),
+                data => data.text().unwrap_or_default().to_string(),
             };
             ExportedItem {
                 shortcut: item.shortcut.clone(),
@@ -573,6 +1079,289 @@ pub async fn get_global_shortcut() -> Result<String, String> {
     }
 }
 
+#[tauri::command]
+pub fn get_monitor_state() -> MonitorState {
+    monitor::monitor_state()
+}
+
+/// Pause capture, for `minutes` if given, otherwise until resumed
+#[tauri::command]
+pub fn pause_monitoring(minutes: Option<u64>) -> MonitorState {
+    monitor::pause_monitoring(minutes.map(|m| std::time::Duration::from_secs(m.saturating_mul(60))))
+}
+
+#[tauri::command]
+pub fn resume_monitoring() -> MonitorState {
+    monitor::resume_monitoring()
+}
+
+#[tauri::command]
+pub async fn get_history_policy() -> Result<HistoryPolicy, String> {
+    let state = APP_STATE.get().ok_or("App state not initialized")?;
+    Ok(state.history_policy.read().await.clone())
+}
+
+#[tauri::command]
+pub async fn set_history_policy(policy: HistoryPolicy) -> Result<(), String> {
+    let state = APP_STATE.get().ok_or("App state not initialized")?;
+    {
+        let storage = state.persistent_storage.read().await;
+        if let Some(storage) = storage.as_ref() {
+            let json = serde_json::to_string(&policy).map_err(|e| e.to_string())?;
+            storage
+                .set_setting(history::HISTORY_POLICY_SETTING, &json)
+                .await
+                .map_err(|e| e.to_string())?;
+        }
+    }
+    *state.history_policy.write().await = policy;
+
+    // Apply the new retention right away
+    history::run_cleanup().await?;
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn get_lock_state() -> Result<LockState, String> {
+    let state = APP_STATE.get().ok_or("App state not initialized")?;
+    let storage = state.persistent_storage.read().await;
+    let storage = storage.as_ref().ok_or("Storage not initialized")?;
+    storage.lock_state().await.map_err(|e| e.to_string())
+}
+
+/// Unlock encrypted data with the master password, then load what was skipped while locked
+#[tauri::command]
+pub async fn unlock(app: tauri::AppHandle, password: String) -> Result<LockState, String> {
+    let password = Zeroizing::new(password);
+    let state = APP_STATE.get().ok_or("App state not initialized")?;
+    let lock_state = {
+        let storage = state.persistent_storage.read().await;
+        let storage = storage.as_ref().ok_or("Storage not initialized")?;
+        storage.unlock(&password).await.map_err(|e| e.to_string())?;
+        storage.lock_state().await.map_err(|e| e.to_string())?
+    };
+
+    lock::touch();
+    history::restore_history().await;
+    state.search_index.invalidate().await;
+    lock::notify(&app, &lock_state);
+    Ok(lock_state)
+}
+
+#[tauri::command]
+pub async fn verify_master_password(password: String) -> Result<bool, String> {
+    let password = Zeroizing::new(password);
+    let state = APP_STATE.get().ok_or("App state not initialized")?;
+    let storage = state.persistent_storage.read().await;
+    let storage = storage.as_ref().ok_or("Storage not initialized")?;
+    storage
+        .verify_master_password(&password)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Protect the database with a master password instead of the machine-derived key,
+/// or change the existing password, given as `current_password`. Stored data is
+/// re-encrypted with the new key.
+#[tauri::command]
+pub async fn set_master_password(
+    app: tauri::AppHandle,
+    current_password: Option<String>,
+    password: String,
+) -> Result<LockState, String> {
+    let current_password = current_password.map(Zeroizing::new);
+    let password = Zeroizing::new(password);
+    if password.chars().count() < 8 {
+        return Err("Master password must be at least 8 characters".to_string());
+    }
+
+    let state = APP_STATE.get().ok_or("App state not initialized")?;
+    let storage = state.persistent_storage.read().await;
+    let storage = storage.as_ref().ok_or("Storage not initialized")?;
+    storage
+        .set_master_password(current_password.as_ref().map(|p| p.as_str()), &password)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let lock_state = storage.lock_state().await.map_err(|e| e.to_string())?;
+    lock::notify(&app, &lock_state);
+    Ok(lock_state)
+}
+
+#[tauri::command]
+pub async fn get_kdf_params() -> Result<KdfParams, String> {
+    let state = APP_STATE.get().ok_or("App state not initialized")?;
+    let storage = state.persistent_storage.read().await;
+    let storage = storage.as_ref().ok_or("Storage not initialized")?;
+    storage.kdf_params().await.map_err(|e| e.to_string())
+}
+
+/// Re-encrypt stored data with a key from a new salt and, optionally, stronger
+/// Argon2 parameters. Emits `key-rotation-progress` while running.
+#[tauri::command]
+pub async fn rotate_encryption_key(
+    app: tauri::AppHandle,
+    password: Option<String>,
+    kdf: Option<KdfParams>,
+) -> Result<KdfParams, String> {
+    let password = password.map(Zeroizing::new);
+    let state = APP_STATE.get().ok_or("App state not initialized")?;
+    let storage = state.persistent_storage.read().await;
+    let storage = storage.as_ref().ok_or("Storage not initialized")?;
+
+    let kdf = kdf.unwrap_or_default();
+    let minimum = KdfParams::default();
+    if kdf.memory_kib < minimum.memory_kib || kdf.iterations < minimum.iterations {
+        return Err("Key derivation parameters are weaker than the defaults".to_string());
+    }
+    let maximum = KdfParams::MAX;
+    if kdf.memory_kib > maximum.memory_kib
+        || kdf.iterations > maximum.iterations
+        || !(1..=maximum.parallelism).contains(&kdf.parallelism)
+    {
+        return Err("Key derivation parameters exceed the supported maximum".to_string());
+    }
+
+    storage
+        .rotate_key(password.as_ref().map(|p| p.as_str()), kdf, |progress: RekeyProgress| {
+            if progress.done % 50 == 0 || progress.done == progress.total {
+                let _ = app.emit("key-rotation-progress", progress);
+            }
+        })
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(kdf)
+}
+
+/// Lock now; fails without a master password
+#[tauri::command]
+pub async fn lock(app: tauri::AppHandle) -> Result<LockState, String> {
+    lock::lock(&app).await
+}
+
+#[tauri::command]
+pub async fn get_auto_lock_policy() -> Result<AutoLockPolicy, String> {
+    let state = APP_STATE.get().ok_or("App state not initialized")?;
+    Ok(*state.auto_lock.read().await)
+}
+
+#[tauri::command]
+pub async fn set_auto_lock_policy(policy: AutoLockPolicy) -> Result<(), String> {
+    let state = APP_STATE.get().ok_or("App state not initialized")?;
+    {
+        let storage = state.persistent_storage.read().await;
+        if let Some(storage) = storage.as_ref() {
+            let json = serde_json::to_string(&policy).map_err(|e| e.to_string())?;
+            storage
+                .set_setting(lock::AUTO_LOCK_SETTING, &json)
+                .await
+                .map_err(|e| e.to_string())?;
+        }
+    }
+    *state.auto_lock.write().await = policy;
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn get_auto_clear_policy() -> Result<AutoClearPolicy, String> {
+    let state = APP_STATE.get().ok_or("App state not initialized")?;
+    Ok(*state.auto_clear.read().await)
+}
+
+#[tauri::command]
+pub async fn set_auto_clear_policy(policy: AutoClearPolicy) -> Result<(), String> {
+    let state = APP_STATE.get().ok_or("App state not initialized")?;
+    {
+        let storage = state.persistent_storage.read().await;
+        if let Some(storage) = storage.as_ref() {
+            let json = serde_json::to_string(&policy).map_err(|e| e.to_string())?;
+            storage
+                .set_setting(auto_clear::AUTO_CLEAR_SETTING, &json)
+                .await
+                .map_err(|e| e.to_string())?;
+        }
+    }
+    *state.auto_clear.write().await = policy;
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn get_dedupe_mode() -> Result<DedupeMode, String> {
+    let state = APP_STATE.get().ok_or("App state not initialized")?;
+    Ok(state.volatile_storage.dedupe_mode().await)
+}
+
+#[tauri::command]
+pub async fn set_dedupe_mode(mode: DedupeMode) -> Result<(), String> {
+    let state = APP_STATE.get().ok_or("App state not initialized")?;
+    {
+        let storage = state.persistent_storage.read().await;
+        if let Some(storage) = storage.as_ref() {
+            let json = serde_json::to_string(&mode).map_err(|e| e.to_string())?;
+            storage
+                .set_setting(DEDUPE_MODE_SETTING, &json)
+                .await
+                .map_err(|e| e.to_string())?;
+        }
+    }
+    state.volatile_storage.set_dedupe_mode(mode).await;
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn get_history_limits() -> Result<HistoryLimits, String> {
+    let state = APP_STATE.get().ok_or("App state not initialized")?;
+    Ok(state.volatile_storage.limits().await)
+}
+
+/// Takes effect immediately; entries evicted by tighter limits are announced to the frontend
+#[tauri::command]
+pub async fn set_history_limits(app: tauri::AppHandle, limits: HistoryLimits) -> Result<HistoryLimits, String> {
+    let limits = limits.sanitize();
+    let state = APP_STATE.get().ok_or("App state not initialized")?;
+    {
+        let storage = state.persistent_storage.read().await;
+        if let Some(storage) = storage.as_ref() {
+            let json = serde_json::to_string(&limits).map_err(|e| e.to_string())?;
+            storage
+                .set_setting(HISTORY_LIMITS_SETTING, &json)
+                .await
+                .map_err(|e| e.to_string())?;
+        }
+    }
+
+    let evicted = state.volatile_storage.set_limits(limits).await;
+    if !evicted.is_empty() {
+        let _ = app.emit("clipboard-evicted", &evicted);
+    }
+    Ok(limits)
+}
+
+#[tauri::command]
+pub async fn get_excluded_apps() -> Result<Vec<String>, String> {
+    let state = APP_STATE.get().ok_or("App state not initialized")?;
+    Ok(state.exclusions.apps().await)
+}
+
+#[tauri::command]
+pub async fn set_excluded_apps(apps: Vec<String>) -> Result<Vec<String>, String> {
+    let apps = crate::clipboard::exclusion::sanitize(apps);
+
+    let state = APP_STATE.get().ok_or("App state not initialized")?;
+    let storage = state.persistent_storage.read().await;
+
+    if let Some(storage) = storage.as_ref() {
+        let json = serde_json::to_string(&apps).map_err(|e| e.to_string())?;
+        storage
+            .set_setting(crate::clipboard::exclusion::EXCLUDED_APPS_SETTING, &json)
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+
+    state.exclusions.set_apps(apps.clone()).await;
+    Ok(apps)
+}
+
 #[tauri::command]
 pub async fn set_global_shortcut(app: tauri::AppHandle, shortcut: String) -> Result<(), String> {
     // Validate the shortcut format first
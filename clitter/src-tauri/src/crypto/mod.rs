@@ -1,2 +1,2 @@
 pub mod secure_store;
-pub use secure_store::SecureStore;
+pub use secure_store::{KdfParams, SecureStore};
@@ -5,7 +5,11 @@ use aes_gcm::{
 use argon2::{Algorithm, Argon2, Params, Version};
 use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
 use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
 use thiserror::Error;
+use zeroize::Zeroize;
 
 const SALT_LEN: usize = 32;
 const NONCE_LEN: usize = 12;
@@ -24,24 +28,103 @@ pub enum CryptoError {
     Base64DecodeFailed,
 }
 
+/// Argon2id cost, stored next to the salt so it can be raised later
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KdfParams {
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
+
+impl KdfParams {
+    /// Highest cost accepted from the user, so a derivation cannot exhaust
+    /// memory or run for minutes
+    pub const MAX: Self = Self {
+        memory_kib: 1024 * 1024,
+        iterations: 32,
+        parallelism: 16,
+    };
+}
+
+impl Default for KdfParams {
+    fn default() -> Self {
+        Self {
+            memory_kib: 65536,
+            iterations: 3,
+            parallelism: 4,
+        }
+    }
+}
+
+/// Key material on its own heap allocation, kept out of swap where the OS
+/// allows and overwritten when dropped
+struct KeyBytes(Box<[u8; 32]>);
+
+impl KeyBytes {
+    fn new() -> Self {
+        let key = Box::new([0u8; 32]);
+        lock_memory(key.as_ptr(), key.len());
+        Self(key)
+    }
+}
+
+impl Drop for KeyBytes {
+    // Not unlocked: the page may hold another key
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+/// Best effort; fails without privileges or over RLIMIT_MEMLOCK
+fn lock_memory(ptr: *const u8, len: usize) {
+    #[cfg(unix)]
+    unsafe {
+        libc::mlock(ptr.cast(), len);
+    }
+
+    #[cfg(windows)]
+    unsafe {
+        let _ = windows::Win32::System::Memory::VirtualLock(ptr.cast(), len);
+    }
+
+    #[cfg(not(any(unix, windows)))]
+    let _ = (ptr, len);
+}
+
 pub struct SecureStore {
-    key: [u8; 32],
+    key: KeyBytes,
 }
 
 impl SecureStore {
     pub fn from_password(password: &str, salt: &[u8; SALT_LEN]) -> Result<Self, CryptoError> {
-        let params =
-            Params::new(65536, 3, 4, Some(32)).map_err(|_| CryptoError::KeyDerivationFailed)?;
+        Self::from_password_with(password, salt, &KdfParams::default())
+    }
+
+    pub fn from_password_with(
+        password: &str,
+        salt: &[u8; SALT_LEN],
+        kdf: &KdfParams,
+    ) -> Result<Self, CryptoError> {
+        let params = Params::new(kdf.memory_kib, kdf.iterations, kdf.parallelism, Some(32))
+            .map_err(|_| CryptoError::KeyDerivationFailed)?;
         let argon2 = Argon2::new(Algorithm::Argon2id, Version::V0x13, params);
 
-        let mut key = [0u8; 32];
+        let mut key = KeyBytes::new();
         argon2
-            .hash_password_into(password.as_bytes(), salt, &mut key)
+            .hash_password_into(password.as_bytes(), salt, key.0.as_mut_slice())
             .map_err(|_| CryptoError::KeyDerivationFailed)?;
 
         Ok(Self { key })
     }
 
+    /// Random key that only lives as long as this process
+    pub fn ephemeral() -> Self {
+        let mut key = KeyBytes::new();
+        rand::rng().fill_bytes(key.0.as_mut_slice());
+        Self { key }
+    }
+
     pub fn generate_salt() -> [u8; SALT_LEN] {
         let mut salt = [0u8; SALT_LEN];
         rand::rng().fill_bytes(&mut salt);
@@ -50,7 +133,7 @@ impl SecureStore {
 
     pub fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>, CryptoError> {
         let cipher =
-            Aes256Gcm::new_from_slice(&self.key).map_err(|_| CryptoError::EncryptionFailed)?;
+            Aes256Gcm::new_from_slice(self.key()).map_err(|_| CryptoError::EncryptionFailed)?;
 
         let mut nonce_bytes = [0u8; NONCE_LEN];
         rand::rng().fill_bytes(&mut nonce_bytes);
@@ -74,7 +157,7 @@ impl SecureStore {
         }
 
         let cipher =
-            Aes256Gcm::new_from_slice(&self.key).map_err(|_| CryptoError::DecryptionFailed)?;
+            Aes256Gcm::new_from_slice(self.key()).map_err(|_| CryptoError::DecryptionFailed)?;
         let nonce = Nonce::from_slice(&data[..NONCE_LEN]);
         let ciphertext = &data[NONCE_LEN..];
 
@@ -86,7 +169,15 @@ impl SecureStore {
     }
 
     pub fn key(&self) -> &[u8; 32] {
-        &self.key
+        &self.key.0
+    }
+
+    /// HMAC-SHA256 with this store's key, for comparing secrets without keeping
+    /// a plain hash of them around
+    pub fn fingerprint(&self, data: &[u8]) -> String {
+        let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(self.key()).expect("HMAC takes keys of any size");
+        mac.update(data);
+        crate::types::to_hex(&mac.finalize().into_bytes())
     }
 
     /// Encrypt text and return as base64 string
@@ -105,9 +196,9 @@ impl SecureStore {
     }
 
     /// Create a SecureStore from machine-specific information
-    pub fn from_machine_id(salt: &[u8; SALT_LEN]) -> Result<Self, CryptoError> {
+    pub fn from_machine_id(salt: &[u8; SALT_LEN], kdf: &KdfParams) -> Result<Self, CryptoError> {
         let machine_id = get_machine_id();
-        Self::from_password(&machine_id, salt)
+        Self::from_password_with(&machine_id, salt, kdf)
     }
 }
 
@@ -154,4 +245,14 @@ mod tests {
         // Decryption with wrong password should fail
         assert!(store2.decrypt(&encrypted).is_err());
     }
+
+    #[test]
+    fn test_fingerprint_is_keyed() {
+        let store1 = SecureStore::ephemeral();
+        let store2 = SecureStore::ephemeral();
+
+        assert_eq!(store1.fingerprint(b"1234"), store1.fingerprint(b"1234"));
+        assert_ne!(store1.fingerprint(b"1234"), store1.fingerprint(b"1235"));
+        assert_ne!(store1.fingerprint(b"1234"), store2.fingerprint(b"1234"));
+    }
 }
@@ -1,6 +1,7 @@
 use std::sync::RwLock;
 use tauri::{AppHandle, Manager};
 use tauri_plugin_global_shortcut::{Code, GlobalShortcutExt, Modifiers, Shortcut, ShortcutState};
+use crate::clipboard::monitor;
 use crate::window_focus;
 use crate::APP_STATE;
 
@@ -11,6 +12,31 @@ pub fn register_global_shortcuts(app: &tauri::App) -> Result<(), Box<dyn std::er
     // Default shortcut - will be updated after storage is initialized
     let default_shortcut = Shortcut::new(Some(Modifiers::ALT), Code::KeyV);
     register_shortcut_internal(app.handle(), default_shortcut)?;
+
+    // Toggle capture pause
+    let pause_shortcut = Shortcut::new(Some(Modifiers::ALT | Modifiers::SHIFT), Code::KeyV);
+    if let Err(e) = app.global_shortcut().on_shortcut(pause_shortcut, |_app, _shortcut, event| {
+        if event.state == ShortcutState::Pressed {
+            monitor::toggle_pause();
+        }
+    }) {
+        eprintln!("Failed to register pause shortcut: {}", e);
+    }
+
+    // Lock encrypted data
+    let lock_shortcut = Shortcut::new(Some(Modifiers::ALT | Modifiers::SHIFT), Code::KeyL);
+    if let Err(e) = app.global_shortcut().on_shortcut(lock_shortcut, |app, _shortcut, event| {
+        if event.state == ShortcutState::Pressed {
+            let app = app.clone();
+            tauri::async_runtime::spawn(async move {
+                if let Err(e) = crate::lock::lock(&app).await {
+                    eprintln!("Failed to lock: {}", e);
+                }
+            });
+        }
+    }) {
+        eprintln!("Failed to register lock shortcut: {}", e);
+    }
     Ok(())
 }
 
@@ -26,6 +52,7 @@ fn register_shortcut_internal(app: &AppHandle, shortcut: Shortcut) -> Result<(),
                     let _ = window.hide();
                 } else {
                     window_focus::save_previous_window();
+                    crate::lock::touch();
                     let _ = window.show();
                     let _ = window.set_focus();
                 }
@@ -57,6 +84,7 @@ pub fn change_shortcut(app: &AppHandle, new_shortcut_str: &str) -> Result<(), St
                     let _ = window.hide();
                 } else {
                     window_focus::save_previous_window();
+                    crate::lock::touch();
                     let _ = window.show();
                     let _ = window.set_focus();
                 }
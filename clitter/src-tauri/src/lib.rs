@@ -2,13 +2,22 @@ pub mod clipboard;
 pub mod commands;
 pub mod crypto;
 pub mod hotkey;
+pub mod lock;
+pub mod search;
 pub mod storage;
 pub mod tray;
 pub mod types;
 pub mod window_focus;
 
+use clipboard::auto_clear::AutoClearPolicy;
+use clipboard::exclusion::ExclusionList;
+use lock::AutoLockPolicy;
 use once_cell::sync::OnceCell;
-use storage::{persistent::PersistentStorage, volatile::VolatileStorage};
+use search::SearchIndex;
+use storage::{
+    history::HistoryPolicy, persistent::PersistentStorage, volatile::VolatileStorage,
+    whiteboard_cache::WhiteboardCache,
+};
 use tokio::sync::RwLock;
 
 pub static APP_STATE: OnceCell<AppState> = OnceCell::new();
@@ -16,7 +25,12 @@ pub static APP_STATE: OnceCell<AppState> = OnceCell::new();
 pub struct AppState {
     pub volatile_storage: VolatileStorage,
     pub persistent_storage: RwLock<Option<PersistentStorage>>,
-    pub crypto_key: RwLock<Option<[u8; 32]>>,
+    pub exclusions: ExclusionList,
+    pub history_policy: RwLock<HistoryPolicy>,
+    pub search_index: SearchIndex,
+    pub whiteboard: WhiteboardCache,
+    pub auto_lock: RwLock<AutoLockPolicy>,
+    pub auto_clear: RwLock<AutoClearPolicy>,
 }
 
 impl AppState {
@@ -24,7 +38,12 @@ impl AppState {
         Self {
             volatile_storage: VolatileStorage::new(),
             persistent_storage: RwLock::new(None),
-            crypto_key: RwLock::new(None),
+            exclusions: ExclusionList::new(),
+            history_policy: RwLock::new(HistoryPolicy::default()),
+            search_index: SearchIndex::new(),
+            whiteboard: WhiteboardCache::new(),
+            auto_lock: RwLock::new(AutoLockPolicy::default()),
+            auto_clear: RwLock::new(AutoClearPolicy::default()),
         }
     }
 }
@@ -63,6 +82,17 @@ pub fn run() {
                         }
                         // Load saved shortcut after storage is ready
                         hotkey::load_saved_shortcut(&app_handle).await;
+                        clipboard::exclusion::load_saved_exclusions().await;
+                        clipboard::auto_clear::load_saved_policy().await;
+                        storage::history::load_saved_policy().await;
+                        storage::history::load_saved_dedupe_mode().await;
+                        storage::history::load_saved_limits().await;
+                        storage::history::restore_history().await;
+                        storage::history::start_cleanup_job();
+                        storage::snapshot::start_snapshot_job();
+                        lock::load_saved_policy().await;
+                        lock::announce(&app_handle).await;
+                        lock::start_auto_lock(app_handle.clone());
                     }
                     Err(e) => {
                         eprintln!("Failed to initialize persistent storage: {}", e);
@@ -77,6 +107,10 @@ pub fn run() {
         })
         .invoke_handler(tauri::generate_handler![
             commands::get_clipboard_history,
+            commands::get_clipboard_history_page,
+            commands::get_clipboard_content,
+            commands::get_clipboard_history_by_source,
+            commands::search,
             commands::get_recent_items,
             commands::copy_to_clipboard,
             commands::paste_to_previous_window,
@@ -87,15 +121,56 @@ pub fn run() {
             commands::remove_from_whiteboard,
             commands::create_group,
             commands::update_group,
+            commands::move_group,
             commands::delete_group,
+            commands::list_trash,
+            commands::restore_from_trash,
+            commands::delete_from_trash,
+            commands::empty_trash,
+            commands::get_trash_retention_days,
+            commands::set_trash_retention_days,
             commands::set_group_color,
             commands::set_item_shortcut,
             commands::set_group_shortcut,
             commands::get_items_with_shortcuts,
             commands::import_whiteboard_json,
+            commands::undo,
+            commands::redo,
+            commands::get_undo_state,
+            commands::get_undo_depth,
+            commands::set_undo_depth,
+            commands::create_snapshot,
+            commands::list_snapshots,
+            commands::diff_snapshot,
+            commands::restore_snapshot,
+            commands::delete_snapshot,
+            commands::get_snapshot_policy,
+            commands::set_snapshot_policy,
             commands::export_whiteboard_json,
             commands::get_global_shortcut,
             commands::set_global_shortcut,
+            commands::get_monitor_state,
+            commands::pause_monitoring,
+            commands::resume_monitoring,
+            commands::get_history_policy,
+            commands::set_history_policy,
+            commands::get_lock_state,
+            commands::unlock,
+            commands::verify_master_password,
+            commands::set_master_password,
+            commands::get_kdf_params,
+            commands::rotate_encryption_key,
+            commands::lock,
+            commands::get_auto_lock_policy,
+            commands::set_auto_lock_policy,
+            commands::get_auto_clear_policy,
+            commands::set_auto_clear_policy,
+            commands::get_dedupe_mode,
+            commands::set_dedupe_mode,
+            commands::get_history_limits,
+            commands::set_history_limits,
+            commands::get_excluded_apps,
+            commands::set_excluded_apps,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
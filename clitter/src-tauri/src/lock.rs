@@ -0,0 +1,228 @@
+//! Locking encrypted data again after inactivity, on screen lock or on request
+
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::time::Duration;
+use tauri::{AppHandle, Emitter};
+
+use crate::storage::persistent::{KeyMode, LockState, StorageError};
+use crate::tray;
+use crate::APP_STATE;
+
+/// Settings key holding the policy as JSON
+pub const AUTO_LOCK_SETTING: &str = "auto_lock";
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(15);
+
+/// When to lock automatically; only applies with a master password
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AutoLockPolicy {
+    /// Minutes without using Clitter, None to never lock on inactivity
+    pub idle_minutes: Option<u32>,
+    pub on_screen_lock: bool,
+}
+
+impl Default for AutoLockPolicy {
+    fn default() -> Self {
+        Self {
+            idle_minutes: Some(15),
+            on_screen_lock: true,
+        }
+    }
+}
+
+// Unix time (seconds) of the last use of encrypted data
+static LAST_ACTIVITY: AtomicI64 = AtomicI64::new(0);
+
+/// Record use of the app, postponing the idle lock
+pub fn touch() {
+    LAST_ACTIVITY.store(chrono::Utc::now().timestamp(), Ordering::Relaxed);
+}
+
+fn idle_for() -> Duration {
+    let idle = chrono::Utc::now().timestamp() - LAST_ACTIVITY.load(Ordering::Relaxed);
+    Duration::from_secs(idle.max(0) as u64)
+}
+
+/// True if a master password is set and has not been entered
+pub async fn is_locked() -> bool {
+    let Some(state) = APP_STATE.get() else {
+        return false;
+    };
+    let storage = state.persistent_storage.read().await;
+    match storage.as_ref() {
+        Some(storage) => storage.is_locked().await,
+        None => false,
+    }
+}
+
+/// Err(`Locked`) for commands that must not hand out encrypted data while locked
+pub async fn ensure_unlocked() -> Result<(), String> {
+    if is_locked().await {
+        return Err(StorageError::Locked.to_string());
+    }
+    touch();
+    Ok(())
+}
+
+/// Drop the key from memory. Only possible with a master password, since the
+/// machine-derived key could be derived again by anyone.
+pub async fn lock(app: &AppHandle) -> Result<LockState, String> {
+    let state = APP_STATE.get().ok_or("App state not initialized")?;
+    let lock_state = {
+        let storage = state.persistent_storage.read().await;
+        let storage = storage.as_ref().ok_or("Storage not initialized")?;
+        if storage.key_mode().await.map_err(|e| e.to_string())? != KeyMode::Password {
+            return Err("No master password set".to_string());
+        }
+        storage.lock().await;
+        storage.lock_state().await.map_err(|e| e.to_string())?
+    };
+
+    // The search index and the whiteboard cache hold decrypted data
+    state.search_index.invalidate().await;
+    state.whiteboard.invalidate().await;
+    notify(app, &lock_state);
+    Ok(lock_state)
+}
+
+/// Tell the tray and the frontend about a lock state change
+pub fn notify(app: &AppHandle, lock_state: &LockState) {
+    tray::update_lock_state(app, lock_state.locked);
+    let _ = app.emit("lock-state-changed", lock_state);
+}
+
+/// Load the saved policy from storage
+pub async fn load_saved_policy() {
+    if let Some(state) = APP_STATE.get() {
+        let storage_guard = state.persistent_storage.read().await;
+        if let Some(storage) = storage_guard.as_ref() {
+            match storage.get_setting(AUTO_LOCK_SETTING).await {
+                Ok(Some(json)) => match serde_json::from_str::<AutoLockPolicy>(&json) {
+                    Ok(policy) => *state.auto_lock.write().await = policy,
+                    Err(e) => eprintln!("Invalid auto-lock setting: {}", e),
+                },
+                Ok(None) => {}
+                Err(e) => {
+                    eprintln!("Failed to read auto-lock setting: {}", e);
+                }
+            }
+        }
+    }
+}
+
+/// Publish the lock state after startup, when the database may be locked
+pub async fn announce(app: &AppHandle) {
+    if let Some(state) = APP_STATE.get() {
+        let storage = state.persistent_storage.read().await;
+        if let Some(Ok(lock_state)) = match storage.as_ref() {
+            Some(storage) => Some(storage.lock_state().await),
+            None => None,
+        } {
+            notify(app, &lock_state);
+        }
+    }
+}
+
+/// Periodically lock when idle or when the screen is locked
+pub fn start_auto_lock(app: AppHandle) {
+    touch();
+    tauri::async_runtime::spawn(async move {
+        let mut interval = tokio::time::interval(CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+            let Some(state) = APP_STATE.get() else {
+                continue;
+            };
+            if is_locked().await {
+                continue;
+            }
+
+            let policy = *state.auto_lock.read().await;
+            let idle = policy
+                .idle_minutes
+                .is_some_and(|minutes| idle_for() >= Duration::from_secs(minutes as u64 * 60));
+            let screen_locked = policy.on_screen_lock && screen_locked();
+            if !idle && !screen_locked {
+                continue;
+            }
+
+            let password_mode = {
+                let storage = state.persistent_storage.read().await;
+                match storage.as_ref() {
+                    Some(storage) => storage.key_mode().await.ok() == Some(KeyMode::Password),
+                    None => false,
+                }
+            };
+            if password_mode {
+                if let Err(e) = lock(&app).await {
+                    eprintln!("Auto-lock failed: {}", e);
+                }
+            }
+        }
+    });
+}
+
+/// Whether the session's screen is locked (or, on X11, the screen saver is active)
+fn screen_locked() -> bool {
+    #[cfg(windows)]
+    {
+        use windows::Win32::System::StationsAndDesktops::{
+            CloseDesktop, OpenInputDesktop, SwitchDesktop, DESKTOP_CONTROL_FLAGS,
+            DESKTOP_SWITCHDESKTOP,
+        };
+
+        // The input desktop cannot be switched to while the lock screen is shown
+        unsafe {
+            match OpenInputDesktop(DESKTOP_CONTROL_FLAGS(0), false, DESKTOP_SWITCHDESKTOP) {
+                Ok(desktop) => {
+                    let locked = SwitchDesktop(desktop).is_err();
+                    let _ = CloseDesktop(desktop);
+                    locked
+                }
+                Err(_) => true,
+            }
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    {
+        x11_screen_saver_active().unwrap_or(false)
+    }
+
+    #[cfg(not(any(windows, target_os = "linux")))]
+    {
+        false
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn x11_screen_saver_active() -> Option<bool> {
+    use x11rb::connection::{Connection, RequestConnection};
+    use x11rb::protocol::screensaver::{self, ConnectionExt as _};
+
+    let (conn, screen_num) = x11rb::connect(None).ok()?;
+    conn.extension_information(screensaver::X11_EXTENSION_NAME)
+        .ok()
+        .flatten()?;
+    let root = conn.setup().roots.get(screen_num)?.root;
+    let info = conn.screensaver_query_info(root).ok()?.reply().ok()?;
+    Some(info.state == u8::from(screensaver::State::ON))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_policy_json() {
+        let json = serde_json::to_value(AutoLockPolicy::default()).unwrap();
+        assert_eq!(json, serde_json::json!({ "idleMinutes": 15, "onScreenLock": true }));
+
+        let never: AutoLockPolicy =
+            serde_json::from_value(serde_json::json!({ "idleMinutes": null, "onScreenLock": false }))
+                .unwrap();
+        assert_eq!(never.idle_minutes, None);
+    }
+}
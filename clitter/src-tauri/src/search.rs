@@ -0,0 +1,498 @@
+//! Search over the clipboard history and the whiteboard.
+//! Contents are encrypted at rest, so persisted history is decrypted once into an
+//! in-memory index and searched there. Secure contents are only kept masked, and
+//! are found by their category, source or time but never by their text.
+
+use chrono::{DateTime, Utc};
+use fuzzy_matcher::skim::SkimMatcherV2;
+use fuzzy_matcher::FuzzyMatcher;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use tokio::sync::{RwLock, RwLockReadGuard};
+use uuid::Uuid;
+
+use crate::storage::persistent::PersistentStorage;
+use crate::types::{Category, ClipboardContent, ClipboardData, Group};
+use crate::APP_STATE;
+
+const DEFAULT_LIMIT: usize = 50;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchMode {
+    #[default]
+    Substring,
+    Fuzzy,
+    Regex,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchQuery {
+    #[serde(default)]
+    pub query: String,
+    #[serde(default)]
+    pub mode: SearchMode,
+    pub category: Option<Category>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    /// Source application, compared case-insensitively
+    pub source: Option<String>,
+    /// Only whiteboard items inside this group or its subgroups
+    pub group: Option<Uuid>,
+    pub limit: Option<usize>,
+}
+
+/// Highlight range in UTF-16 code units, as used by JavaScript strings
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct MatchRange {
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchResult {
+    /// Images from the persisted history come without the original; fetch it
+    /// with `get_clipboard_content`
+    pub content: ClipboardContent,
+    pub score: i64,
+    /// Ranges within the content's text (file names for file lists)
+    pub matches: Vec<MatchRange>,
+    /// Set when the result is a whiteboard item
+    pub whiteboard_item_id: Option<Uuid>,
+    pub group_id: Option<Uuid>,
+}
+
+/// Something that can be searched, borrowed from where it is kept
+#[derive(Debug, Clone)]
+pub struct Candidate<'a> {
+    pub content: &'a ClipboardContent,
+    pub whiteboard_item_id: Option<Uuid>,
+    pub group_id: Option<Uuid>,
+    /// The item's group and all groups above it
+    pub group_path: Vec<Uuid>,
+}
+
+impl<'a> Candidate<'a> {
+    pub fn history(content: &'a ClipboardContent) -> Self {
+        Self {
+            content,
+            whiteboard_item_id: None,
+            group_id: None,
+            group_path: Vec::new(),
+        }
+    }
+}
+
+/// Copy of a content for the index: Secure payloads masked and images without
+/// the original, which only the previews are needed of
+fn indexed(mut content: ClipboardContent) -> ClipboardContent {
+    if content.category == Category::Secure {
+        let masked = content.masked();
+        content.wipe();
+        return masked;
+    }
+    if let ClipboardData::Image { base64, .. } = &mut content.data {
+        *base64 = String::new();
+    }
+    content
+}
+
+/// Decrypted copy of the persisted history, built on first use
+pub struct SearchIndex {
+    persisted: RwLock<Option<Vec<ClipboardContent>>>,
+}
+
+impl SearchIndex {
+    pub fn new() -> Self {
+        Self {
+            persisted: RwLock::new(None),
+        }
+    }
+
+    /// Drop the index, e.g. after entries were removed from disk
+    pub async fn invalidate(&self) {
+        *self.persisted.write().await = None;
+    }
+
+    /// Add a newly persisted entry if the index has been built
+    pub async fn record(&self, content: &ClipboardContent) {
+        if let Some(entries) = self.persisted.write().await.as_mut() {
+            entries.retain(|c| c.id != content.id);
+            entries.push(indexed(content.clone()));
+        }
+    }
+
+    async fn persisted(&self, storage: &PersistentStorage) -> RwLockReadGuard<'_, [ClipboardContent]> {
+        let index = self.persisted.read().await;
+        if index.is_some() {
+            return RwLockReadGuard::map(index, |i| i.as_deref().unwrap_or_default());
+        }
+        drop(index);
+
+        let mut index = self.persisted.write().await;
+        if index.is_none() {
+            match storage.load_history(i64::MAX).await {
+                Ok(entries) => *index = Some(entries.into_iter().map(indexed).collect()),
+                Err(e) => eprintln!("Failed to build search index: {}", e),
+            }
+        }
+        RwLockReadGuard::map(index.downgrade(), |i| i.as_deref().unwrap_or_default())
+    }
+}
+
+impl Default for SearchIndex {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Search the in-memory history, the persisted history and the whiteboard
+pub async fn search(query: &SearchQuery) -> Result<Vec<SearchResult>, String> {
+    let state = APP_STATE.get().ok_or("App state not initialized")?;
+
+    // While locked only the non-secure in-memory history is searchable
+    let locked = crate::lock::is_locked().await;
+    let storage = state.persistent_storage.read().await;
+    let storage = storage.as_ref().filter(|_| !locked);
+
+    // Searched in place; only the results are copied
+    let persisted = match storage {
+        Some(storage) => Some(state.search_index.persisted(storage).await),
+        None => None,
+    };
+    let whiteboard = match storage {
+        Some(storage) => Some(state.whiteboard.read(storage).await.map_err(|e| e.to_string())?),
+        None => None,
+    };
+    let history = state.volatile_storage.entries().await;
+
+    let mut candidates: Vec<Candidate> = history
+        .iter()
+        .filter(|c| !(locked && c.category == Category::Secure))
+        .map(Candidate::history)
+        .collect();
+
+    if let Some(persisted) = &persisted {
+        let seen: HashSet<Uuid> = candidates.iter().map(|c| c.content.id).collect();
+        candidates.extend(
+            persisted
+                .iter()
+                .filter(|c| !seen.contains(&c.id))
+                .map(Candidate::history),
+        );
+    }
+
+    if let Some(whiteboard) = &whiteboard {
+        for item in whiteboard.items.values() {
+            candidates.push(Candidate {
+                group_path: group_path(&whiteboard.groups, item.parent_group),
+                content: &item.content,
+                whiteboard_item_id: Some(item.id),
+                group_id: item.parent_group,
+            });
+        }
+    }
+
+    rank(candidates, query)
+}
+
+/// Filter, match and order candidates, best match first
+pub fn rank(candidates: Vec<Candidate<'_>>, query: &SearchQuery) -> Result<Vec<SearchResult>, String> {
+    let matcher = Matcher::new(query)?;
+
+    let mut scored: Vec<(Candidate, i64, Vec<MatchRange>)> = candidates
+        .into_iter()
+        .filter(|c| passes_filters(c, query))
+        .filter_map(|c| {
+            let (score, matches) = match &matcher {
+                None => (0, Vec::new()),
+                Some(matcher) => matcher.find(searchable_text(c.content)?)?,
+            };
+            Some((c, score, matches))
+        })
+        .collect();
+
+    // Equal scores: most recently copied first
+    scored.sort_by(|(a, a_score, _), (b, b_score, _)| {
+        b_score
+            .cmp(a_score)
+            .then(b.content.copied_at.cmp(&a.content.copied_at))
+    });
+    scored.truncate(query.limit.unwrap_or(DEFAULT_LIMIT));
+
+    Ok(scored
+        .into_iter()
+        .map(|(c, score, matches)| SearchResult {
+            content: c.content.masked(),
+            score,
+            matches,
+            whiteboard_item_id: c.whiteboard_item_id,
+            group_id: c.group_id,
+        })
+        .collect())
+}
+
+fn passes_filters(candidate: &Candidate, query: &SearchQuery) -> bool {
+    let content = candidate.content;
+
+    if query.category.as_ref().is_some_and(|c| *c != content.category) {
+        return false;
+    }
+    if query.from.is_some_and(|from| content.copied_at < from) {
+        return false;
+    }
+    if query.to.is_some_and(|to| content.copied_at > to) {
+        return false;
+    }
+    if let Some(source) = &query.source {
+        match &content.source {
+            Some(s) if s.eq_ignore_ascii_case(source) => {}
+            _ => return false,
+        }
+    }
+    if let Some(group) = query.group {
+        if !candidate.group_path.contains(&group) {
+            return false;
+        }
+    }
+    true
+}
+
+fn group_path(groups: &HashMap<Uuid, Group>, parent: Option<Uuid>) -> Vec<Uuid> {
+    let mut path = Vec::new();
+    let mut current = parent;
+    while let Some(id) = current {
+        // Guard against cycles in corrupted data
+        if path.contains(&id) {
+            break;
+        }
+        path.push(id);
+        current = groups.get(&id).and_then(|g| g.parent_group);
+    }
+    path
+}
+
+fn searchable_text(content: &ClipboardContent) -> Option<&str> {
+    if content.category == Category::Secure {
+        return None;
+    }
+    match &content.data {
+        ClipboardData::Files { preview, .. } => Some(preview),
+        data => data.text(),
+    }
+}
+
+enum Matcher {
+    Substring(Vec<char>),
+    Fuzzy(Box<SkimMatcherV2>, String),
+    Regex(Regex),
+}
+
+impl Matcher {
+    /// None for an empty query, which matches everything
+    fn new(query: &SearchQuery) -> Result<Option<Self>, String> {
+        if query.query.is_empty() {
+            return Ok(None);
+        }
+
+        let matcher = match query.mode {
+            SearchMode::Substring => Matcher::Substring(query.query.chars().map(fold_case).collect()),
+            SearchMode::Fuzzy => Matcher::Fuzzy(Box::default(), query.query.clone()),
+            SearchMode::Regex => Matcher::Regex(
+                Regex::new(&query.query).map_err(|e| format!("Invalid regular expression: {}", e))?,
+            ),
+        };
+        Ok(Some(matcher))
+    }
+
+    /// Score and highlight ranges, or None if the text does not match
+    fn find(&self, text: &str) -> Option<(i64, Vec<MatchRange>)> {
+        match self {
+            Matcher::Substring(needle) => {
+                let haystack: Vec<char> = text.chars().map(fold_case).collect();
+                let offsets = utf16_offsets(text);
+
+                let mut ranges = Vec::new();
+                let mut i = 0;
+                while i + needle.len() <= haystack.len() {
+                    if haystack[i..i + needle.len()] == needle[..] {
+                        ranges.push(MatchRange {
+                            start: offsets[i],
+                            end: offsets[i + needle.len()],
+                        });
+                        i += needle.len();
+                    } else {
+                        i += 1;
+                    }
+                }
+                occurrence_score(ranges)
+            }
+            Matcher::Fuzzy(matcher, pattern) => {
+                let (score, indices) = matcher.fuzzy_indices(text, pattern)?;
+                let offsets = utf16_offsets(text);
+
+                // Merge consecutive character indices into ranges
+                let mut ranges: Vec<MatchRange> = Vec::new();
+                for index in indices {
+                    let (start, end) = (offsets[index], offsets[index + 1]);
+                    match ranges.last_mut() {
+                        Some(last) if last.end == start => last.end = end,
+                        _ => ranges.push(MatchRange { start, end }),
+                    }
+                }
+                Some((score, ranges))
+            }
+            Matcher::Regex(regex) => {
+                let ranges = regex
+                    .find_iter(text)
+                    .filter(|m| !m.is_empty())
+                    .map(|m| MatchRange {
+                        start: text[..m.start()].encode_utf16().count(),
+                        end: text[..m.end()].encode_utf16().count(),
+                    })
+                    .collect();
+                occurrence_score(ranges)
+            }
+        }
+    }
+}
+
+/// More occurrences rank higher, then an earlier first occurrence
+fn occurrence_score(ranges: Vec<MatchRange>) -> Option<(i64, Vec<MatchRange>)> {
+    let first = ranges.first()?.start as i64;
+    Some((ranges.len() as i64 * 1000 - first.min(999), ranges))
+}
+
+/// Lowercase a character when that keeps it a single character, so offsets stay aligned
+fn fold_case(c: char) -> char {
+    let mut lower = c.to_lowercase();
+    match (lower.next(), lower.next()) {
+        (Some(l), None) => l,
+        _ => c,
+    }
+}
+
+/// UTF-16 offset of every char boundary, including the end of the text
+fn utf16_offsets(text: &str) -> Vec<usize> {
+    let mut offsets = Vec::with_capacity(text.len() + 1);
+    let mut offset = 0;
+    offsets.push(0);
+    for c in text.chars() {
+        offset += c.len_utf16();
+        offsets.push(offset);
+    }
+    offsets
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(s: &str) -> ClipboardContent {
+        ClipboardContent::new_text(s.to_string(), Category::Text)
+    }
+
+    fn history(contents: &[ClipboardContent]) -> Vec<Candidate<'_>> {
+        contents.iter().map(Candidate::history).collect()
+    }
+
+    fn query(q: &str, mode: SearchMode) -> SearchQuery {
+        SearchQuery {
+            query: q.to_string(),
+            mode,
+            ..Default::default()
+        }
+    }
+
+    fn texts(results: &[SearchResult]) -> Vec<&str> {
+        results.iter().filter_map(|r| r.content.data.text()).collect()
+    }
+
+    #[test]
+    fn test_substring_ranking_and_offsets() {
+        let contents = [text("say hello"), text("Hello hello"), text("goodbye")];
+        let results = rank(history(&contents), &query("hello", SearchMode::Substring)).unwrap();
+
+        assert_eq!(texts(&results), vec!["Hello hello", "say hello"]);
+        assert_eq!(
+            results[0].matches,
+            vec![MatchRange { start: 0, end: 5 }, MatchRange { start: 6, end: 11 }]
+        );
+    }
+
+    #[test]
+    fn test_offsets_are_utf16() {
+        let contents = [text("😀 クリップ")];
+        let results = rank(history(&contents), &query("クリ", SearchMode::Substring)).unwrap();
+        assert_eq!(results[0].matches, vec![MatchRange { start: 3, end: 5 }]);
+    }
+
+    #[test]
+    fn test_fuzzy_and_regex_modes() {
+        let contents = [text("clipboard manager"), text("unrelated")];
+        let results = rank(history(&contents), &query("cbm", SearchMode::Fuzzy)).unwrap();
+        assert_eq!(texts(&results), vec!["clipboard manager"]);
+        assert_eq!(results[0].matches.len(), 3);
+
+        let contents = [text("order 1234"), text("no digits")];
+        let results = rank(history(&contents), &query(r"\d+", SearchMode::Regex)).unwrap();
+        assert_eq!(texts(&results), vec!["order 1234"]);
+        assert_eq!(results[0].matches, vec![MatchRange { start: 6, end: 10 }]);
+
+        assert!(rank(Vec::new(), &query("(", SearchMode::Regex)).is_err());
+    }
+
+    #[test]
+    fn test_filters() {
+        let mut from_editor = text("note");
+        from_editor.source = Some("Code".to_string());
+        let contents = [from_editor, text("note"), text("note")];
+        let mut candidates = history(&contents);
+        let (group, subgroup) = (Uuid::new_v4(), Uuid::new_v4());
+        candidates[1].whiteboard_item_id = Some(Uuid::new_v4());
+        candidates[1].group_id = Some(subgroup);
+        candidates[1].group_path = vec![subgroup, group];
+
+        let by_source = SearchQuery {
+            source: Some("code".to_string()),
+            ..query("note", SearchMode::Substring)
+        };
+        let results = rank(candidates.clone(), &by_source).unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].content.source.as_deref(), Some("Code"));
+
+        let by_group = SearchQuery {
+            group: Some(group),
+            ..query("", SearchMode::Substring)
+        };
+        let results = rank(candidates.clone(), &by_group).unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].group_id, Some(subgroup));
+
+        let future = SearchQuery {
+            from: Some(Utc::now() + chrono::Duration::days(1)),
+            ..query("note", SearchMode::Substring)
+        };
+        assert!(rank(candidates, &future).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_secure_contents_are_masked() {
+        let secret = [ClipboardContent::new_text("hunter2".to_string(), Category::Secure)];
+        assert!(rank(history(&secret), &query("hunter", SearchMode::Substring)).unwrap().is_empty());
+
+        let by_category = SearchQuery {
+            category: Some(Category::Secure),
+            ..query("", SearchMode::Substring)
+        };
+        let results = rank(history(&secret), &by_category).unwrap();
+        assert_eq!(texts(&results), vec![""]);
+        assert!(matches!(
+            &results[0].content.data,
+            ClipboardData::Text { preview, .. } if preview == crate::types::MASKED_PREVIEW
+        ));
+    }
+}
@@ -0,0 +1,288 @@
+//! Opt-in persistence of the clipboard history with per-category retention
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+use crate::storage::volatile::{DedupeMode, HistoryLimits, DEDUPE_MODE_SETTING, HISTORY_LIMITS_SETTING};
+use crate::types::{Category, ClipboardContent};
+use crate::APP_STATE;
+
+/// Settings key holding the policy as JSON
+pub const HISTORY_POLICY_SETTING: &str = "history_policy";
+
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// How long captured items of a category are kept on disk
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "mode", content = "days", rename_all = "camelCase")]
+pub enum Retention {
+    Never,
+    Days(u32),
+    Forever,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryPolicy {
+    /// Persisting history is opt-in
+    pub enabled: bool,
+    pub text: Retention,
+    pub image: Retention,
+    pub numeric: Retention,
+    pub secure: Retention,
+    pub url: Retention,
+}
+
+impl Default for HistoryPolicy {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            text: Retention::Days(30),
+            image: Retention::Days(7),
+            numeric: Retention::Days(30),
+            secure: Retention::Never,
+            url: Retention::Days(30),
+        }
+    }
+}
+
+impl HistoryPolicy {
+    pub fn retention(&self, category: &Category) -> Retention {
+        match category {
+            Category::Text => self.text,
+            Category::Image => self.image,
+            Category::Numeric => self.numeric,
+            Category::Secure => self.secure,
+            Category::Url => self.url,
+        }
+    }
+
+    pub fn should_persist(&self, category: &Category) -> bool {
+        self.enabled && self.retention(category) != Retention::Never
+    }
+}
+
+/// Load the saved policy from storage
+pub async fn load_saved_policy() {
+    if let Some(state) = APP_STATE.get() {
+        let storage_guard = state.persistent_storage.read().await;
+        if let Some(storage) = storage_guard.as_ref() {
+            match storage.get_setting(HISTORY_POLICY_SETTING).await {
+                Ok(Some(json)) => match serde_json::from_str::<HistoryPolicy>(&json) {
+                    Ok(policy) => *state.history_policy.write().await = policy,
+                    Err(e) => eprintln!("Invalid history policy setting: {}", e),
+                },
+                Ok(None) => {}
+                Err(e) => {
+                    eprintln!("Failed to read history policy setting: {}", e);
+                }
+            }
+        }
+    }
+}
+
+/// Load the saved dedupe mode from storage
+pub async fn load_saved_dedupe_mode() {
+    if let Some(state) = APP_STATE.get() {
+        let storage_guard = state.persistent_storage.read().await;
+        if let Some(storage) = storage_guard.as_ref() {
+            match storage.get_setting(DEDUPE_MODE_SETTING).await {
+                Ok(Some(json)) => match serde_json::from_str::<DedupeMode>(&json) {
+                    Ok(mode) => state.volatile_storage.set_dedupe_mode(mode).await,
+                    Err(e) => eprintln!("Invalid dedupe mode setting: {}", e),
+                },
+                Ok(None) => {}
+                Err(e) => {
+                    eprintln!("Failed to read dedupe mode setting: {}", e);
+                }
+            }
+        }
+    }
+}
+
+/// Load the saved in-memory history limits from storage
+pub async fn load_saved_limits() {
+    if let Some(state) = APP_STATE.get() {
+        let storage_guard = state.persistent_storage.read().await;
+        if let Some(storage) = storage_guard.as_ref() {
+            match storage.get_setting(HISTORY_LIMITS_SETTING).await {
+                Ok(Some(json)) => match serde_json::from_str::<HistoryLimits>(&json) {
+                    Ok(limits) => {
+                        state.volatile_storage.set_limits(limits).await;
+                    }
+                    Err(e) => eprintln!("Invalid history limits setting: {}", e),
+                },
+                Ok(None) => {}
+                Err(e) => {
+                    eprintln!("Failed to read history limits setting: {}", e);
+                }
+            }
+        }
+    }
+}
+
+/// Count a captured item as another copy of its persisted entry, which may have
+/// been evicted from the in-memory history already
+pub async fn merge_persisted(content: &mut ClipboardContent) {
+    if let Some(state) = APP_STATE.get() {
+        if !state.history_policy.read().await.should_persist(&content.category) {
+            return;
+        }
+
+        let storage_guard = state.persistent_storage.read().await;
+        if let Some(storage) = storage_guard.as_ref() {
+            if storage.is_locked().await {
+                return;
+            }
+            if let Err(e) = storage.merge_history_entry(content).await {
+                eprintln!("Failed to look up persisted clipboard history: {}", e);
+            }
+        }
+    }
+}
+
+/// Write a captured item to disk if the policy allows it
+pub async fn persist(content: &ClipboardContent) {
+    if let Some(state) = APP_STATE.get() {
+        if !state.history_policy.read().await.should_persist(&content.category) {
+            return;
+        }
+
+        let storage_guard = state.persistent_storage.read().await;
+        if let Some(storage) = storage_guard.as_ref() {
+            // Nothing can be encrypted while locked
+            if storage.is_locked().await {
+                return;
+            }
+            match storage.save_history_entry(content).await {
+                Ok(()) => state.search_index.record(content).await,
+                Err(e) => eprintln!("Failed to persist clipboard history: {}", e),
+            }
+        }
+    }
+}
+
+/// Fill the in-memory history from disk after startup
+pub async fn restore_history() {
+    if let Some(state) = APP_STATE.get() {
+        if !state.history_policy.read().await.enabled {
+            return;
+        }
+
+        let storage_guard = state.persistent_storage.read().await;
+        if let Some(storage) = storage_guard.as_ref() {
+            // Restored once the master password is entered
+            if storage.is_locked().await {
+                return;
+            }
+            let max_entries = state.volatile_storage.limits().await.max_entries;
+            match storage.load_history(max_entries as i64).await {
+                Ok(items) => state.volatile_storage.restore(items).await,
+                Err(e) => eprintln!("Failed to load clipboard history: {}", e),
+            }
+        }
+    }
+}
+
+/// Apply the retention policy to the persisted history.
+/// With persistence disabled everything stored so far is removed.
+pub async fn run_cleanup() -> Result<u64, String> {
+    let state = APP_STATE.get().ok_or("App state not initialized")?;
+    let policy = state.history_policy.read().await.clone();
+
+    let storage_guard = state.persistent_storage.read().await;
+    let storage = match storage_guard.as_ref() {
+        Some(storage) => storage,
+        None => return Ok(0),
+    };
+
+    let now = Utc::now();
+    let mut removed = 0;
+    for category in [
+        Category::Text,
+        Category::Image,
+        Category::Numeric,
+        Category::Secure,
+        Category::Url,
+    ] {
+        let cutoff = match policy.retention(&category) {
+            _ if !policy.enabled => now,
+            Retention::Never => now,
+            Retention::Days(days) => now - chrono::Duration::days(days as i64),
+            Retention::Forever => continue,
+        };
+        removed += storage
+            .expire_history(&category, cutoff)
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+
+    if removed > 0 {
+        state.search_index.invalidate().await;
+    }
+    Ok(removed)
+}
+
+/// Delete whiteboard trash entries older than the retention period
+async fn purge_trash() {
+    if let Some(state) = APP_STATE.get() {
+        let storage_guard = state.persistent_storage.read().await;
+        if let Some(storage) = storage_guard.as_ref() {
+            match storage.purge_expired_trash().await {
+                Ok(0) => {}
+                Ok(purged) => println!("Purged {} trash entries", purged),
+                Err(e) => eprintln!("Trash purge failed: {}", e),
+            }
+        }
+    }
+}
+
+/// Run the cleanup now and then periodically
+pub fn start_cleanup_job() {
+    tauri::async_runtime::spawn(async {
+        let mut interval = tokio::time::interval(CLEANUP_INTERVAL);
+        loop {
+            interval.tick().await;
+            match run_cleanup().await {
+                Ok(0) => {}
+                Ok(removed) => println!("Removed {} expired history entries", removed),
+                Err(e) => eprintln!("History cleanup failed: {}", e),
+            }
+            purge_trash().await;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_policy_is_opt_in() {
+        let policy = HistoryPolicy::default();
+        assert!(!policy.should_persist(&Category::Text));
+
+        let policy = HistoryPolicy {
+            enabled: true,
+            ..policy
+        };
+        assert!(policy.should_persist(&Category::Text));
+        assert!(!policy.should_persist(&Category::Secure));
+    }
+
+    #[test]
+    fn test_policy_json() {
+        let policy = HistoryPolicy {
+            enabled: true,
+            image: Retention::Forever,
+            ..HistoryPolicy::default()
+        };
+        let json = serde_json::to_value(&policy).unwrap();
+        assert_eq!(json["text"], serde_json::json!({ "mode": "days", "days": 30 }));
+        assert_eq!(json["secure"], serde_json::json!({ "mode": "never" }));
+
+        let parsed: HistoryPolicy = serde_json::from_value(json).unwrap();
+        assert_eq!(parsed, policy);
+    }
+}
@@ -0,0 +1,386 @@
+//! Undo journal for the whiteboard. Each step stores the rows it changed as
+//! they were before and after, so undo and redo just write them back. While a
+//! step is recorded, triggers keep the rows it touches as they were before, so
+//! only those are compared when it ends.
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use sqlx::{Row, SqliteConnection};
+use std::collections::HashMap;
+
+use super::persistent::StorageError;
+
+/// Settings key for the number of steps kept
+pub const UNDO_DEPTH_SETTING: &str = "undo_depth";
+
+pub const DEFAULT_UNDO_DEPTH: u32 = 50;
+
+/// Tables whose rows make up the whiteboard. Contents are not journaled; they
+/// are kept as long as a step refers to them (see `JOURNALED_CONTENT_IDS`).
+const TABLES: &[&str] = &["groups", "whiteboard_items", "trash"];
+
+/// Content ids referenced by journaled whiteboard items, including those
+/// touched by the step being recorded
+pub const JOURNALED_CONTENT_IDS: &str = r#"
+    SELECT content_id FROM whiteboard_journal_contents
+    UNION
+    SELECT content_id FROM (
+        SELECT json_extract(before, '$.content_id') AS content_id FROM whiteboard_journal_touched
+    ) WHERE content_id IS NOT NULL
+"#;
+
+/// A row's columns by name
+pub type RowImage = Map<String, Value>;
+
+/// One row as it was before and after a step; None if it did not exist
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RowChange {
+    pub table: String,
+    pub id: String,
+    pub before: Option<RowImage>,
+    pub after: Option<RowImage>,
+}
+
+/// What undo and redo would do next
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UndoState {
+    /// Label of the step undo reverts
+    pub undo: Option<String>,
+    /// Label of the step redo applies again
+    pub redo: Option<String>,
+}
+
+async fn columns(conn: &mut SqliteConnection, table: &str) -> Result<Vec<String>, StorageError> {
+    let rows = sqlx::query("SELECT name FROM pragma_table_info(?)")
+        .bind(table)
+        .fetch_all(conn)
+        .await?;
+    Ok(rows.iter().map(|r| r.get("name")).collect())
+}
+
+/// Expression building a row image of `table`
+async fn image_of(conn: &mut SqliteConnection, table: &str) -> Result<String, StorageError> {
+    let fields: Vec<String> = columns(conn, table)
+        .await?
+        .iter()
+        .map(|column| format!("'{0}', {0}", column))
+        .collect();
+    Ok(format!("json_object({})", fields.join(", ")))
+}
+
+/// Install the triggers recording touched rows, built from the current
+/// columns, and drop a step left open by a crash
+pub async fn init(conn: &mut SqliteConnection) -> Result<(), StorageError> {
+    for table in TABLES {
+        let image = image_of(conn, table).await?;
+        for (event, key) in [("INSERT", "NEW"), ("UPDATE", "OLD"), ("DELETE", "OLD")] {
+            let name = format!("journal_{}_{}", table, event.to_lowercase());
+            sqlx::query(&format!("DROP TRIGGER IF EXISTS {}", name))
+                .execute(&mut *conn)
+                .await?;
+            sqlx::query(&format!(
+                r#"
+                CREATE TRIGGER {name} BEFORE {event} ON {table}
+                WHEN EXISTS (SELECT 1 FROM whiteboard_journal_step)
+                BEGIN
+                    INSERT OR IGNORE INTO whiteboard_journal_touched (tbl, id, before)
+                    VALUES ('{table}', {key}.id, (SELECT {image} FROM {table} WHERE id = {key}.id));
+                END
+                "#
+            ))
+            .execute(&mut *conn)
+            .await?;
+        }
+    }
+    finish(conn).await?;
+    Ok(())
+}
+
+/// Start recording the rows touched from now on
+pub async fn begin(conn: &mut SqliteConnection) -> Result<(), StorageError> {
+    sqlx::query("DELETE FROM whiteboard_journal_touched")
+        .execute(&mut *conn)
+        .await?;
+    sqlx::query("INSERT OR IGNORE INTO whiteboard_journal_step (id) VALUES (1)")
+        .execute(&mut *conn)
+        .await?;
+    Ok(())
+}
+
+/// Stop recording, returning the touched rows that changed
+pub async fn finish(conn: &mut SqliteConnection) -> Result<Vec<RowChange>, StorageError> {
+    sqlx::query("DELETE FROM whiteboard_journal_step")
+        .execute(&mut *conn)
+        .await?;
+    let rows = sqlx::query("SELECT tbl, id, before FROM whiteboard_journal_touched ORDER BY tbl, id")
+        .fetch_all(&mut *conn)
+        .await?;
+
+    let mut images = HashMap::new();
+    let mut changes = Vec::new();
+    for row in rows {
+        let Some(table) = TABLES.iter().find(|t| **t == row.get::<&str, _>("tbl")) else {
+            continue;
+        };
+        if !images.contains_key(table) {
+            images.insert(*table, image_of(conn, table).await?);
+        }
+        let id: String = row.get("id");
+        let after = sqlx::query(&format!("SELECT {} AS image FROM {} WHERE id = ?", images[table], table))
+            .bind(&id)
+            .fetch_optional(&mut *conn)
+            .await?
+            .map(|r| serde_json::from_str::<RowImage>(r.get("image")))
+            .transpose()?;
+        let before = row
+            .get::<Option<&str>, _>("before")
+            .map(serde_json::from_str::<RowImage>)
+            .transpose()?;
+        if before != after {
+            changes.push(RowChange {
+                table: table.to_string(),
+                id,
+                before,
+                after,
+            });
+        }
+    }
+
+    sqlx::query("DELETE FROM whiteboard_journal_touched")
+        .execute(&mut *conn)
+        .await?;
+    Ok(changes)
+}
+
+/// Content ids the rows of a step refer to
+pub fn content_ids(changes: &[RowChange]) -> Vec<String> {
+    changes
+        .iter()
+        .flat_map(|change| [&change.before, &change.after])
+        .flatten()
+        .filter_map(|image| image.get("content_id")?.as_str().map(str::to_string))
+        .collect()
+}
+
+/// Put a row into the given state
+async fn write_row(
+    conn: &mut SqliteConnection,
+    table: &str,
+    id: &str,
+    image: Option<&RowImage>,
+) -> Result<(), StorageError> {
+    let Some(table) = TABLES.iter().find(|t| **t == table) else {
+        return Ok(());
+    };
+    let Some(image) = image else {
+        sqlx::query(&format!("DELETE FROM {} WHERE id = ?", table))
+            .bind(id)
+            .execute(conn)
+            .await?;
+        return Ok(());
+    };
+
+    // Columns dropped since the step was recorded are skipped
+    let existing = columns(conn, table).await?;
+    let fields: Vec<(&String, &Value)> = image.iter().filter(|(k, _)| existing.contains(k)).collect();
+    let names: Vec<&str> = fields.iter().map(|(k, _)| k.as_str()).collect();
+    let statement = format!(
+        "INSERT INTO {table} ({}) VALUES ({}) ON CONFLICT(id) DO UPDATE SET {}",
+        names.join(", "),
+        vec!["?"; names.len()].join(", "),
+        names
+            .iter()
+            .map(|name| format!("{0} = excluded.{0}", name))
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+
+    let mut query = sqlx::query(&statement);
+    for (_, value) in fields {
+        query = match value {
+            Value::Null => query.bind(None::<String>),
+            Value::Bool(b) => query.bind(*b as i64),
+            Value::Number(n) => match n.as_i64() {
+                Some(i) => query.bind(i),
+                None => query.bind(n.as_f64()),
+            },
+            Value::String(s) => query.bind(s.clone()),
+            other => query.bind(other.to_string()),
+        };
+    }
+    query.execute(conn).await?;
+    Ok(())
+}
+
+/// Store a step, dropping undone steps and the oldest beyond `depth`.
+/// Returns the content ids of the dropped steps.
+pub async fn record(
+    conn: &mut SqliteConnection,
+    label: &str,
+    changes: &[RowChange],
+    depth: u32,
+) -> Result<Vec<String>, StorageError> {
+    let mut dropped = take_steps(conn, "undone = 1").await?;
+
+    let seq = sqlx::query("INSERT INTO whiteboard_journal (label, changes, created_at) VALUES (?, ?, ?)")
+        .bind(label)
+        .bind(serde_json::to_string(changes)?)
+        .bind(chrono::Utc::now().to_rfc3339())
+        .execute(&mut *conn)
+        .await?
+        .last_insert_rowid();
+    for content_id in content_ids(changes) {
+        sqlx::query("INSERT OR IGNORE INTO whiteboard_journal_contents (seq, content_id) VALUES (?, ?)")
+            .bind(seq)
+            .bind(content_id)
+            .execute(&mut *conn)
+            .await?;
+    }
+
+    dropped.extend(trim(conn, depth).await?);
+    Ok(dropped)
+}
+
+/// Drop the oldest steps beyond `depth`, returning their content ids
+pub async fn trim(conn: &mut SqliteConnection, depth: u32) -> Result<Vec<String>, StorageError> {
+    take_steps(
+        conn,
+        &format!(
+            "seq NOT IN (SELECT seq FROM whiteboard_journal ORDER BY seq DESC LIMIT {})",
+            depth
+        ),
+    )
+    .await
+}
+
+async fn take_steps(conn: &mut SqliteConnection, condition: &str) -> Result<Vec<String>, StorageError> {
+    let rows = sqlx::query(&format!("SELECT changes FROM whiteboard_journal WHERE {}", condition))
+        .fetch_all(&mut *conn)
+        .await?;
+    let mut ids = Vec::new();
+    for row in rows {
+        let changes: Vec<RowChange> = serde_json::from_str(row.get("changes"))?;
+        ids.extend(content_ids(&changes));
+    }
+    sqlx::query(&format!("DELETE FROM whiteboard_journal WHERE {}", condition))
+        .execute(&mut *conn)
+        .await?;
+    sqlx::query("DELETE FROM whiteboard_journal_contents WHERE seq NOT IN (SELECT seq FROM whiteboard_journal)")
+        .execute(&mut *conn)
+        .await?;
+    Ok(ids)
+}
+
+/// Revert the latest step. Returns false if there is nothing to undo.
+pub async fn undo(conn: &mut SqliteConnection) -> Result<bool, StorageError> {
+    let row = sqlx::query("SELECT seq, changes FROM whiteboard_journal WHERE undone = 0 ORDER BY seq DESC LIMIT 1")
+        .fetch_optional(&mut *conn)
+        .await?;
+    let Some(row) = row else {
+        return Ok(false);
+    };
+    let changes: Vec<RowChange> = serde_json::from_str(row.get("changes"))?;
+
+    // Rows may briefly point at groups that are written later
+    sqlx::query("PRAGMA defer_foreign_keys = ON").execute(&mut *conn).await?;
+    for change in changes.iter().rev() {
+        write_row(conn, &change.table, &change.id, change.before.as_ref()).await?;
+    }
+    sqlx::query("UPDATE whiteboard_journal SET undone = 1 WHERE seq = ?")
+        .bind(row.get::<i64, _>("seq"))
+        .execute(&mut *conn)
+        .await?;
+    Ok(true)
+}
+
+/// Apply the earliest undone step again. Returns false if there is nothing to redo.
+pub async fn redo(conn: &mut SqliteConnection) -> Result<bool, StorageError> {
+    let row = sqlx::query("SELECT seq, changes FROM whiteboard_journal WHERE undone = 1 ORDER BY seq LIMIT 1")
+        .fetch_optional(&mut *conn)
+        .await?;
+    let Some(row) = row else {
+        return Ok(false);
+    };
+    let changes: Vec<RowChange> = serde_json::from_str(row.get("changes"))?;
+
+    sqlx::query("PRAGMA defer_foreign_keys = ON").execute(&mut *conn).await?;
+    for change in &changes {
+        write_row(conn, &change.table, &change.id, change.after.as_ref()).await?;
+    }
+    sqlx::query("UPDATE whiteboard_journal SET undone = 0 WHERE seq = ?")
+        .bind(row.get::<i64, _>("seq"))
+        .execute(&mut *conn)
+        .await?;
+    Ok(true)
+}
+
+pub async fn state(conn: &mut SqliteConnection) -> Result<UndoState, StorageError> {
+    let undo = sqlx::query("SELECT label FROM whiteboard_journal WHERE undone = 0 ORDER BY seq DESC LIMIT 1")
+        .fetch_optional(&mut *conn)
+        .await?;
+    let redo = sqlx::query("SELECT label FROM whiteboard_journal WHERE undone = 1 ORDER BY seq LIMIT 1")
+        .fetch_optional(&mut *conn)
+        .await?;
+    Ok(UndoState {
+        undo: undo.map(|r| r.get("label")),
+        redo: redo.map(|r| r.get("label")),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(pairs: &[(&str, Value)]) -> RowImage {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.clone())).collect()
+    }
+
+    #[test]
+    fn test_content_ids() {
+        let item = |x: f64| image(&[("id", "i".into()), ("content_id", "c".into()), ("position_x", x.into())]);
+        let changes = vec![
+            RowChange { table: "trash".into(), id: "t".into(), before: None, after: Some(image(&[("id", "t".into())])) },
+            RowChange { table: "whiteboard_items".into(), id: "i".into(), before: Some(item(1.0)), after: Some(item(2.0)) },
+        ];
+        assert_eq!(content_ids(&changes), vec!["c", "c"]);
+    }
+
+    #[tokio::test]
+    async fn test_only_touched_rows_are_recorded() {
+        let pool = sqlx::sqlite::SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        super::super::migrations::run(&pool).await.unwrap();
+        let mut conn = pool.acquire().await.unwrap();
+        init(&mut conn).await.unwrap();
+
+        let insert = "INSERT OR REPLACE INTO groups (id, name, position_x, position_y, created_at, updated_at) VALUES (?, ?, 0, 0, '', '')";
+        for (id, name) in [("a", "A"), ("b", "B"), ("c", "C")] {
+            sqlx::query(insert).bind(id).bind(name).execute(&mut *conn).await.unwrap();
+        }
+
+        begin(&mut conn).await.unwrap();
+        // Replaced, renamed back and forth, added and removed again, added
+        sqlx::query(insert).bind("a").bind("A2").execute(&mut *conn).await.unwrap();
+        sqlx::query("UPDATE groups SET name = 'X' WHERE id = 'b'").execute(&mut *conn).await.unwrap();
+        sqlx::query("UPDATE groups SET name = 'B' WHERE id = 'b'").execute(&mut *conn).await.unwrap();
+        sqlx::query(insert).bind("d").bind("D").execute(&mut *conn).await.unwrap();
+        sqlx::query("DELETE FROM groups WHERE id = 'd'").execute(&mut *conn).await.unwrap();
+        sqlx::query(insert).bind("e").bind("E").execute(&mut *conn).await.unwrap();
+        let changes = finish(&mut conn).await.unwrap();
+
+        let ids: Vec<&str> = changes.iter().map(|c| c.id.as_str()).collect();
+        assert_eq!(ids, vec!["a", "e"]);
+        assert_eq!(changes[0].before.as_ref().unwrap()["name"], "A");
+        assert_eq!(changes[0].after.as_ref().unwrap()["name"], "A2");
+        assert_eq!(changes[1].before, None);
+
+        // Nothing is recorded outside a step
+        sqlx::query("DELETE FROM groups").execute(&mut *conn).await.unwrap();
+        begin(&mut conn).await.unwrap();
+        assert!(finish(&mut conn).await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,537 @@
+//! Numbered schema migrations, recorded in the `schema_version` table
+
+use chrono::Utc;
+use sqlx::{Pool, Row, Sqlite, SqliteConnection};
+
+use super::persistent::StorageError;
+
+/// One step of a migration
+pub enum Step {
+    Sql(&'static str),
+    /// Add a column unless the table already has it (databases from before versioning)
+    AddColumn {
+        table: &'static str,
+        column: &'static str,
+        definition: &'static str,
+    },
+}
+
+pub struct Migration {
+    pub version: i64,
+    pub description: &'static str,
+    pub steps: &'static [Step],
+}
+
+/// Migrations in order. Never edit a released one; append a new version instead.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "baseline schema",
+        steps: BASELINE,
+    },
+    Migration {
+        version: 2,
+        description: "master password key mode",
+        steps: &[Step::Sql(
+            "ALTER TABLE encryption_config ADD COLUMN key_mode TEXT NOT NULL DEFAULT 'machine'",
+        )],
+    },
+    Migration {
+        version: 3,
+        description: "stored key derivation parameters",
+        // Defaults are the parameters every existing key was derived with
+        steps: &[
+            Step::Sql("ALTER TABLE encryption_config ADD COLUMN kdf_memory_kib INTEGER NOT NULL DEFAULT 65536"),
+            Step::Sql("ALTER TABLE encryption_config ADD COLUMN kdf_iterations INTEGER NOT NULL DEFAULT 3"),
+            Step::Sql("ALTER TABLE encryption_config ADD COLUMN kdf_parallelism INTEGER NOT NULL DEFAULT 4"),
+        ],
+    },
+    Migration {
+        version: 4,
+        description: "trash for deleted groups and items",
+        steps: &[
+            // Groups and items left pointing at groups deleted before
+            Step::Sql("UPDATE whiteboard_items SET parent_group_id = NULL WHERE parent_group_id NOT IN (SELECT id FROM groups)"),
+            Step::Sql("UPDATE groups SET parent_group_id = NULL WHERE parent_group_id NOT IN (SELECT id FROM groups)"),
+            // One entry per deletion; the deleted rows point at it with trash_id
+            Step::Sql(
+                r#"
+                CREATE TABLE trash (
+                    id TEXT PRIMARY KEY,
+                    kind TEXT NOT NULL,
+                    root_id TEXT NOT NULL,
+                    deleted_at TEXT NOT NULL
+                )
+                "#,
+            ),
+            Step::Sql("ALTER TABLE whiteboard_items ADD COLUMN trash_id TEXT"),
+            Step::Sql("ALTER TABLE groups ADD COLUMN trash_id TEXT"),
+            Step::Sql("CREATE INDEX idx_whiteboard_items_trash ON whiteboard_items(trash_id)"),
+            Step::Sql("CREATE INDEX idx_groups_trash ON groups(trash_id)"),
+        ],
+    },
+    Migration {
+        version: 5,
+        description: "undo journal for the whiteboard",
+        steps: &[Step::Sql(
+            r#"
+            CREATE TABLE whiteboard_journal (
+                seq INTEGER PRIMARY KEY AUTOINCREMENT,
+                label TEXT NOT NULL,
+                changes TEXT NOT NULL,
+                undone INTEGER NOT NULL DEFAULT 0,
+                created_at TEXT NOT NULL
+            )
+            "#,
+        )],
+    },
+    Migration {
+        version: 6,
+        description: "whiteboard snapshots",
+        steps: &[
+            // label and data are encrypted
+            Step::Sql(
+                r#"
+                CREATE TABLE whiteboard_snapshots (
+                    id TEXT PRIMARY KEY,
+                    label TEXT,
+                    automatic INTEGER NOT NULL,
+                    fingerprint TEXT NOT NULL,
+                    group_count INTEGER NOT NULL,
+                    item_count INTEGER NOT NULL,
+                    data TEXT NOT NULL,
+                    created_at TEXT NOT NULL
+                )
+                "#,
+            ),
+            // Contents a snapshot's items point at, kept while it exists
+            Step::Sql(
+                r#"
+                CREATE TABLE snapshot_contents (
+                    snapshot_id TEXT NOT NULL,
+                    content_id TEXT NOT NULL,
+                    PRIMARY KEY (snapshot_id, content_id)
+                )
+                "#,
+            ),
+            Step::Sql("CREATE INDEX idx_snapshot_contents_content ON snapshot_contents(content_id)"),
+        ],
+    },
+    Migration {
+        version: 7,
+        description: "keyed blob hashes",
+        // Existing blobs are addressed by a plain SHA-256; they are re-addressed
+        // once the key is available (PersistentStorage::key_blob_hashes)
+        steps: &[Step::Sql("ALTER TABLE blobs ADD COLUMN keyed INTEGER NOT NULL DEFAULT 0")],
+    },
+    Migration {
+        version: 8,
+        description: "keyed content hashes",
+        // Plain hashes are dropped; keyed ones are backfilled once the key is
+        // available (PersistentStorage::backfill_content_hashes)
+        steps: &[Step::Sql("UPDATE clipboard_contents SET content_hash = NULL")],
+    },
+    Migration {
+        version: 9,
+        description: "journal rows touched by a step",
+        steps: &[
+            // Has a row while an undo step is recorded
+            Step::Sql("CREATE TABLE whiteboard_journal_step (id INTEGER PRIMARY KEY CHECK (id = 1))"),
+            // Rows the step touched, as they were before it (filled by triggers,
+            // see journal::init)
+            Step::Sql(
+                r#"
+                CREATE TABLE whiteboard_journal_touched (
+                    tbl TEXT NOT NULL,
+                    id TEXT NOT NULL,
+                    before TEXT,
+                    PRIMARY KEY (tbl, id)
+                )
+                "#,
+            ),
+            // Contents the rows of a step point at, kept while it exists
+            Step::Sql(
+                r#"
+                CREATE TABLE whiteboard_journal_contents (
+                    seq INTEGER NOT NULL,
+                    content_id TEXT NOT NULL,
+                    PRIMARY KEY (seq, content_id)
+                )
+                "#,
+            ),
+            Step::Sql("CREATE INDEX idx_whiteboard_journal_contents_content ON whiteboard_journal_contents(content_id)"),
+            Step::Sql(
+                r#"
+                INSERT OR IGNORE INTO whiteboard_journal_contents (seq, content_id)
+                SELECT seq, content_id FROM (
+                    SELECT j.seq, json_extract(c.value, '$.before.content_id') AS content_id
+                    FROM whiteboard_journal j, json_each(j.changes) c
+                    UNION
+                    SELECT j.seq, json_extract(c.value, '$.after.content_id')
+                    FROM whiteboard_journal j, json_each(j.changes) c
+                ) WHERE content_id IS NOT NULL
+                "#,
+            ),
+        ],
+    },
+    Migration {
+        version: 10,
+        description: "plain content hashes",
+        // Only Secure contents keep a keyed hash; the others are backfilled with
+        // the plain one (PersistentStorage::backfill_content_hashes)
+        steps: &[Step::Sql("UPDATE clipboard_contents SET content_hash = NULL WHERE category != 'secure'")],
+    },
+];
+
+/// Schema version this build writes
+pub const CURRENT_SCHEMA_VERSION: i64 = MIGRATIONS[MIGRATIONS.len() - 1].version;
+
+/// The schema as it was before versioning. Databases created back then have the
+/// tables but may miss columns that were added over time.
+const BASELINE: &[Step] = &[
+    Step::Sql(
+        r#"
+        CREATE TABLE IF NOT EXISTS clipboard_contents (
+            id TEXT PRIMARY KEY,
+            category TEXT NOT NULL,
+            data_type TEXT NOT NULL,
+            text_content TEXT,
+            text_preview TEXT,
+            image_base64 TEXT,
+            image_width INTEGER,
+            image_height INTEGER,
+            image_format TEXT,
+            source TEXT,
+            copied_at TEXT NOT NULL,
+            is_encrypted INTEGER DEFAULT 0
+        )
+        "#,
+    ),
+    Step::Sql(
+        r#"
+        CREATE TABLE IF NOT EXISTS whiteboard_items (
+            id TEXT PRIMARY KEY,
+            content_id TEXT NOT NULL,
+            position_x REAL NOT NULL,
+            position_y REAL NOT NULL,
+            width REAL NOT NULL DEFAULT 200,
+            height REAL NOT NULL DEFAULT 150,
+            parent_group_id TEXT,
+            shortcut TEXT,
+            created_at TEXT NOT NULL,
+            updated_at TEXT NOT NULL,
+            FOREIGN KEY (content_id) REFERENCES clipboard_contents(id) ON DELETE CASCADE
+        )
+        "#,
+    ),
+    Step::Sql(
+        r#"
+        CREATE TABLE IF NOT EXISTS groups (
+            id TEXT PRIMARY KEY,
+            name TEXT NOT NULL,
+            position_x REAL NOT NULL,
+            position_y REAL NOT NULL,
+            collapsed INTEGER NOT NULL DEFAULT 0,
+            parent_group_id TEXT,
+            shortcut TEXT,
+            created_at TEXT NOT NULL,
+            updated_at TEXT NOT NULL,
+            FOREIGN KEY (parent_group_id) REFERENCES groups(id) ON DELETE SET NULL
+        )
+        "#,
+    ),
+    Step::Sql(
+        r#"
+        CREATE TABLE IF NOT EXISTS encryption_config (
+            id INTEGER PRIMARY KEY CHECK (id = 1),
+            salt BLOB NOT NULL,
+            verification_hash TEXT NOT NULL,
+            created_at TEXT NOT NULL
+        )
+        "#,
+    ),
+    // Content-addressed binary payloads (encrypted), shared between contents
+    Step::Sql(
+        r#"
+        CREATE TABLE IF NOT EXISTS blobs (
+            hash TEXT PRIMARY KEY,
+            data BLOB NOT NULL,
+            size INTEGER NOT NULL,
+            created_at TEXT NOT NULL
+        )
+        "#,
+    ),
+    Step::Sql(
+        r#"
+        CREATE TABLE IF NOT EXISTS settings (
+            key TEXT PRIMARY KEY,
+            value TEXT NOT NULL
+        )
+        "#,
+    ),
+    Step::AddColumn {
+        table: "groups",
+        column: "color",
+        definition: "TEXT",
+    },
+    Step::AddColumn {
+        table: "whiteboard_items",
+        column: "label",
+        definition: "TEXT",
+    },
+    // HTML, RTF or uri-list payload
+    Step::AddColumn {
+        table: "clipboard_contents",
+        column: "rich_content",
+        definition: "TEXT",
+    },
+    Step::AddColumn {
+        table: "clipboard_contents",
+        column: "source_title",
+        definition: "TEXT",
+    },
+    // Downscaled copies of images
+    Step::AddColumn {
+        table: "clipboard_contents",
+        column: "image_thumbnail",
+        definition: "TEXT",
+    },
+    Step::AddColumn {
+        table: "clipboard_contents",
+        column: "image_preview",
+        definition: "TEXT",
+    },
+    // blobs.hash of the original image
+    Step::AddColumn {
+        table: "clipboard_contents",
+        column: "image_blob",
+        definition: "TEXT",
+    },
+    // Re-copies of a history entry
+    Step::AddColumn {
+        table: "clipboard_contents",
+        column: "copy_count",
+        definition: "INTEGER NOT NULL DEFAULT 1",
+    },
+    Step::AddColumn {
+        table: "clipboard_contents",
+        column: "last_copied_at",
+        definition: "TEXT",
+    },
+    // SHA-256, see ClipboardContent::content_hash
+    Step::AddColumn {
+        table: "clipboard_contents",
+        column: "content_hash",
+        definition: "TEXT",
+    },
+    // Row is part of the persisted clipboard history
+    Step::AddColumn {
+        table: "clipboard_contents",
+        column: "in_history",
+        definition: "INTEGER NOT NULL DEFAULT 0",
+    },
+    Step::Sql(
+        "CREATE INDEX IF NOT EXISTS idx_whiteboard_items_parent ON whiteboard_items(parent_group_id)",
+    ),
+    Step::Sql("CREATE INDEX IF NOT EXISTS idx_groups_parent ON groups(parent_group_id)"),
+    Step::Sql(
+        "CREATE INDEX IF NOT EXISTS idx_clipboard_contents_copied_at ON clipboard_contents(copied_at)",
+    ),
+    Step::Sql(
+        "CREATE INDEX IF NOT EXISTS idx_clipboard_contents_hash ON clipboard_contents(content_hash)",
+    ),
+    // Default global shortcut
+    Step::Sql("INSERT OR IGNORE INTO settings (key, value) VALUES ('global_shortcut', 'Alt+V')"),
+];
+
+/// Bring the database up to the current schema version
+pub async fn run(pool: &Pool<Sqlite>) -> Result<(), StorageError> {
+    run_migrations(pool, MIGRATIONS).await
+}
+
+async fn run_migrations(pool: &Pool<Sqlite>, migrations: &[Migration]) -> Result<(), StorageError> {
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS schema_version (
+            version INTEGER PRIMARY KEY,
+            description TEXT NOT NULL,
+            applied_at TEXT NOT NULL
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    let current = schema_version(pool).await?;
+    let supported = migrations.last().map_or(0, |m| m.version);
+    if current > supported {
+        return Err(StorageError::SchemaTooNew {
+            found: current,
+            supported,
+        });
+    }
+
+    for migration in migrations.iter().filter(|m| m.version > current) {
+        let mut tx = pool.begin().await?;
+        apply(&mut tx, migration)
+            .await
+            .map_err(|source| StorageError::Migration {
+                version: migration.version,
+                source,
+            })?;
+        tx.commit().await?;
+        println!(
+            "Applied schema migration {} ({})",
+            migration.version, migration.description
+        );
+    }
+
+    Ok(())
+}
+
+/// Highest applied version, 0 for a new or pre-versioning database
+pub async fn schema_version(pool: &Pool<Sqlite>) -> Result<i64, StorageError> {
+    let row = sqlx::query("SELECT COALESCE(MAX(version), 0) AS version FROM schema_version")
+        .fetch_one(pool)
+        .await?;
+    Ok(row.get("version"))
+}
+
+async fn apply(conn: &mut SqliteConnection, migration: &Migration) -> Result<(), sqlx::Error> {
+    for step in migration.steps {
+        match step {
+            Step::Sql(sql) => {
+                sqlx::query(sql).execute(&mut *conn).await?;
+            }
+            Step::AddColumn {
+                table,
+                column,
+                definition,
+            } => {
+                let exists = sqlx::query(&format!("SELECT 1 FROM pragma_table_info('{}') WHERE name = ?", table))
+                    .bind(column)
+                    .fetch_optional(&mut *conn)
+                    .await?
+                    .is_some();
+                if !exists {
+                    sqlx::query(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition))
+                        .execute(&mut *conn)
+                        .await?;
+                }
+            }
+        }
+    }
+
+    sqlx::query("INSERT INTO schema_version (version, description, applied_at) VALUES (?, ?, ?)")
+        .bind(migration.version)
+        .bind(migration.description)
+        .bind(Utc::now().to_rfc3339())
+        .execute(&mut *conn)
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    async fn memory_pool() -> Pool<Sqlite> {
+        SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap()
+    }
+
+    async fn columns(pool: &Pool<Sqlite>, table: &str) -> Vec<String> {
+        sqlx::query(&format!("SELECT name FROM pragma_table_info('{}')", table))
+            .fetch_all(pool)
+            .await
+            .unwrap()
+            .iter()
+            .map(|row| row.get("name"))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_fresh_database_gets_current_version() {
+        let pool = memory_pool().await;
+        run(&pool).await.unwrap();
+        assert_eq!(schema_version(&pool).await.unwrap(), CURRENT_SCHEMA_VERSION);
+
+        // Running again is a no-op
+        run(&pool).await.unwrap();
+        assert_eq!(schema_version(&pool).await.unwrap(), CURRENT_SCHEMA_VERSION);
+    }
+
+    #[tokio::test]
+    async fn test_unversioned_database_is_adopted() {
+        let pool = memory_pool().await;
+        sqlx::query(
+            "CREATE TABLE clipboard_contents (id TEXT PRIMARY KEY, category TEXT NOT NULL, data_type TEXT NOT NULL, copied_at TEXT NOT NULL, source_title TEXT)",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        sqlx::query("INSERT INTO clipboard_contents (id, category, data_type, copied_at) VALUES ('a', 'text', 'text', '2024-01-01T00:00:00Z')")
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        run(&pool).await.unwrap();
+
+        let columns = columns(&pool, "clipboard_contents").await;
+        assert!(columns.iter().any(|c| c == "content_hash"));
+        assert!(columns.iter().any(|c| c == "in_history"));
+        let count: i64 = sqlx::query("SELECT COUNT(*) AS n FROM clipboard_contents")
+            .fetch_one(&pool)
+            .await
+            .unwrap()
+            .get("n");
+        assert_eq!(count, 1);
+    }
+
+    #[tokio::test]
+    async fn test_newer_database_is_refused() {
+        let pool = memory_pool().await;
+        run(&pool).await.unwrap();
+        sqlx::query("INSERT INTO schema_version (version, description, applied_at) VALUES (?, 'future', '')")
+            .bind(CURRENT_SCHEMA_VERSION + 1)
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            run(&pool).await,
+            Err(StorageError::SchemaTooNew { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_failed_migration_is_rolled_back() {
+        const BROKEN: &[Migration] = &[
+            Migration {
+                version: 1,
+                description: "baseline schema",
+                steps: BASELINE,
+            },
+            Migration {
+                version: 2,
+                description: "broken",
+                steps: &[
+                    Step::Sql("CREATE TABLE half_done (id INTEGER)"),
+                    Step::Sql("ALTER TABLE missing_table ADD COLUMN x TEXT"),
+                ],
+            },
+        ];
+
+        let pool = memory_pool().await;
+        assert!(matches!(
+            run_migrations(&pool, BROKEN).await,
+            Err(StorageError::Migration { version: 2, .. })
+        ));
+        assert_eq!(schema_version(&pool).await.unwrap(), 1);
+        assert!(columns(&pool, "half_done").await.is_empty());
+    }
+}
@@ -1,2 +1,7 @@
+pub mod history;
+pub mod journal;
+pub mod migrations;
 pub mod persistent;
+pub mod snapshot;
 pub mod volatile;
+pub mod whiteboard_cache;
@@ -1,10 +1,21 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::{DateTime, Utc};
 use directories::ProjectDirs;
-use sqlx::{sqlite::SqlitePoolOptions, Pool, Row, Sqlite};
+use sqlx::{
+    sqlite::{SqlitePoolOptions, SqliteRow},
+    Pool, Row, Sqlite, SqliteConnection,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 use std::path::PathBuf;
 use thiserror::Error;
+use tokio::sync::{RwLock, RwLockReadGuard};
 use uuid::Uuid;
+use zeroize::Zeroizing;
 
-use crate::crypto::SecureStore;
+use super::journal::{self, UndoState, DEFAULT_UNDO_DEPTH, JOURNALED_CONTENT_IDS, UNDO_DEPTH_SETTING};
+use super::snapshot::{SnapshotData, SnapshotInfo, SnapshotPolicy, SNAPSHOT_CONTENT_IDS, SNAPSHOT_POLICY_SETTING};
+use crate::crypto::{KdfParams, SecureStore};
 use crate::types::{
     Category, ClipboardContent, ClipboardData, Group, Position, Size, WhiteboardItem,
     WhiteboardState,
@@ -22,13 +33,183 @@ pub enum StorageError {
     Serialization(#[from] serde_json::Error),
     #[error("Crypto error: {0}")]
     Crypto(#[from] crate::crypto::secure_store::CryptoError),
+    #[error("Database schema version {found} is newer than supported version {supported}; please update Clitter")]
+    SchemaTooNew { found: i64, supported: i64 },
+    #[error("Schema migration {version} failed: {source}")]
+    Migration { version: i64, source: sqlx::Error },
+    #[error("Locked")]
+    Locked,
+    #[error("Wrong password")]
+    WrongPassword,
+    #[error("Group not found: {0}")]
+    GroupNotFound(Uuid),
+    #[error("A group cannot be moved into itself or one of its subgroups")]
+    GroupCycle,
+}
+
+/// Plaintext encrypted into `encryption_config.verification_hash` to check a key
+const VERIFICATION_TEXT: &str = "clitter_verification";
+
+/// Where the database key comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum KeyMode {
+    /// Derived from the user and host name, no password needed
+    Machine,
+    /// Derived from a master password, locked until it is entered
+    Password,
+}
+
+impl KeyMode {
+    fn as_str(&self) -> &'static str {
+        match self {
+            KeyMode::Machine => "machine",
+            KeyMode::Password => "password",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LockState {
+    pub mode: KeyMode,
+    pub locked: bool,
+}
+
+/// Values re-encrypted so far during a key change
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RekeyProgress {
+    pub done: usize,
+    pub total: usize,
+}
+
+/// What happens to what a deleted group contains
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DeleteMode {
+    /// Delete child groups and items for good
+    Cascade,
+    /// Move child groups and items up to the group's parent
+    Reparent,
+    /// Move the group with everything in it to the trash
+    Trash,
+}
+
+/// Fields of a whiteboard item to change; None leaves a field as it is
+#[derive(Debug, Clone, Default)]
+pub struct ItemChanges {
+    pub position: Option<Position>,
+    pub size: Option<Size>,
+    pub parent_group: Option<Option<Uuid>>,
+    pub shortcut: Option<Option<String>>,
+    pub label: Option<Option<String>>,
+}
+
+/// Fields of a group to change; None leaves a field as it is
+#[derive(Debug, Clone, Default)]
+pub struct GroupChanges {
+    pub name: Option<String>,
+    pub position: Option<Position>,
+    pub collapsed: Option<bool>,
+    pub shortcut: Option<Option<String>>,
+    pub color: Option<Option<String>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum TrashKind {
+    Item,
+    Group,
+}
+
+impl TrashKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            TrashKind::Item => "item",
+            TrashKind::Group => "group",
+        }
+    }
+}
+
+/// A deleted item, or a group with its contents, that can still be restored
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrashEntry {
+    pub id: Uuid,
+    pub kind: TrashKind,
+    /// Id of the deleted item or group
+    pub root_id: Uuid,
+    /// Group name or item label
+    pub name: Option<String>,
+    pub item_count: i64,
+    pub group_count: i64,
+    pub deleted_at: DateTime<Utc>,
+}
+
+/// Settings key holding the number of days deleted things stay in the trash
+pub const TRASH_RETENTION_SETTING: &str = "trash_retention_days";
+
+pub const DEFAULT_TRASH_RETENTION_DAYS: u32 = 30;
+
+/// Contents of the `encryption_config` row
+struct KeyConfig {
+    salt: [u8; 32],
+    verification_hash: String,
+    kdf: KdfParams,
+}
+
+impl KeyConfig {
+    fn from_row(row: &SqliteRow) -> Result<Self, StorageError> {
+        let salt: Vec<u8> = row.get("salt");
+        let salt = salt
+            .try_into()
+            .map_err(|_| crate::crypto::secure_store::CryptoError::InvalidData)?;
+        Ok(Self {
+            salt,
+            verification_hash: row.get("verification_hash"),
+            kdf: KdfParams {
+                memory_kib: row.get::<i64, _>("kdf_memory_kib") as u32,
+                iterations: row.get::<i64, _>("kdf_iterations") as u32,
+                parallelism: row.get::<i64, _>("kdf_parallelism") as u32,
+            },
+        })
+    }
 }
 
+const KEY_CONFIG_COLUMNS: &str =
+    "salt, verification_hash, key_mode, kdf_memory_kib, kdf_iterations, kdf_parallelism";
+
 pub struct PersistentStorage {
     pool: Pool<Sqlite>,
     #[allow(dead_code)]
     data_dir: PathBuf,
-    crypto: SecureStore,
+    /// None while locked. Read while data is encrypted or decrypted, written
+    /// while the key is replaced.
+    crypto: RwLock<Option<SecureStore>>,
+    /// Held while an undo step is recorded, undone or redone
+    journal: tokio::sync::Mutex<()>,
+}
+
+/// Whiteboard changes made until `commit` is called, recorded as one undo step
+pub struct JournalStep<'a> {
+    storage: &'a PersistentStorage,
+    label: String,
+    _guard: tokio::sync::MutexGuard<'a, ()>,
+}
+
+impl JournalStep<'_> {
+    pub async fn commit(self) -> Result<(), StorageError> {
+        let depth = self.storage.undo_depth().await?;
+        let mut tx = self.storage.pool.begin().await?;
+        let changes = journal::finish(&mut tx).await?;
+        if !changes.is_empty() {
+            let unused = journal::record(&mut tx, &self.label, &changes, depth).await?;
+            PersistentStorage::delete_unused_contents(&mut tx, &unused).await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
 }
 
 impl PersistentStorage {
@@ -47,192 +228,579 @@ impl PersistentStorage {
             .connect(&db_url)
             .await?;
 
+        Self::with_pool(pool, data_dir).await
+    }
+
+    async fn with_pool(pool: Pool<Sqlite>, data_dir: PathBuf) -> Result<Self, StorageError> {
         // Run migrations
-        Self::run_migrations(&pool).await?;
+        super::migrations::run(&pool).await?;
+        journal::init(&mut *pool.acquire().await?).await?;
 
-        // Initialize or load encryption key
+        // Initialize or load encryption key; stays locked in password mode
         let crypto = Self::init_crypto(&pool).await?;
 
-        Ok(Self { pool, data_dir, crypto })
+        let storage = Self {
+            pool,
+            data_dir,
+            crypto: RwLock::new(crypto),
+            journal: tokio::sync::Mutex::new(()),
+        };
+        if !storage.is_locked().await {
+            storage.run_data_migrations().await?;
+        }
+        Ok(storage)
     }
 
-    async fn init_crypto(pool: &Pool<Sqlite>) -> Result<SecureStore, StorageError> {
-        // Check if salt exists in encryption_config
-        let row = sqlx::query("SELECT salt FROM encryption_config WHERE id = 1")
-            .fetch_optional(pool)
-            .await?;
+    /// Migrations that need the key to rewrite encrypted data
+    async fn run_data_migrations(&self) -> Result<(), StorageError> {
+        self.key_blob_hashes().await?;
+        self.migrate_images_to_blobs().await?;
+        self.backfill_content_hashes().await
+    }
 
-        let salt: [u8; 32] = if let Some(row) = row {
-            let salt_bytes: Vec<u8> = row.get("salt");
-            salt_bytes.try_into().map_err(|_| StorageError::NoHomeDir)?
-        } else {
-            // Generate new salt and save it
-            let salt = SecureStore::generate_salt();
-            let verification = "clitter_verification";
+    /// The key, which is not replaced while the guard is held
+    async fn crypto(&self) -> Result<RwLockReadGuard<'_, SecureStore>, StorageError> {
+        RwLockReadGuard::try_map(self.crypto.read().await, Option::as_ref)
+            .map_err(|_| StorageError::Locked)
+    }
 
-            sqlx::query(
-                "INSERT INTO encryption_config (id, salt, verification_hash, created_at) VALUES (1, ?, ?, ?)"
-            )
-            .bind(salt.as_slice())
-            .bind(verification)
-            .bind(chrono::Utc::now().to_rfc3339())
-            .execute(pool)
-            .await?;
+    pub async fn is_locked(&self) -> bool {
+        self.crypto.read().await.is_none()
+    }
 
-            salt
-        };
+    /// Forget the key until `unlock` is called again
+    pub async fn lock(&self) {
+        *self.crypto.write().await = None;
+    }
 
-        Ok(SecureStore::from_machine_id(&salt)?)
+    pub async fn key_mode(&self) -> Result<KeyMode, StorageError> {
+        let row = sqlx::query("SELECT key_mode FROM encryption_config WHERE id = 1")
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(match row.map(|r| r.get::<String, _>("key_mode")).as_deref() {
+            Some("password") => KeyMode::Password,
+            _ => KeyMode::Machine,
+        })
     }
 
-    async fn run_migrations(pool: &Pool<Sqlite>) -> Result<(), StorageError> {
-        sqlx::query(
-            r#"
-            CREATE TABLE IF NOT EXISTS clipboard_contents (
-                id TEXT PRIMARY KEY,
-                category TEXT NOT NULL,
-                data_type TEXT NOT NULL,
-                text_content TEXT,
-                text_preview TEXT,
-                image_base64 TEXT,
-                image_width INTEGER,
-                image_height INTEGER,
-                image_format TEXT,
-                source TEXT,
-                copied_at TEXT NOT NULL,
-                is_encrypted INTEGER DEFAULT 0
-            )
-            "#,
-        )
-        .execute(pool)
-        .await?;
+    pub async fn lock_state(&self) -> Result<LockState, StorageError> {
+        Ok(LockState {
+            mode: self.key_mode().await?,
+            locked: self.is_locked().await,
+        })
+    }
 
-        sqlx::query(
-            r#"
-            CREATE TABLE IF NOT EXISTS whiteboard_items (
-                id TEXT PRIMARY KEY,
-                content_id TEXT NOT NULL,
-                position_x REAL NOT NULL,
-                position_y REAL NOT NULL,
-                width REAL NOT NULL DEFAULT 200,
-                height REAL NOT NULL DEFAULT 150,
-                parent_group_id TEXT,
-                shortcut TEXT,
-                created_at TEXT NOT NULL,
-                updated_at TEXT NOT NULL,
-                FOREIGN KEY (content_id) REFERENCES clipboard_contents(id) ON DELETE CASCADE
-            )
-            "#,
-        )
-        .execute(pool)
+    async fn key_config(&self) -> Result<KeyConfig, StorageError> {
+        let row = sqlx::query(&format!(
+            "SELECT {} FROM encryption_config WHERE id = 1",
+            KEY_CONFIG_COLUMNS
+        ))
+        .fetch_one(&self.pool)
         .await?;
+        KeyConfig::from_row(&row)
+    }
+
+    /// Argon2 parameters the current key was derived with
+    pub async fn kdf_params(&self) -> Result<KdfParams, StorageError> {
+        Ok(self.key_config().await?.kdf)
+    }
+
+    fn verifies(crypto: &SecureStore, verification_hash: &str) -> bool {
+        crypto
+            .decrypt_text(verification_hash)
+            .is_ok_and(|text| text == VERIFICATION_TEXT)
+    }
+
+    /// Check a master password without unlocking
+    pub async fn verify_master_password(&self, password: &str) -> Result<bool, StorageError> {
+        if self.key_mode().await? != KeyMode::Password {
+            return Ok(false);
+        }
+        let config = self.key_config().await?;
+        let crypto = Self::derive_key(Some(password), config.salt, config.kdf).await?;
+        Ok(Self::verifies(&crypto, &config.verification_hash))
+    }
+
+    /// Derive the key from the master password and make encrypted data available
+    pub async fn unlock(&self, password: &str) -> Result<(), StorageError> {
+        if !self.is_locked().await {
+            return Ok(());
+        }
+        let config = self.key_config().await?;
+        let crypto = Self::derive_key(Some(password), config.salt, config.kdf).await?;
+        if !Self::verifies(&crypto, &config.verification_hash) {
+            return Err(StorageError::WrongPassword);
+        }
+
+        *self.crypto.write().await = Some(crypto);
+        self.run_data_migrations().await
+    }
+
+    /// Switch to (or change) the master password, re-encrypting all stored data
+    /// with the new key in one transaction. Requires the storage to be unlocked,
+    /// and the `current` password when one is set.
+    pub async fn set_master_password(&self, current: Option<&str>, password: &str) -> Result<(), StorageError> {
+        if self.key_mode().await? == KeyMode::Password {
+            let current = current.ok_or(StorageError::WrongPassword)?;
+            if !self.verify_master_password(current).await? {
+                return Err(StorageError::WrongPassword);
+            }
+        }
+        let kdf = self.kdf_params().await?;
+        self.rekey(KeyMode::Password, Some(password), kdf, |_| {}).await
+    }
+
+    /// Derive a new key from a fresh salt and `kdf`, keeping the key mode, and
+    /// re-encrypt all stored data with it. In password mode the current master
+    /// password is required.
+    pub async fn rotate_key(
+        &self,
+        password: Option<&str>,
+        kdf: KdfParams,
+        progress: impl Fn(RekeyProgress),
+    ) -> Result<(), StorageError> {
+        let mode = self.key_mode().await?;
+        if mode == KeyMode::Password {
+            let password = password.ok_or(StorageError::WrongPassword)?;
+            if !self.verify_master_password(password).await? {
+                return Err(StorageError::WrongPassword);
+            }
+        }
+        self.rekey(mode, password, kdf, progress).await
+    }
 
+    /// Derive a key from `password`, or from the machine id without one. Argon2
+    /// runs on a blocking thread so it does not stall the async runtime.
+    async fn derive_key(
+        password: Option<&str>,
+        salt: [u8; 32],
+        kdf: KdfParams,
+    ) -> Result<SecureStore, StorageError> {
+        let password = password.map(|p| Zeroizing::new(p.to_string()));
+        let crypto = tokio::task::spawn_blocking(move || match password {
+            Some(password) => SecureStore::from_password_with(&password, &salt, &kdf),
+            None => SecureStore::from_machine_id(&salt, &kdf),
+        })
+        .await
+        .map_err(|e| StorageError::Io(std::io::Error::other(e)))??;
+        Ok(crypto)
+    }
+
+    /// Replace the key in one transaction: either every value and the
+    /// config are switched to the new key, or nothing is.
+    async fn rekey(
+        &self,
+        mode: KeyMode,
+        password: Option<&str>,
+        kdf: KdfParams,
+        progress: impl Fn(RekeyProgress),
+    ) -> Result<(), StorageError> {
+        // Held throughout, so nothing is written with the old key meanwhile
+        let mut key = self.crypto.write().await;
+        let current = key.as_ref().ok_or(StorageError::Locked)?;
+        let salt = SecureStore::generate_salt();
+        let crypto = match (mode, password) {
+            (KeyMode::Password, Some(password)) => Self::derive_key(Some(password), salt, kdf).await?,
+            (KeyMode::Password, None) => return Err(StorageError::WrongPassword),
+            (KeyMode::Machine, _) => Self::derive_key(None, salt, kdf).await?,
+        };
+
+        let mut tx = self.pool.begin().await?;
+        Self::reencrypt_all(&mut tx, current, &crypto, &progress).await?;
         sqlx::query(
             r#"
-            CREATE TABLE IF NOT EXISTS groups (
-                id TEXT PRIMARY KEY,
-                name TEXT NOT NULL,
-                position_x REAL NOT NULL,
-                position_y REAL NOT NULL,
-                collapsed INTEGER NOT NULL DEFAULT 0,
-                parent_group_id TEXT,
-                shortcut TEXT,
-                created_at TEXT NOT NULL,
-                updated_at TEXT NOT NULL,
-                FOREIGN KEY (parent_group_id) REFERENCES groups(id) ON DELETE SET NULL
-            )
+            UPDATE encryption_config
+            SET salt = ?, verification_hash = ?, key_mode = ?,
+                kdf_memory_kib = ?, kdf_iterations = ?, kdf_parallelism = ?
+            WHERE id = 1
             "#,
         )
-        .execute(pool)
+        .bind(salt.as_slice())
+        .bind(crypto.encrypt_text(VERIFICATION_TEXT)?)
+        .bind(mode.as_str())
+        .bind(kdf.memory_kib as i64)
+        .bind(kdf.iterations as i64)
+        .bind(kdf.parallelism as i64)
+        .execute(&mut *tx)
         .await?;
+        tx.commit().await?;
 
-        sqlx::query(
+        *key = Some(crypto);
+        Ok(())
+    }
+
+    /// Decrypt every encrypted value with `from` and encrypt it again with `to`
+    async fn reencrypt_all(
+        conn: &mut SqliteConnection,
+        from: &SecureStore,
+        to: &SecureStore,
+        progress: &impl Fn(RekeyProgress),
+    ) -> Result<(), StorageError> {
+        const COLUMNS: [&str; 8] = [
+            "text_content",
+            "text_preview",
+            "rich_content",
+            "image_base64",
+            "image_thumbnail",
+            "image_preview",
+            "source",
+            "source_title",
+        ];
+
+        let rows = sqlx::query(&format!("SELECT id, {} FROM clipboard_contents", COLUMNS.join(", ")))
+            .fetch_all(&mut *conn)
+            .await?;
+        let blobs = sqlx::query("SELECT hash, data FROM blobs")
+            .fetch_all(&mut *conn)
+            .await?;
+        let snapshots = sqlx::query("SELECT id, label, data FROM whiteboard_snapshots")
+            .fetch_all(&mut *conn)
+            .await?;
+        let total = rows.len() + blobs.len() + snapshots.len();
+        let mut done = 0;
+        progress(RekeyProgress { done, total });
+
+        let update = format!(
+            "UPDATE clipboard_contents SET {} WHERE id = ?",
+            COLUMNS.map(|c| format!("{} = ?", c)).join(", ")
+        );
+        for row in &rows {
+            let mut query = sqlx::query(&update);
+            for column in COLUMNS {
+                let value = row
+                    .get::<Option<String>, _>(column)
+                    .map(|v| to.encrypt_text(&Zeroizing::new(from.decrypt_text(&v)?)))
+                    .transpose()?;
+                query = query.bind(value);
+            }
+            query
+                .bind(row.get::<String, _>("id"))
+                .execute(&mut *conn)
+                .await?;
+            done += 1;
+            progress(RekeyProgress { done, total });
+        }
+
+        for blob in &blobs {
+            let bytes = Zeroizing::new(from.decrypt(&blob.get::<Vec<u8>, _>("data"))?);
+            let hash: String = blob.get("hash");
+            sqlx::query("UPDATE blobs SET data = ? WHERE hash = ?")
+                .bind(to.encrypt(&bytes)?)
+                .bind(&hash)
+                .execute(&mut *conn)
+                .await?;
+            Self::rename_blob(conn, &hash, &to.fingerprint(&bytes)).await?;
+            done += 1;
+            progress(RekeyProgress { done, total });
+        }
+
+        for snapshot in &snapshots {
+            let data = Zeroizing::new(from.decrypt_text(snapshot.get("data"))?);
+            let label = snapshot
+                .get::<Option<String>, _>("label")
+                .map(|l| to.encrypt_text(&Zeroizing::new(from.decrypt_text(&l)?)))
+                .transpose()?;
+            sqlx::query("UPDATE whiteboard_snapshots SET label = ?, data = ?, fingerprint = ? WHERE id = ?")
+                .bind(label)
+                .bind(to.encrypt_text(&data)?)
+                .bind(to.fingerprint(data.as_bytes()))
+                .bind(snapshot.get::<String, _>("id"))
+                .execute(&mut *conn)
+                .await?;
+            done += 1;
+            progress(RekeyProgress { done, total });
+        }
+
+        // Hashes are keyed too
+        Self::hash_contents(conn, to, "content_hash IS NOT NULL AND category = 'secure'").await
+    }
+
+    /// `ClipboardContent::content_hash` as stored in `clipboard_contents.content_hash`.
+    /// Secure contents get it keyed with the database key, so that it cannot be
+    /// used to guess the secret; the others keep the plain, portable hash.
+    fn stored_hash(crypto: &SecureStore, category: &Category, plain_hash: &str) -> String {
+        match category {
+            Category::Secure => crypto.fingerprint(plain_hash.as_bytes()),
+            _ => plain_hash.to_string(),
+        }
+    }
+
+    /// Compute content_hash for rows written before it was stored (or keyed)
+    async fn backfill_content_hashes(&self) -> Result<(), StorageError> {
+        let crypto = self.crypto().await?;
+        let mut tx = self.pool.begin().await?;
+        Self::hash_contents(&mut tx, &crypto, "content_hash IS NULL").await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Store the keyed hash of the contents matching `condition`
+    async fn hash_contents(conn: &mut SqliteConnection, crypto: &SecureStore, condition: &str) -> Result<(), StorageError> {
+        let rows = sqlx::query(&format!(
             r#"
-            CREATE TABLE IF NOT EXISTS encryption_config (
-                id INTEGER PRIMARY KEY CHECK (id = 1),
-                salt BLOB NOT NULL,
-                verification_hash TEXT NOT NULL,
-                created_at TEXT NOT NULL
-            )
+            SELECT id, category, data_type, text_content, text_preview, rich_content,
+                image_width, image_height, image_format, image_thumbnail, image_preview,
+                source, source_title, copied_at, copy_count, last_copied_at, b.data AS image_data
+            FROM clipboard_contents
+            LEFT JOIN blobs b ON b.hash = image_blob
+            WHERE {}
             "#,
-        )
-        .execute(pool)
+            condition
+        ))
+        .fetch_all(&mut *conn)
         .await?;
 
-        // Create indexes
-        sqlx::query(
-            "CREATE INDEX IF NOT EXISTS idx_whiteboard_items_parent ON whiteboard_items(parent_group_id)",
+        for row in &rows {
+            let content = match Self::content_from_row(crypto, row, "id") {
+                Ok(content) => content,
+                Err(_) => {
+                    eprintln!("Skipping content {} that cannot be decrypted", row.get::<String, _>("id"));
+                    continue;
+                }
+            };
+            sqlx::query("UPDATE clipboard_contents SET content_hash = ? WHERE id = ?")
+                .bind(Self::stored_hash(crypto, &content.category, &content.content_hash()))
+                .bind(content.id.to_string())
+                .execute(&mut *conn)
+                .await?;
+            content.wipe();
+        }
+        Ok(())
+    }
+
+    /// Move images still stored inline as encrypted base64 into the blob table
+    async fn migrate_images_to_blobs(&self) -> Result<(), StorageError> {
+        let crypto = self.crypto().await?;
+        let rows = sqlx::query(
+            "SELECT id, image_base64 FROM clipboard_contents WHERE image_base64 IS NOT NULL",
         )
-        .execute(pool)
+        .fetch_all(&self.pool)
         .await?;
+        if rows.is_empty() {
+            return Ok(());
+        }
 
-        sqlx::query("CREATE INDEX IF NOT EXISTS idx_groups_parent ON groups(parent_group_id)")
-            .execute(pool)
+        let mut tx = self.pool.begin().await?;
+        let mut moved = 0;
+        for row in &rows {
+            let id: String = row.get("id");
+            let encrypted: String = row.get("image_base64");
+            // Rows that cannot be read are left inline rather than failing startup
+            let bytes = match crypto.decrypt_text(&encrypted).map(|base64| STANDARD.decode(base64)) {
+                Ok(Ok(bytes)) => bytes,
+                _ => {
+                    eprintln!("Skipping image {} that cannot be decrypted", id);
+                    continue;
+                }
+            };
+
+            let hash = Self::put_blob(&mut tx, &crypto, &bytes).await?;
+            sqlx::query("UPDATE clipboard_contents SET image_blob = ?, image_base64 = NULL WHERE id = ?")
+                .bind(hash)
+                .bind(id)
+                .execute(&mut *tx)
+                .await?;
+            moved += 1;
+        }
+        tx.commit().await?;
+
+        println!("Moved {} images to blob storage", moved);
+        Ok(())
+    }
+
+    /// Re-address blobs stored under a plain SHA-256 of the image by their keyed hash
+    async fn key_blob_hashes(&self) -> Result<(), StorageError> {
+        let crypto = self.crypto().await?;
+        let rows = sqlx::query("SELECT hash, data FROM blobs WHERE keyed = 0")
+            .fetch_all(&self.pool)
             .await?;
+        if rows.is_empty() {
+            return Ok(());
+        }
 
-        sqlx::query(
-            "CREATE INDEX IF NOT EXISTS idx_clipboard_contents_copied_at ON clipboard_contents(copied_at)",
+        let mut tx = self.pool.begin().await?;
+        for row in &rows {
+            let hash: String = row.get("hash");
+            let Ok(bytes) = crypto.decrypt(&row.get::<Vec<u8>, _>("data")).map(Zeroizing::new) else {
+                eprintln!("Skipping blob {} that cannot be decrypted", hash);
+                continue;
+            };
+            Self::rename_blob(&mut tx, &hash, &crypto.fingerprint(&bytes)).await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Move a blob and the contents referring to it to a new keyed hash
+    async fn rename_blob(conn: &mut SqliteConnection, from: &str, to: &str) -> Result<(), StorageError> {
+        sqlx::query("UPDATE blobs SET hash = ?, keyed = 1 WHERE hash = ?")
+            .bind(to)
+            .bind(from)
+            .execute(&mut *conn)
+            .await?;
+        sqlx::query("UPDATE clipboard_contents SET image_blob = ? WHERE image_blob = ?")
+            .bind(to)
+            .bind(from)
+            .execute(&mut *conn)
+            .await?;
+        Ok(())
+    }
+
+    /// Store bytes under their hash keyed with the database key, encrypting them
+    /// only if not stored yet. The hash tells nothing about the image without the key.
+    async fn put_blob(
+        conn: &mut SqliteConnection,
+        crypto: &SecureStore,
+        bytes: &[u8],
+    ) -> Result<String, StorageError> {
+        let hash = crypto.fingerprint(bytes);
+
+        let exists = sqlx::query("SELECT 1 FROM blobs WHERE hash = ?")
+            .bind(&hash)
+            .fetch_optional(&mut *conn)
+            .await?
+            .is_some();
+        if !exists {
+            let encrypted = crypto.encrypt(bytes)?;
+            sqlx::query("INSERT INTO blobs (hash, data, size, keyed, created_at) VALUES (?, ?, ?, 1, ?)")
+                .bind(&hash)
+                .bind(encrypted)
+                .bind(bytes.len() as i64)
+                .bind(Utc::now().to_rfc3339())
+                .execute(&mut *conn)
+                .await?;
+        }
+
+        Ok(hash)
+    }
+
+    /// Delete blobs no longer referenced by any content
+    async fn prune_blobs(conn: &mut SqliteConnection) -> Result<u64, StorageError> {
+        let result = sqlx::query(
+            "DELETE FROM blobs WHERE hash NOT IN (SELECT image_blob FROM clipboard_contents WHERE image_blob IS NOT NULL)",
         )
-        .execute(pool)
+        .execute(conn)
         .await?;
+        Ok(result.rows_affected())
+    }
 
-        // Add color column to groups if not exists
-        let _ = sqlx::query("ALTER TABLE groups ADD COLUMN color TEXT")
-            .execute(pool)
-            .await;
+    /// Load the machine-derived key, or None if a master password is required
+    async fn init_crypto(pool: &Pool<Sqlite>) -> Result<Option<SecureStore>, StorageError> {
+        // Check if salt exists in encryption_config
+        let row = sqlx::query(&format!(
+            "SELECT {} FROM encryption_config WHERE id = 1",
+            KEY_CONFIG_COLUMNS
+        ))
+        .fetch_optional(pool)
+        .await?;
 
-        // Add label column to whiteboard_items if not exists
-        let _ = sqlx::query("ALTER TABLE whiteboard_items ADD COLUMN label TEXT")
-            .execute(pool)
-            .await;
+        let Some(row) = row else {
+            // Generate new salt and save it
+            let salt = SecureStore::generate_salt();
+            let kdf = KdfParams::default();
+            let crypto = Self::derive_key(None, salt, kdf).await?;
 
-        // Create settings table
-        sqlx::query(
-            r#"
-            CREATE TABLE IF NOT EXISTS settings (
-                key TEXT PRIMARY KEY,
-                value TEXT NOT NULL
+            sqlx::query(
+                r#"
+                INSERT INTO encryption_config
+                    (id, salt, verification_hash, key_mode, kdf_memory_kib, kdf_iterations, kdf_parallelism, created_at)
+                VALUES (1, ?, ?, ?, ?, ?, ?, ?)
+                "#,
             )
-            "#,
-        )
-        .execute(pool)
-        .await?;
+            .bind(salt.as_slice())
+            .bind(crypto.encrypt_text(VERIFICATION_TEXT)?)
+            .bind(KeyMode::Machine.as_str())
+            .bind(kdf.memory_kib as i64)
+            .bind(kdf.iterations as i64)
+            .bind(kdf.parallelism as i64)
+            .bind(chrono::Utc::now().to_rfc3339())
+            .execute(pool)
+            .await?;
 
-        // Set default global shortcut if not exists
-        let _ = sqlx::query(
-            "INSERT OR IGNORE INTO settings (key, value) VALUES ('global_shortcut', 'Alt+V')"
-        )
-        .execute(pool)
-        .await;
+            return Ok(Some(crypto));
+        };
 
-        Ok(())
+        if row.get::<String, _>("key_mode") == KeyMode::Password.as_str() {
+            return Ok(None);
+        }
+
+        let config = KeyConfig::from_row(&row)?;
+        let crypto = Self::derive_key(None, config.salt, config.kdf).await?;
+
+        // Older databases stored the verification text in plain
+        let verification_hash = config.verification_hash;
+        if verification_hash == VERIFICATION_TEXT {
+            sqlx::query("UPDATE encryption_config SET verification_hash = ? WHERE id = 1")
+                .bind(crypto.encrypt_text(VERIFICATION_TEXT)?)
+                .execute(pool)
+                .await?;
+        } else if !Self::verifies(&crypto, &verification_hash) {
+            eprintln!("Machine key does not match this database; existing entries cannot be decrypted");
+        }
+
+        Ok(Some(crypto))
     }
 
     pub async fn save_clipboard_content(
         &self,
         content: &ClipboardContent,
     ) -> Result<(), StorageError> {
-        let (data_type, text_content, text_preview, image_base64, image_width, image_height, image_format) =
+        let crypto = self.crypto().await?;
+        let mut tx = self.pool.begin().await?;
+        Self::write_clipboard_content(&mut tx, &crypto, content).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Encrypt a content and upsert its row on `conn`
+    async fn write_clipboard_content(
+        conn: &mut SqliteConnection,
+        crypto: &SecureStore,
+        content: &ClipboardContent,
+    ) -> Result<(), StorageError> {
+        let (data_type, text_content, text_preview, rich_content, image_bytes, image_width, image_height, image_format) =
             match &content.data {
                 ClipboardData::Text { text, preview } => {
                     // Encrypt text content
-                    let encrypted_text = self.crypto.encrypt_text(text)?;
-                    let encrypted_preview = self.crypto.encrypt_text(preview)?;
-                    ("text", Some(encrypted_text), Some(encrypted_preview), None, None, None, None)
+                    let encrypted_text = crypto.encrypt_text(text)?;
+                    let encrypted_preview = crypto.encrypt_text(preview)?;
+                    ("text", Some(encrypted_text), Some(encrypted_preview), None, None, None, None, None)
+                }
+                ClipboardData::Html { html: rich, text, preview }
+                | ClipboardData::Rtf { rtf: rich, text, preview } => {
+                    let data_type = if matches!(content.data, ClipboardData::Html { .. }) { "html" } else { "rtf" };
+                    let encrypted_text = crypto.encrypt_text(text)?;
+                    let encrypted_preview = crypto.encrypt_text(preview)?;
+                    let encrypted_rich = crypto.encrypt_text(rich)?;
+                    (data_type, Some(encrypted_text), Some(encrypted_preview), Some(encrypted_rich), None, None, None, None)
+                }
+                ClipboardData::Files { uris, preview } => {
+                    // Stored as text/uri-list
+                    let encrypted_preview = crypto.encrypt_text(preview)?;
+                    let encrypted_uris = crypto.encrypt_text(&uris.join("\r\n"))?;
+                    ("files", None, Some(encrypted_preview), Some(encrypted_uris), None, None, None, None)
                 }
                 ClipboardData::Image {
                     base64,
                     width,
                     height,
                     format,
+                    ..
                 } => {
-                    // Encrypt image data
-                    let encrypted_base64 = self.crypto.encrypt_text(base64)?;
+                    // Empty when the image was loaded lazily; the stored blob is kept then
+                    let bytes = if base64.is_empty() {
+                        None
+                    } else {
+                        Some(STANDARD.decode(base64).map_err(|_| {
+                            crate::crypto::secure_store::CryptoError::Base64DecodeFailed
+                        })?)
+                    };
                     (
                         "image",
                         None,
                         None,
-                        Some(encrypted_base64),
+                        None,
+                        bytes,
                         Some(*width as i64),
                         Some(*height as i64),
                         Some(format.clone()),
@@ -240,18 +808,63 @@ impl PersistentStorage {
                 }
             };
 
+        let (image_thumbnail, image_preview) = match &content.data {
+            ClipboardData::Image { thumbnail, preview, .. } => (
+                thumbnail.as_ref().map(|t| crypto.encrypt_text(t)).transpose()?,
+                preview.as_ref().map(|p| crypto.encrypt_text(p)).transpose()?,
+            ),
+            _ => (None, None),
+        };
+
         // Encrypt source if present
         let encrypted_source = content
             .source
             .as_ref()
-            .map(|s| self.crypto.encrypt_text(s))
+            .map(|s| crypto.encrypt_text(s))
             .transpose()?;
+        let encrypted_source_title = content
+            .source_title
+            .as_ref()
+            .map(|s| crypto.encrypt_text(s))
+            .transpose()?;
+
+        // Lazily loaded images cannot be hashed; the stored hash is kept like the blob
+        let hash = match &image_bytes {
+            None if data_type == "image" => None,
+            _ => Some(Self::stored_hash(crypto, &content.category, &content.content_hash())),
+        };
 
+        let image_blob = match &image_bytes {
+            Some(bytes) => Some(Self::put_blob(&mut *conn, crypto, bytes).await?),
+            None => None,
+        };
+
+        // Upsert rather than replace: a replace deletes the row first, which would cascade
+        // to whiteboard items and reset in_history
         sqlx::query(
             r#"
-            INSERT OR REPLACE INTO clipboard_contents
-            (id, category, data_type, text_content, text_preview, image_base64, image_width, image_height, image_format, source, copied_at, is_encrypted)
-            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, 1)
+            INSERT INTO clipboard_contents
+            (id, category, data_type, text_content, text_preview, rich_content, image_blob, image_width, image_height, image_format, image_thumbnail, image_preview, source, source_title, copied_at, copy_count, last_copied_at, content_hash, is_encrypted)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, 1)
+            ON CONFLICT(id) DO UPDATE SET
+                category = excluded.category,
+                data_type = excluded.data_type,
+                text_content = excluded.text_content,
+                text_preview = excluded.text_preview,
+                rich_content = excluded.rich_content,
+                image_blob = COALESCE(excluded.image_blob, clipboard_contents.image_blob),
+                image_width = excluded.image_width,
+                image_height = excluded.image_height,
+                image_format = excluded.image_format,
+                image_thumbnail = excluded.image_thumbnail,
+                image_preview = excluded.image_preview,
+                source = excluded.source,
+                source_title = excluded.source_title,
+                copied_at = excluded.copied_at,
+                copy_count = excluded.copy_count,
+                last_copied_at = excluded.last_copied_at,
+                content_hash = COALESCE(excluded.content_hash, clipboard_contents.content_hash),
+                is_encrypted = excluded.is_encrypted
             "#,
         )
         .bind(content.id.to_string())
@@ -259,21 +872,30 @@ impl PersistentStorage {
         .bind(data_type)
         .bind(text_content)
         .bind(text_preview)
-        .bind(image_base64)
+        .bind(rich_content)
+        .bind(image_blob)
         .bind(image_width)
         .bind(image_height)
         .bind(image_format)
+        .bind(image_thumbnail)
+        .bind(image_preview)
         .bind(encrypted_source)
+        .bind(encrypted_source_title)
         .bind(content.copied_at.to_rfc3339())
-        .execute(&self.pool)
+        .bind(content.copy_count as i64)
+        .bind(content.last_copied_at.to_rfc3339())
+        .bind(hash)
+        .execute(&mut *conn)
         .await?;
 
         Ok(())
     }
 
     pub async fn save_whiteboard_item(&self, item: &WhiteboardItem) -> Result<(), StorageError> {
+        let crypto = self.crypto().await?;
+        let mut tx = self.pool.begin().await?;
         // First save the content
-        self.save_clipboard_content(&item.content).await?;
+        Self::write_clipboard_content(&mut tx, &crypto, &item.content).await?;
 
         sqlx::query(
             r#"
@@ -293,9 +915,10 @@ impl PersistentStorage {
         .bind(&item.label)
         .bind(item.created_at.to_rfc3339())
         .bind(item.updated_at.to_rfc3339())
-        .execute(&self.pool)
+        .execute(&mut *tx)
         .await?;
 
+        tx.commit().await?;
         Ok(())
     }
 
@@ -324,11 +947,12 @@ impl PersistentStorage {
     }
 
     pub async fn load_whiteboard(&self) -> Result<WhiteboardState, StorageError> {
+        let crypto = self.crypto().await?;
         let mut state = WhiteboardState::default();
 
         // Load groups
         let group_rows = sqlx::query(
-            "SELECT id, name, position_x, position_y, collapsed, parent_group_id, shortcut, color, created_at, updated_at FROM groups",
+            "SELECT id, name, position_x, position_y, collapsed, parent_group_id, shortcut, color, created_at, updated_at FROM groups WHERE trash_id IS NULL",
         )
         .fetch_all(&self.pool)
         .await?;
@@ -368,10 +992,15 @@ impl PersistentStorage {
             r#"
             SELECT
                 wi.id, wi.position_x, wi.position_y, wi.width, wi.height, wi.parent_group_id, wi.shortcut, wi.label, wi.created_at, wi.updated_at,
-                cc.id as content_id, cc.category, cc.data_type, cc.text_content, cc.text_preview,
-                cc.image_base64, cc.image_width, cc.image_height, cc.image_format, cc.source, cc.copied_at
+                cc.id as content_id, cc.category, cc.data_type, cc.text_content, cc.text_preview, cc.rich_content,
+                cc.image_width, cc.image_height, cc.image_format, cc.image_thumbnail, cc.image_preview, cc.source, cc.source_title, cc.copied_at,
+                cc.copy_count, cc.last_copied_at,
+                -- Images with a preview are shown without the original, which is loaded on demand
+                CASE WHEN cc.image_preview IS NULL THEN b.data END AS image_data
             FROM whiteboard_items wi
             JOIN clipboard_contents cc ON wi.content_id = cc.id
+            LEFT JOIN blobs b ON b.hash = cc.image_blob
+            WHERE wi.trash_id IS NULL
             "#,
         )
         .fetch_all(&self.pool)
@@ -381,46 +1010,15 @@ impl PersistentStorage {
             let id: String = row.get("id");
             let id = Uuid::parse_str(&id).unwrap_or_default();
 
-            let content_id: String = row.get("content_id");
-            let content_id = Uuid::parse_str(&content_id).unwrap_or_default();
-
             let parent_group: Option<String> = row.get("parent_group_id");
             let parent_group = parent_group.and_then(|s| Uuid::parse_str(&s).ok());
 
-            let category: String = row.get("category");
-            let category: Category = category.parse().unwrap_or(Category::Text);
-
-            let data_type: String = row.get("data_type");
-            let data = if data_type == "image" {
-                let encrypted_base64 = row.get::<Option<String>, _>("image_base64").unwrap_or_default();
-                let base64 = self.crypto.decrypt_text(&encrypted_base64).unwrap_or_default();
-                ClipboardData::Image {
-                    base64,
-                    width: row.get::<Option<i64>, _>("image_width").unwrap_or(0) as u32,
-                    height: row.get::<Option<i64>, _>("image_height").unwrap_or(0) as u32,
-                    format: row.get::<Option<String>, _>("image_format").unwrap_or_default(),
+            let content = match Self::content_from_row(&crypto, &row, "content_id") {
+                Ok(content) => content,
+                Err(_) => {
+                    eprintln!("Skipping whiteboard item {} that cannot be decrypted", id);
+                    continue;
                 }
-            } else {
-                let encrypted_text = row.get::<Option<String>, _>("text_content").unwrap_or_default();
-                let encrypted_preview = row.get::<Option<String>, _>("text_preview").unwrap_or_default();
-                let text = self.crypto.decrypt_text(&encrypted_text).unwrap_or_default();
-                let preview = self.crypto.decrypt_text(&encrypted_preview).unwrap_or_default();
-                ClipboardData::Text { text, preview }
-            };
-
-            let encrypted_source: Option<String> = row.get("source");
-            let source = encrypted_source
-                .map(|s| self.crypto.decrypt_text(&s).ok())
-                .flatten();
-
-            let content = ClipboardContent {
-                id: content_id,
-                category,
-                data,
-                copied_at: chrono::DateTime::parse_from_rfc3339(row.get("copied_at"))
-                    .map(|dt| dt.with_timezone(&chrono::Utc))
-                    .unwrap_or_else(|_| chrono::Utc::now()),
-                source,
             };
 
             let item = WhiteboardItem {
@@ -445,80 +1043,960 @@ impl PersistentStorage {
                     .unwrap_or_else(|_| chrono::Utc::now()),
             };
 
-            if parent_group.is_none() {
-                state.root_items.push(id);
-            }
-
             state.items.insert(id, item);
         }
 
+        state.rebuild_children();
         Ok(state)
     }
 
-    pub async fn delete_whiteboard_item(&self, id: Uuid) -> Result<(), StorageError> {
-        // Get content_id first
-        let row = sqlx::query("SELECT content_id FROM whiteboard_items WHERE id = ?")
-            .bind(id.to_string())
-            .fetch_optional(&self.pool)
-            .await?;
-
-        if let Some(row) = row {
-            let content_id: String = row.get("content_id");
+    /// Decode a `clipboard_contents` row (columns as selected by the callers).
+    /// Fails if a column cannot be decrypted, e.g. with a different key.
+    fn content_from_row(
+        crypto: &SecureStore,
+        row: &SqliteRow,
+        id_column: &str,
+    ) -> Result<ClipboardContent, StorageError> {
+        let content_id: String = row.get(id_column);
+        let content_id = Uuid::parse_str(&content_id).unwrap_or_default();
+
+        let category: String = row.get("category");
+        let category: Category = category.parse().unwrap_or(Category::Text);
+
+        let data_type: String = row.get("data_type");
+        let decrypt_optional = |column: &str| {
+            row.get::<Option<String>, _>(column)
+                .map(|encrypted| crypto.decrypt_text(&encrypted))
+                .transpose()
+        };
+        let decrypt_column = |column: &str| Ok::<_, StorageError>(decrypt_optional(column)?.unwrap_or_default());
+        let data = match data_type.as_str() {
+            "image" => ClipboardData::Image {
+                // Not selected when the image is loaded lazily
+                base64: row
+                    .try_get::<Option<Vec<u8>>, _>("image_data")
+                    .ok()
+                    .flatten()
+                    .map(|data| crypto.decrypt(&data))
+                    .transpose()?
+                    .map(|bytes| STANDARD.encode(bytes))
+                    .unwrap_or_default(),
+                width: row.get::<Option<i64>, _>("image_width").unwrap_or(0) as u32,
+                height: row.get::<Option<i64>, _>("image_height").unwrap_or(0) as u32,
+                format: row.get::<Option<String>, _>("image_format").unwrap_or_default(),
+                thumbnail: decrypt_optional("image_thumbnail")?,
+                preview: decrypt_optional("image_preview")?,
+            },
+            "html" => ClipboardData::Html {
+                html: decrypt_column("rich_content")?,
+                text: decrypt_column("text_content")?,
+                preview: decrypt_column("text_preview")?,
+            },
+            "rtf" => ClipboardData::Rtf {
+                rtf: decrypt_column("rich_content")?,
+                text: decrypt_column("text_content")?,
+                preview: decrypt_column("text_preview")?,
+            },
+            "files" => ClipboardData::Files {
+                uris: decrypt_column("rich_content")?
+                    .lines()
+                    .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                    .map(|line| line.to_string())
+                    .collect(),
+                preview: decrypt_column("text_preview")?,
+            },
+            _ => ClipboardData::Text {
+                text: decrypt_column("text_content")?,
+                preview: decrypt_column("text_preview")?,
+            },
+        };
 
-            // Delete whiteboard item
-            sqlx::query("DELETE FROM whiteboard_items WHERE id = ?")
-                .bind(id.to_string())
-                .execute(&self.pool)
-                .await?;
+        let source = decrypt_optional("source")?;
+        let source_title = decrypt_optional("source_title")?;
+
+        let copied_at = chrono::DateTime::parse_from_rfc3339(row.get("copied_at"))
+            .map(|dt| dt.with_timezone(&chrono::Utc))
+            .unwrap_or_else(|_| chrono::Utc::now());
+        // Rows written before re-copies were tracked have no last_copied_at
+        let last_copied_at = row
+            .get::<Option<String>, _>("last_copied_at")
+            .and_then(|s| chrono::DateTime::parse_from_rfc3339(&s).ok())
+            .map(|dt| dt.with_timezone(&chrono::Utc))
+            .unwrap_or(copied_at);
+
+        Ok(ClipboardContent {
+            id: content_id,
+            category,
+            data,
+            copied_at,
+            source,
+            source_title,
+            copy_count: row.get::<i64, _>("copy_count") as u32,
+            last_copied_at,
+        })
+    }
 
-            // Delete content
-            sqlx::query("DELETE FROM clipboard_contents WHERE id = ?")
-                .bind(content_id)
-                .execute(&self.pool)
-                .await?;
+    /// Move a whiteboard item to the trash, returning the trash entry
+    pub async fn delete_whiteboard_item(&self, id: Uuid) -> Result<Option<Uuid>, StorageError> {
+        let mut tx = self.pool.begin().await?;
+        let exists = sqlx::query("SELECT 1 FROM whiteboard_items WHERE id = ? AND trash_id IS NULL")
+            .bind(id.to_string())
+            .fetch_optional(&mut *tx)
+            .await?
+            .is_some();
+        if !exists {
+            return Ok(None);
         }
 
-        Ok(())
-    }
-
-    pub async fn delete_group(&self, id: Uuid) -> Result<(), StorageError> {
-        sqlx::query("DELETE FROM groups WHERE id = ?")
+        let trash_id = Self::create_trash_entry(&mut tx, TrashKind::Item, id).await?;
+        sqlx::query("UPDATE whiteboard_items SET trash_id = ? WHERE id = ?")
+            .bind(trash_id.to_string())
             .bind(id.to_string())
-            .execute(&self.pool)
+            .execute(&mut *tx)
             .await?;
-        Ok(())
+        tx.commit().await?;
+        Ok(Some(trash_id))
     }
 
-    pub async fn update_item_shortcut(
-        &self,
-        id: Uuid,
-        shortcut: Option<String>,
-    ) -> Result<(), StorageError> {
-        sqlx::query("UPDATE whiteboard_items SET shortcut = ?, updated_at = ? WHERE id = ?")
-            .bind(shortcut)
-            .bind(chrono::Utc::now().to_rfc3339())
+    /// Delete a group, handling its child groups and items according to `mode`.
+    /// Returns the trash entry when moved to the trash.
+    pub async fn delete_group(&self, id: Uuid, mode: DeleteMode) -> Result<Option<Uuid>, StorageError> {
+        let mut tx = self.pool.begin().await?;
+        let row = sqlx::query("SELECT parent_group_id FROM groups WHERE id = ? AND trash_id IS NULL")
             .bind(id.to_string())
-            .execute(&self.pool)
+            .fetch_optional(&mut *tx)
             .await?;
-        Ok(())
-    }
-
-    pub async fn update_group_shortcut(
-        &self,
-        id: Uuid,
-        shortcut: Option<String>,
-    ) -> Result<(), StorageError> {
-        sqlx::query("UPDATE groups SET shortcut = ?, updated_at = ? WHERE id = ?")
+        let Some(row) = row else {
+            return Ok(None);
+        };
+        let parent: Option<String> = row.get("parent_group_id");
+        let now = Utc::now().to_rfc3339();
+
+        let mut trash_id = None;
+        match mode {
+            DeleteMode::Cascade => {
+                let mut content_ids = Vec::new();
+                for group in Self::subtree_groups(&mut tx, id).await? {
+                    let rows = sqlx::query(
+                        "SELECT content_id FROM whiteboard_items WHERE parent_group_id = ? AND trash_id IS NULL",
+                    )
+                    .bind(&group)
+                    .fetch_all(&mut *tx)
+                    .await?;
+                    content_ids.extend(rows.iter().map(|r| r.get::<String, _>("content_id")));
+
+                    sqlx::query("DELETE FROM whiteboard_items WHERE parent_group_id = ? AND trash_id IS NULL")
+                        .bind(&group)
+                        .execute(&mut *tx)
+                        .await?;
+                    sqlx::query("DELETE FROM groups WHERE id = ?")
+                        .bind(&group)
+                        .execute(&mut *tx)
+                        .await?;
+                    // Trashed entries from the group are restored to the top level
+                    for table in ["whiteboard_items", "groups"] {
+                        sqlx::query(&format!(
+                            "UPDATE {} SET parent_group_id = NULL WHERE parent_group_id = ? AND trash_id IS NOT NULL",
+                            table
+                        ))
+                        .bind(&group)
+                        .execute(&mut *tx)
+                        .await?;
+                    }
+                }
+                Self::delete_unused_contents(&mut tx, &content_ids).await?;
+            }
+            DeleteMode::Reparent => {
+                for table in ["whiteboard_items", "groups"] {
+                    sqlx::query(&format!(
+                        "UPDATE {} SET parent_group_id = ?, updated_at = ? WHERE parent_group_id = ?",
+                        table
+                    ))
+                    .bind(&parent)
+                    .bind(&now)
+                    .bind(id.to_string())
+                    .execute(&mut *tx)
+                    .await?;
+                }
+                sqlx::query("DELETE FROM groups WHERE id = ?")
+                    .bind(id.to_string())
+                    .execute(&mut *tx)
+                    .await?;
+            }
+            DeleteMode::Trash => {
+                let entry = Self::create_trash_entry(&mut tx, TrashKind::Group, id).await?;
+                for group in Self::subtree_groups(&mut tx, id).await? {
+                    sqlx::query("UPDATE groups SET trash_id = ? WHERE id = ?")
+                        .bind(entry.to_string())
+                        .bind(&group)
+                        .execute(&mut *tx)
+                        .await?;
+                    sqlx::query(
+                        "UPDATE whiteboard_items SET trash_id = ? WHERE parent_group_id = ? AND trash_id IS NULL",
+                    )
+                    .bind(entry.to_string())
+                    .bind(&group)
+                    .execute(&mut *tx)
+                    .await?;
+                }
+                trash_id = Some(entry);
+            }
+        }
+
+        tx.commit().await?;
+        Ok(trash_id)
+    }
+
+    /// Ids of a group and all groups below it, not counting ones already in the trash
+    async fn subtree_groups(conn: &mut SqliteConnection, id: Uuid) -> Result<Vec<String>, StorageError> {
+        let rows = sqlx::query(
+            r#"
+            WITH RECURSIVE subtree(id) AS (
+                SELECT ?
+                UNION
+                SELECT g.id FROM groups g JOIN subtree s ON g.parent_group_id = s.id
+                WHERE g.trash_id IS NULL
+            )
+            SELECT id FROM subtree
+            "#,
+        )
+        .bind(id.to_string())
+        .fetch_all(conn)
+        .await?;
+        Ok(rows.iter().map(|r| r.get("id")).collect())
+    }
+
+    async fn create_trash_entry(
+        conn: &mut SqliteConnection,
+        kind: TrashKind,
+        root_id: Uuid,
+    ) -> Result<Uuid, StorageError> {
+        let id = Uuid::new_v4();
+        sqlx::query("INSERT INTO trash (id, kind, root_id, deleted_at) VALUES (?, ?, ?, ?)")
+            .bind(id.to_string())
+            .bind(kind.as_str())
+            .bind(root_id.to_string())
+            .bind(Utc::now().to_rfc3339())
+            .execute(conn)
+            .await?;
+        Ok(id)
+    }
+
+    /// Delete contents that are neither history nor on the whiteboard (or in the
+    /// trash or undo journal) anymore
+    async fn delete_unused_contents(
+        conn: &mut SqliteConnection,
+        content_ids: &[String],
+    ) -> Result<(), StorageError> {
+        let statement = format!(
+            "DELETE FROM clipboard_contents WHERE id = ? AND in_history = 0 AND id NOT IN (SELECT content_id FROM whiteboard_items) AND id NOT IN ({}) AND id NOT IN ({})",
+            JOURNALED_CONTENT_IDS, SNAPSHOT_CONTENT_IDS
+        );
+        for content_id in content_ids {
+            sqlx::query(&statement)
+                .bind(content_id)
+                .execute(&mut *conn)
+                .await?;
+        }
+        Self::prune_blobs(conn).await?;
+        Ok(())
+    }
+
+    /// Deleted items and groups, most recently deleted first
+    pub async fn list_trash(&self) -> Result<Vec<TrashEntry>, StorageError> {
+        let rows = sqlx::query(
+            r#"
+            SELECT t.id, t.kind, t.root_id, t.deleted_at,
+                (SELECT COUNT(*) FROM whiteboard_items WHERE trash_id = t.id) AS item_count,
+                (SELECT COUNT(*) FROM groups WHERE trash_id = t.id) AS group_count,
+                COALESCE(
+                    (SELECT name FROM groups WHERE id = t.root_id),
+                    (SELECT label FROM whiteboard_items WHERE id = t.root_id)
+                ) AS name
+            FROM trash t
+            ORDER BY t.deleted_at DESC
+            "#,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .iter()
+            .map(|row| TrashEntry {
+                id: Uuid::parse_str(row.get("id")).unwrap_or_default(),
+                kind: match row.get::<String, _>("kind").as_str() {
+                    "group" => TrashKind::Group,
+                    _ => TrashKind::Item,
+                },
+                root_id: Uuid::parse_str(row.get("root_id")).unwrap_or_default(),
+                name: row.get("name"),
+                item_count: row.get("item_count"),
+                group_count: row.get("group_count"),
+                deleted_at: chrono::DateTime::parse_from_rfc3339(row.get("deleted_at"))
+                    .map(|dt| dt.with_timezone(&chrono::Utc))
+                    .unwrap_or_else(|_| chrono::Utc::now()),
+            })
+            .collect())
+    }
+
+    /// Put a trash entry back on the whiteboard. It goes to the top level if its
+    /// group is gone, and shortcuts taken in the meantime are dropped.
+    /// Returns false if there is no such entry.
+    pub async fn restore_from_trash(&self, trash_id: Uuid) -> Result<bool, StorageError> {
+        let mut tx = self.pool.begin().await?;
+        let row = sqlx::query("SELECT kind, root_id FROM trash WHERE id = ?")
+            .bind(trash_id.to_string())
+            .fetch_optional(&mut *tx)
+            .await?;
+        let Some(row) = row else {
+            return Ok(false);
+        };
+        let root_table = match row.get::<String, _>("kind").as_str() {
+            "group" => "groups",
+            _ => "whiteboard_items",
+        };
+        let now = Utc::now().to_rfc3339();
+
+        sqlx::query(&format!(
+            "UPDATE {} SET parent_group_id = NULL WHERE id = ? AND parent_group_id NOT IN (SELECT id FROM groups WHERE trash_id IS NULL)",
+            root_table
+        ))
+        .bind(row.get::<String, _>("root_id"))
+        .execute(&mut *tx)
+        .await?;
+
+        for table in ["whiteboard_items", "groups"] {
+            sqlx::query(&format!(
+                r#"
+                UPDATE {table} SET shortcut = NULL
+                WHERE trash_id = ? AND shortcut IN (
+                    SELECT shortcut FROM {table} WHERE trash_id IS NULL AND shortcut IS NOT NULL
+                )
+                "#
+            ))
+            .bind(trash_id.to_string())
+            .execute(&mut *tx)
+            .await?;
+            sqlx::query(&format!(
+                "UPDATE {} SET trash_id = NULL, updated_at = ? WHERE trash_id = ?",
+                table
+            ))
+            .bind(&now)
+            .bind(trash_id.to_string())
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        sqlx::query("DELETE FROM trash WHERE id = ?")
+            .bind(trash_id.to_string())
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    /// Delete a trash entry for good
+    pub async fn delete_from_trash(&self, trash_id: Uuid) -> Result<(), StorageError> {
+        let mut tx = self.pool.begin().await?;
+        let content_ids = Self::purge_trash_entry(&mut tx, &trash_id.to_string()).await?;
+        Self::delete_unused_contents(&mut tx, &content_ids).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Delete trash entries deleted before `cutoff` for good, returning how many
+    pub async fn purge_trash(&self, cutoff: DateTime<Utc>) -> Result<u64, StorageError> {
+        let mut tx = self.pool.begin().await?;
+        let rows = sqlx::query("SELECT id FROM trash WHERE deleted_at < ?")
+            .bind(cutoff.to_rfc3339())
+            .fetch_all(&mut *tx)
+            .await?;
+        for row in &rows {
+            let content_ids = Self::purge_trash_entry(&mut tx, row.get("id")).await?;
+            Self::delete_unused_contents(&mut tx, &content_ids).await?;
+        }
+        tx.commit().await?;
+        Ok(rows.len() as u64)
+    }
+
+    /// Delete an entry's rows, returning the content ids of its items
+    async fn purge_trash_entry(conn: &mut SqliteConnection, trash_id: &str) -> Result<Vec<String>, StorageError> {
+        let rows = sqlx::query("SELECT content_id FROM whiteboard_items WHERE trash_id = ?")
+            .bind(trash_id)
+            .fetch_all(&mut *conn)
+            .await?;
+        let content_ids: Vec<String> = rows.iter().map(|r| r.get("content_id")).collect();
+
+        for statement in [
+            "DELETE FROM whiteboard_items WHERE trash_id = ?",
+            "DELETE FROM groups WHERE trash_id = ?",
+            "DELETE FROM trash WHERE id = ?",
+        ] {
+            sqlx::query(statement)
+                .bind(trash_id)
+                .execute(&mut *conn)
+                .await?;
+        }
+        Ok(content_ids)
+    }
+
+    pub async fn trash_retention_days(&self) -> Result<u32, StorageError> {
+        Ok(self
+            .get_setting(TRASH_RETENTION_SETTING)
+            .await?
+            .and_then(|days| days.parse().ok())
+            .unwrap_or(DEFAULT_TRASH_RETENTION_DAYS))
+    }
+
+    /// Empty the trash of entries older than the retention period. Waits for an
+    /// undo step being recorded, so the purge does not become part of it.
+    pub async fn purge_expired_trash(&self) -> Result<u64, StorageError> {
+        let days = self.trash_retention_days().await?;
+        let _guard = self.journal.lock().await;
+        self.purge_trash(Utc::now() - chrono::Duration::days(days as i64))
+            .await
+    }
+
+    /// Start recording whiteboard changes as an undo step labelled `label`.
+    /// Steps are recorded one at a time.
+    pub async fn begin_step(&self, label: &str) -> Result<JournalStep<'_>, StorageError> {
+        let guard = self.journal.lock().await;
+        let mut conn = self.pool.acquire().await?;
+        journal::begin(&mut conn).await?;
+        Ok(JournalStep {
+            storage: self,
+            label: label.to_string(),
+            _guard: guard,
+        })
+    }
+
+    /// Revert the latest whiteboard step. Returns false if there is none.
+    pub async fn undo(&self) -> Result<bool, StorageError> {
+        let _guard = self.journal.lock().await;
+        let mut tx = self.pool.begin().await?;
+        let undone = journal::undo(&mut tx).await?;
+        tx.commit().await?;
+        Ok(undone)
+    }
+
+    /// Apply the last undone step again. Returns false if there is none.
+    pub async fn redo(&self) -> Result<bool, StorageError> {
+        let _guard = self.journal.lock().await;
+        let mut tx = self.pool.begin().await?;
+        let redone = journal::redo(&mut tx).await?;
+        tx.commit().await?;
+        Ok(redone)
+    }
+
+    pub async fn undo_state(&self) -> Result<UndoState, StorageError> {
+        let mut conn = self.pool.acquire().await?;
+        journal::state(&mut conn).await
+    }
+
+    /// Number of steps that can be undone
+    pub async fn undo_depth(&self) -> Result<u32, StorageError> {
+        Ok(self
+            .get_setting(UNDO_DEPTH_SETTING)
+            .await?
+            .and_then(|depth| depth.parse().ok())
+            .unwrap_or(DEFAULT_UNDO_DEPTH))
+    }
+
+    /// Change the number of steps kept, dropping older ones right away
+    pub async fn set_undo_depth(&self, depth: u32) -> Result<(), StorageError> {
+        self.set_setting(UNDO_DEPTH_SETTING, &depth.to_string()).await?;
+        let _guard = self.journal.lock().await;
+        let mut tx = self.pool.begin().await?;
+        let unused = journal::trim(&mut tx, depth).await?;
+        Self::delete_unused_contents(&mut tx, &unused).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Store a snapshot of the current whiteboard. Automatic snapshots are
+    /// skipped (None) when nothing changed since the last snapshot.
+    pub async fn create_snapshot(
+        &self,
+        label: Option<&str>,
+        automatic: bool,
+    ) -> Result<Option<SnapshotInfo>, StorageError> {
+        let data = SnapshotData::from_whiteboard(&self.load_whiteboard().await?);
+        let crypto = self.crypto().await?;
+        let json = Zeroizing::new(serde_json::to_string(&data)?);
+        let fingerprint = crypto.fingerprint(json.as_bytes());
+
+        let mut tx = self.pool.begin().await?;
+        if automatic {
+            let last = sqlx::query("SELECT fingerprint FROM whiteboard_snapshots ORDER BY created_at DESC LIMIT 1")
+                .fetch_optional(&mut *tx)
+                .await?;
+            if last.is_some_and(|row| row.get::<String, _>("fingerprint") == fingerprint) {
+                return Ok(None);
+            }
+        }
+
+        let info = SnapshotInfo {
+            id: Uuid::new_v4(),
+            label: label.map(str::to_string),
+            automatic,
+            group_count: data.groups.len() as u32,
+            item_count: data.items.len() as u32,
+            created_at: Utc::now(),
+        };
+        sqlx::query(
+            r#"
+            INSERT INTO whiteboard_snapshots
+            (id, label, automatic, fingerprint, group_count, item_count, data, created_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(info.id.to_string())
+        .bind(label.map(|l| crypto.encrypt_text(l)).transpose()?)
+        .bind(automatic)
+        .bind(fingerprint)
+        .bind(info.group_count as i64)
+        .bind(info.item_count as i64)
+        .bind(crypto.encrypt_text(&json)?)
+        .bind(info.created_at.to_rfc3339())
+        .execute(&mut *tx)
+        .await?;
+        for item in &data.items {
+            sqlx::query("INSERT OR IGNORE INTO snapshot_contents (snapshot_id, content_id) VALUES (?, ?)")
+                .bind(info.id.to_string())
+                .bind(item.content_id.to_string())
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+        Ok(Some(info))
+    }
+
+    /// Snapshots, newest first
+    pub async fn list_snapshots(&self) -> Result<Vec<SnapshotInfo>, StorageError> {
+        let crypto = self.crypto().await?;
+        let rows = sqlx::query(
+            "SELECT id, label, automatic, group_count, item_count, created_at FROM whiteboard_snapshots ORDER BY created_at DESC",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .iter()
+            .map(|row| SnapshotInfo {
+                id: Uuid::parse_str(row.get("id")).unwrap_or_default(),
+                label: row
+                    .get::<Option<String>, _>("label")
+                    .and_then(|l| crypto.decrypt_text(&l).ok()),
+                automatic: row.get("automatic"),
+                group_count: row.get::<i64, _>("group_count") as u32,
+                item_count: row.get::<i64, _>("item_count") as u32,
+                created_at: chrono::DateTime::parse_from_rfc3339(row.get("created_at"))
+                    .map(|dt| dt.with_timezone(&chrono::Utc))
+                    .unwrap_or_else(|_| chrono::Utc::now()),
+            })
+            .collect())
+    }
+
+    pub async fn load_snapshot(&self, id: Uuid) -> Result<Option<SnapshotData>, StorageError> {
+        let crypto = self.crypto().await?;
+        let row = sqlx::query("SELECT data FROM whiteboard_snapshots WHERE id = ?")
+            .bind(id.to_string())
+            .fetch_optional(&self.pool)
+            .await?;
+        let Some(row) = row else {
+            return Ok(None);
+        };
+        let json = Zeroizing::new(crypto.decrypt_text(row.get("data"))?);
+        Ok(Some(serde_json::from_str(&json)?))
+    }
+
+    pub async fn last_snapshot_at(&self) -> Result<Option<DateTime<Utc>>, StorageError> {
+        let row = sqlx::query("SELECT MAX(created_at) AS created_at FROM whiteboard_snapshots")
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(row
+            .get::<Option<String>, _>("created_at")
+            .and_then(|at| chrono::DateTime::parse_from_rfc3339(&at).ok())
+            .map(|at| at.with_timezone(&chrono::Utc)))
+    }
+
+    pub async fn delete_snapshot(&self, id: Uuid) -> Result<(), StorageError> {
+        let mut tx = self.pool.begin().await?;
+        let content_ids = Self::delete_snapshot_rows(&mut tx, &id.to_string()).await?;
+        Self::delete_unused_contents(&mut tx, &content_ids).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Delete automatic snapshots beyond the newest `keep`, returning how many
+    pub async fn prune_snapshots(&self, keep: u32) -> Result<u64, StorageError> {
+        let mut tx = self.pool.begin().await?;
+        let rows = sqlx::query(
+            "SELECT id FROM whiteboard_snapshots WHERE automatic = 1 ORDER BY created_at DESC LIMIT -1 OFFSET ?",
+        )
+        .bind(keep as i64)
+        .fetch_all(&mut *tx)
+        .await?;
+        let mut content_ids = Vec::new();
+        for row in &rows {
+            content_ids.extend(Self::delete_snapshot_rows(&mut tx, row.get("id")).await?);
+        }
+        Self::delete_unused_contents(&mut tx, &content_ids).await?;
+        tx.commit().await?;
+        Ok(rows.len() as u64)
+    }
+
+    /// Delete a snapshot, returning the content ids it kept
+    async fn delete_snapshot_rows(conn: &mut SqliteConnection, id: &str) -> Result<Vec<String>, StorageError> {
+        let rows = sqlx::query("SELECT content_id FROM snapshot_contents WHERE snapshot_id = ?")
+            .bind(id)
+            .fetch_all(&mut *conn)
+            .await?;
+        for statement in [
+            "DELETE FROM whiteboard_snapshots WHERE id = ?",
+            "DELETE FROM snapshot_contents WHERE snapshot_id = ?",
+        ] {
+            sqlx::query(statement).bind(id).execute(&mut *conn).await?;
+        }
+        Ok(rows.iter().map(|r| r.get("content_id")).collect())
+    }
+
+    /// Put the whiteboard, or only `group` with everything below it, back the way
+    /// it was in a snapshot. Items and groups added since are deleted. A restored
+    /// group goes to the top level if its parent is gone, and shortcuts taken in
+    /// the meantime are dropped. Returns false if the snapshot or group is not found.
+    pub async fn restore_snapshot(&self, id: Uuid, group: Option<Uuid>) -> Result<bool, StorageError> {
+        let Some(snapshot) = self.load_snapshot(id).await? else {
+            return Ok(false);
+        };
+        let mut restored = match group {
+            Some(group) => match snapshot.subtree(group) {
+                Some(subtree) => subtree,
+                None => return Ok(false),
+            },
+            None => snapshot,
+        };
+
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("PRAGMA defer_foreign_keys = ON").execute(&mut *tx).await?;
+
+        // Rows the snapshot replaces
+        let (old_groups, old_items) = match group {
+            Some(group) => {
+                let groups = Self::subtree_groups(&mut tx, group).await?;
+                let mut items = Vec::new();
+                for g in &groups {
+                    items.extend(
+                        sqlx::query("SELECT id, content_id FROM whiteboard_items WHERE parent_group_id = ? AND trash_id IS NULL")
+                            .bind(g)
+                            .fetch_all(&mut *tx)
+                            .await?,
+                    );
+                }
+                (groups, items)
+            }
+            None => (
+                sqlx::query("SELECT id FROM groups WHERE trash_id IS NULL")
+                    .fetch_all(&mut *tx)
+                    .await?
+                    .iter()
+                    .map(|r| r.get("id"))
+                    .collect(),
+                sqlx::query("SELECT id, content_id FROM whiteboard_items WHERE trash_id IS NULL")
+                    .fetch_all(&mut *tx)
+                    .await?,
+            ),
+        };
+
+        let group_ids: HashSet<String> = restored.groups.iter().map(|g| g.id.to_string()).collect();
+        let item_ids: HashSet<String> = restored.items.iter().map(|i| i.id.to_string()).collect();
+        let mut content_ids = Vec::new();
+        for row in &old_items {
+            let item_id: String = row.get("id");
+            if !item_ids.contains(&item_id) {
+                sqlx::query("DELETE FROM whiteboard_items WHERE id = ?")
+                    .bind(&item_id)
+                    .execute(&mut *tx)
+                    .await?;
+                content_ids.push(row.get::<String, _>("content_id"));
+            }
+        }
+        for old_group in old_groups.iter().filter(|g| !group_ids.contains(*g)) {
+            sqlx::query("DELETE FROM groups WHERE id = ?")
+                .bind(old_group)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        // Shortcuts of the rows staying as they are
+        let mut taken = HashSet::new();
+        for (table, restored_ids) in [("whiteboard_items", &item_ids), ("groups", &group_ids)] {
+            let rows = sqlx::query(&format!(
+                "SELECT id, shortcut FROM {} WHERE trash_id IS NULL AND shortcut IS NOT NULL",
+                table
+            ))
+            .fetch_all(&mut *tx)
+            .await?;
+            for row in rows {
+                if !restored_ids.contains(&row.get::<String, _>("id")) {
+                    taken.insert(row.get::<String, _>("shortcut"));
+                }
+            }
+        }
+
+        if let Some(root) = restored.groups.iter_mut().find(|g| Some(g.id) == group) {
+            if let Some(parent) = root.parent_group {
+                let parent_exists = sqlx::query("SELECT 1 FROM groups WHERE id = ? AND trash_id IS NULL")
+                    .bind(parent.to_string())
+                    .fetch_optional(&mut *tx)
+                    .await?
+                    .is_some();
+                if !parent_exists {
+                    root.parent_group = None;
+                }
+            }
+        }
+
+        let now = Utc::now().to_rfc3339();
+        for g in &restored.groups {
+            let shortcut = g.shortcut.as_ref().filter(|s| !taken.contains(*s));
+            sqlx::query(
+                r#"
+                INSERT INTO groups
+                (id, name, position_x, position_y, collapsed, parent_group_id, shortcut, color, created_at, updated_at, trash_id)
+                VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, NULL)
+                ON CONFLICT(id) DO UPDATE SET
+                    name = excluded.name, position_x = excluded.position_x, position_y = excluded.position_y,
+                    collapsed = excluded.collapsed, parent_group_id = excluded.parent_group_id,
+                    shortcut = excluded.shortcut, color = excluded.color, updated_at = excluded.updated_at,
+                    trash_id = NULL
+                "#,
+            )
+            .bind(g.id.to_string())
+            .bind(&g.name)
+            .bind(g.position.x)
+            .bind(g.position.y)
+            .bind(g.collapsed)
+            .bind(g.parent_group.map(|id| id.to_string()))
             .bind(shortcut)
+            .bind(&g.color)
+            .bind(g.created_at.to_rfc3339())
+            .bind(&now)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        for item in &restored.items {
+            let content_exists = sqlx::query("SELECT 1 FROM clipboard_contents WHERE id = ?")
+                .bind(item.content_id.to_string())
+                .fetch_optional(&mut *tx)
+                .await?
+                .is_some();
+            if !content_exists {
+                continue;
+            }
+            let shortcut = item.shortcut.as_ref().filter(|s| !taken.contains(*s));
+            sqlx::query(
+                r#"
+                INSERT INTO whiteboard_items
+                (id, content_id, position_x, position_y, width, height, parent_group_id, shortcut, label, created_at, updated_at, trash_id)
+                VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, NULL)
+                ON CONFLICT(id) DO UPDATE SET
+                    content_id = excluded.content_id, position_x = excluded.position_x, position_y = excluded.position_y,
+                    width = excluded.width, height = excluded.height, parent_group_id = excluded.parent_group_id,
+                    shortcut = excluded.shortcut, label = excluded.label, updated_at = excluded.updated_at,
+                    trash_id = NULL
+                "#,
+            )
+            .bind(item.id.to_string())
+            .bind(item.content_id.to_string())
+            .bind(item.position.x)
+            .bind(item.position.y)
+            .bind(item.size.width)
+            .bind(item.size.height)
+            .bind(item.parent_group.map(|id| id.to_string()))
+            .bind(shortcut)
+            .bind(&item.label)
+            .bind(item.created_at.to_rfc3339())
+            .bind(&now)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        Self::delete_unused_contents(&mut tx, &content_ids).await?;
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    pub async fn snapshot_policy(&self) -> Result<SnapshotPolicy, StorageError> {
+        Ok(match self.get_setting(SNAPSHOT_POLICY_SETTING).await? {
+            Some(json) => serde_json::from_str(&json)?,
+            None => SnapshotPolicy::default(),
+        })
+    }
+
+    /// Apply `changes` to a whiteboard item in one transaction
+    pub async fn update_item(&self, id: Uuid, changes: &ItemChanges) -> Result<(), StorageError> {
+        let id = id.to_string();
+        let now = chrono::Utc::now().to_rfc3339();
+        let mut tx = self.pool.begin().await?;
+        if let Some(position) = &changes.position {
+            sqlx::query("UPDATE whiteboard_items SET position_x = ?, position_y = ?, updated_at = ? WHERE id = ?")
+                .bind(position.x)
+                .bind(position.y)
+                .bind(&now)
+                .bind(&id)
+                .execute(&mut *tx)
+                .await?;
+        }
+        if let Some(size) = &changes.size {
+            sqlx::query("UPDATE whiteboard_items SET width = ?, height = ?, updated_at = ? WHERE id = ?")
+                .bind(size.width)
+                .bind(size.height)
+                .bind(&now)
+                .bind(&id)
+                .execute(&mut *tx)
+                .await?;
+        }
+        if let Some(parent_group) = changes.parent_group {
+            sqlx::query("UPDATE whiteboard_items SET parent_group_id = ?, updated_at = ? WHERE id = ?")
+                .bind(parent_group.map(|id| id.to_string()))
+                .bind(&now)
+                .bind(&id)
+                .execute(&mut *tx)
+                .await?;
+        }
+        if let Some(shortcut) = &changes.shortcut {
+            sqlx::query("UPDATE whiteboard_items SET shortcut = ?, updated_at = ? WHERE id = ?")
+                .bind(shortcut)
+                .bind(&now)
+                .bind(&id)
+                .execute(&mut *tx)
+                .await?;
+        }
+        if let Some(label) = &changes.label {
+            sqlx::query("UPDATE whiteboard_items SET label = ?, updated_at = ? WHERE id = ?")
+                .bind(label)
+                .bind(&now)
+                .bind(&id)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Apply `changes` to a group in one transaction
+    pub async fn update_group(&self, id: Uuid, changes: &GroupChanges) -> Result<(), StorageError> {
+        let id = id.to_string();
+        let now = chrono::Utc::now().to_rfc3339();
+        let mut tx = self.pool.begin().await?;
+        if let Some(name) = &changes.name {
+            sqlx::query("UPDATE groups SET name = ?, updated_at = ? WHERE id = ?")
+                .bind(name)
+                .bind(&now)
+                .bind(&id)
+                .execute(&mut *tx)
+                .await?;
+        }
+        if let Some(position) = &changes.position {
+            sqlx::query("UPDATE groups SET position_x = ?, position_y = ?, updated_at = ? WHERE id = ?")
+                .bind(position.x)
+                .bind(position.y)
+                .bind(&now)
+                .bind(&id)
+                .execute(&mut *tx)
+                .await?;
+        }
+        if let Some(collapsed) = changes.collapsed {
+            sqlx::query("UPDATE groups SET collapsed = ?, updated_at = ? WHERE id = ?")
+                .bind(collapsed)
+                .bind(&now)
+                .bind(&id)
+                .execute(&mut *tx)
+                .await?;
+        }
+        if let Some(shortcut) = &changes.shortcut {
+            sqlx::query("UPDATE groups SET shortcut = ?, updated_at = ? WHERE id = ?")
+                .bind(shortcut)
+                .bind(&now)
+                .bind(&id)
+                .execute(&mut *tx)
+                .await?;
+        }
+        if let Some(color) = &changes.color {
+            sqlx::query("UPDATE groups SET color = ?, updated_at = ? WHERE id = ?")
+                .bind(color)
+                .bind(&now)
+                .bind(&id)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Move a group, with everything in it, under `new_parent` (None for the top
+    /// level). Its shortcut is replaced if something there already uses it.
+    /// Returns the group's shortcut after the move.
+    pub async fn move_group(&self, id: Uuid, new_parent: Option<Uuid>) -> Result<Option<String>, StorageError> {
+        let mut tx = self.pool.begin().await?;
+        let row = sqlx::query("SELECT shortcut FROM groups WHERE id = ? AND trash_id IS NULL")
+            .bind(id.to_string())
+            .fetch_optional(&mut *tx)
+            .await?
+            .ok_or(StorageError::GroupNotFound(id))?;
+        let mut shortcut: Option<String> = row.get("shortcut");
+
+        if let Some(parent) = new_parent {
+            let exists = sqlx::query("SELECT 1 FROM groups WHERE id = ? AND trash_id IS NULL")
+                .bind(parent.to_string())
+                .fetch_optional(&mut *tx)
+                .await?
+                .is_some();
+            if !exists {
+                return Err(StorageError::GroupNotFound(parent));
+            }
+            if Self::subtree_groups(&mut tx, id).await?.contains(&parent.to_string()) {
+                return Err(StorageError::GroupCycle);
+            }
+        }
+
+        // Shortcuts only need to be unique among entries with the same parent
+        if let Some(current) = &shortcut {
+            let taken = sqlx::query(
+                r#"
+                SELECT 1 FROM (
+                    SELECT shortcut, parent_group_id FROM groups WHERE trash_id IS NULL AND id != ?
+                    UNION ALL
+                    SELECT shortcut, parent_group_id FROM whiteboard_items WHERE trash_id IS NULL
+                ) WHERE shortcut = ? AND parent_group_id IS ?
+                "#,
+            )
+            .bind(id.to_string())
+            .bind(current)
+            .bind(new_parent.map(|p| p.to_string()))
+            .fetch_optional(&mut *tx)
+            .await?
+            .is_some();
+            if taken {
+                shortcut = Some(format!("g{}", Self::next_group_shortcut_number(&mut tx).await?));
+            }
+        }
+
+        sqlx::query("UPDATE groups SET parent_group_id = ?, shortcut = ?, updated_at = ? WHERE id = ?")
+            .bind(new_parent.map(|p| p.to_string()))
+            .bind(&shortcut)
             .bind(chrono::Utc::now().to_rfc3339())
             .bind(id.to_string())
-            .execute(&self.pool)
+            .execute(&mut *tx)
             .await?;
-        Ok(())
+        tx.commit().await?;
+        Ok(shortcut)
     }
 
     pub async fn get_items_with_shortcuts(&self) -> Result<Vec<(String, Uuid)>, StorageError> {
-        let rows = sqlx::query("SELECT id, shortcut FROM whiteboard_items WHERE shortcut IS NOT NULL")
+        let rows = sqlx::query("SELECT id, shortcut FROM whiteboard_items WHERE shortcut IS NOT NULL AND trash_id IS NULL")
             .fetch_all(&self.pool)
             .await?;
 
@@ -534,23 +2012,9 @@ impl PersistentStorage {
         Ok(result)
     }
 
-    pub async fn update_group_color(
-        &self,
-        id: Uuid,
-        color: Option<String>,
-    ) -> Result<(), StorageError> {
-        sqlx::query("UPDATE groups SET color = ?, updated_at = ? WHERE id = ?")
-            .bind(color)
-            .bind(chrono::Utc::now().to_rfc3339())
-            .bind(id.to_string())
-            .execute(&self.pool)
-            .await?;
-        Ok(())
-    }
-
     pub async fn get_next_shortcut_number(&self) -> Result<i32, StorageError> {
         // Get all numeric shortcuts and find the max
-        let rows = sqlx::query("SELECT shortcut FROM whiteboard_items WHERE shortcut IS NOT NULL")
+        let rows = sqlx::query("SELECT shortcut FROM whiteboard_items WHERE shortcut IS NOT NULL AND trash_id IS NULL")
             .fetch_all(&self.pool)
             .await?;
 
@@ -568,9 +2032,14 @@ impl PersistentStorage {
     }
 
     pub async fn get_next_group_shortcut_number(&self) -> Result<i32, StorageError> {
+        let mut conn = self.pool.acquire().await?;
+        Self::next_group_shortcut_number(&mut conn).await
+    }
+
+    async fn next_group_shortcut_number(conn: &mut SqliteConnection) -> Result<i32, StorageError> {
         // Get all group shortcuts with "g" prefix and find the max
-        let rows = sqlx::query("SELECT shortcut FROM groups WHERE shortcut IS NOT NULL")
-            .fetch_all(&self.pool)
+        let rows = sqlx::query("SELECT shortcut FROM groups WHERE shortcut IS NOT NULL AND trash_id IS NULL")
+            .fetch_all(conn)
             .await?;
 
         let mut max_num = 0;
@@ -607,6 +2076,160 @@ impl PersistentStorage {
         Ok(())
     }
 
+    /// Save captured content as part of the persisted history
+    pub async fn save_history_entry(&self, content: &ClipboardContent) -> Result<(), StorageError> {
+        self.save_clipboard_content(content).await?;
+
+        sqlx::query("UPDATE clipboard_contents SET in_history = 1 WHERE id = ?")
+            .bind(content.id.to_string())
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Count `content` as another copy of the persisted history entry with the
+    /// same content, taking over its id, first copy time and copy count. Returns
+    /// false if there is none.
+    pub async fn merge_history_entry(&self, content: &mut ClipboardContent) -> Result<bool, StorageError> {
+        let crypto = self.crypto().await?;
+        let row = sqlx::query(
+            r#"
+            SELECT id, copied_at, copy_count FROM clipboard_contents
+            WHERE content_hash = ? AND in_history = 1
+            ORDER BY COALESCE(last_copied_at, copied_at) DESC
+            LIMIT 1
+            "#,
+        )
+        .bind(Self::stored_hash(&crypto, &content.category, &content.content_hash()))
+        .fetch_optional(&self.pool)
+        .await?;
+        let Some(row) = row else {
+            return Ok(false);
+        };
+        let Ok(id) = Uuid::parse_str(row.get("id")) else {
+            return Ok(false);
+        };
+
+        content.id = id;
+        if let Ok(copied_at) = chrono::DateTime::parse_from_rfc3339(row.get("copied_at")) {
+            content.copied_at = copied_at.with_timezone(&chrono::Utc);
+        }
+        content.copy_count = row.get::<i64, _>("copy_count") as u32 + 1;
+        Ok(true)
+    }
+
+    /// Load the most recent persisted history entries, newest first
+    pub async fn load_history(&self, limit: i64) -> Result<Vec<ClipboardContent>, StorageError> {
+        let crypto = self.crypto().await?;
+        let rows = sqlx::query(
+            r#"
+            SELECT id, category, data_type, text_content, text_preview, rich_content,
+                image_width, image_height, image_format, image_thumbnail, image_preview,
+                source, source_title, copied_at, copy_count, last_copied_at, b.data AS image_data
+            FROM clipboard_contents
+            LEFT JOIN blobs b ON b.hash = image_blob
+            WHERE in_history = 1
+            ORDER BY COALESCE(last_copied_at, copied_at) DESC
+            LIMIT ?
+            "#,
+        )
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .iter()
+            .filter_map(|row| {
+                Self::content_from_row(&crypto, row, "id")
+                    .inspect_err(|_| {
+                        eprintln!("Skipping history entry {} that cannot be decrypted", row.get::<String, _>("id"))
+                    })
+                    .ok()
+            })
+            .collect())
+    }
+
+    /// Whiteboard item holding content with the given hash (as returned by
+    /// `ClipboardContent::content_hash`), if any
+    pub async fn find_whiteboard_item_by_hash(&self, hash: &str) -> Result<Option<Uuid>, StorageError> {
+        let crypto = self.crypto().await?;
+        let row = sqlx::query(
+            r#"
+            SELECT wi.id FROM whiteboard_items wi
+            JOIN clipboard_contents cc ON wi.content_id = cc.id
+            WHERE cc.content_hash IN (?, ?) AND wi.trash_id IS NULL
+            LIMIT 1
+            "#,
+        )
+        // Stored plain, or keyed for Secure contents
+        .bind(hash)
+        .bind(Self::stored_hash(&crypto, &Category::Secure, hash))
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.and_then(|r| Uuid::parse_str(r.get("id")).ok()))
+    }
+
+    /// Load a single clipboard content by id
+    pub async fn load_clipboard_content(
+        &self,
+        id: Uuid,
+    ) -> Result<Option<ClipboardContent>, StorageError> {
+        let crypto = self.crypto().await?;
+        let row = sqlx::query(
+            r#"
+            SELECT id, category, data_type, text_content, text_preview, rich_content,
+                image_width, image_height, image_format, image_thumbnail, image_preview,
+                source, source_title, copied_at, copy_count, last_copied_at, b.data AS image_data
+            FROM clipboard_contents
+            LEFT JOIN blobs b ON b.hash = image_blob
+            WHERE id = ?
+            "#,
+        )
+        .bind(id.to_string())
+        .fetch_optional(&self.pool)
+        .await?;
+
+        row.map(|row| Self::content_from_row(&crypto, &row, "id")).transpose()
+    }
+
+    /// Remove history entries of a category last copied before `cutoff`.
+    /// Entries pinned to the whiteboard are kept but no longer count as history.
+    pub async fn expire_history(
+        &self,
+        category: &Category,
+        cutoff: DateTime<Utc>,
+    ) -> Result<u64, StorageError> {
+        let mut tx = self.pool.begin().await?;
+
+        let result = sqlx::query(&format!(
+            r#"
+            DELETE FROM clipboard_contents
+            WHERE in_history = 1 AND category = ? AND COALESCE(last_copied_at, copied_at) < ?
+            AND id NOT IN (SELECT content_id FROM whiteboard_items)
+            AND id NOT IN ({})
+            AND id NOT IN ({})
+            "#,
+            JOURNALED_CONTENT_IDS, SNAPSHOT_CONTENT_IDS
+        ))
+        .bind(category.to_string())
+        .bind(cutoff.to_rfc3339())
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query(
+            "UPDATE clipboard_contents SET in_history = 0 WHERE in_history = 1 AND category = ? AND COALESCE(last_copied_at, copied_at) < ?",
+        )
+        .bind(category.to_string())
+        .bind(cutoff.to_rfc3339())
+        .execute(&mut *tx)
+        .await?;
+
+        Self::prune_blobs(&mut tx).await?;
+        tx.commit().await?;
+        Ok(result.rows_affected())
+    }
+
     /// Clean up old clipboard entries that are not referenced by whiteboard items.
     /// Keeps entries from the last `days` days.
     pub async fn cleanup_old_entries(&self, days: i64) -> Result<u64, StorageError> {
@@ -616,17 +2239,710 @@ impl PersistentStorage {
         // Delete clipboard contents that are:
         // 1. Older than the cutoff date
         // 2. Not referenced by any whiteboard item
-        let result = sqlx::query(
+        let result = sqlx::query(&format!(
             r#"
             DELETE FROM clipboard_contents
-            WHERE copied_at < ?
+            WHERE COALESCE(last_copied_at, copied_at) < ?
             AND id NOT IN (SELECT content_id FROM whiteboard_items)
+            AND id NOT IN ({})
+            AND id NOT IN ({})
             "#,
-        )
+            JOURNALED_CONTENT_IDS, SNAPSHOT_CONTENT_IDS
+        ))
         .bind(&cutoff_str)
         .execute(&self.pool)
         .await?;
 
+        let mut conn = self.pool.acquire().await?;
+        Self::prune_blobs(&mut conn).await?;
+
         Ok(result.rows_affected())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::to_hex;
+    use sha2::{Digest, Sha256};
+
+    async fn memory_storage() -> PersistentStorage {
+        // A single connection, otherwise every connection gets its own empty database
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        PersistentStorage::with_pool(pool, std::env::temp_dir()).await.unwrap()
+    }
+
+    fn copied_days_ago(text: &str, category: Category, days: i64) -> ClipboardContent {
+        let mut content = ClipboardContent::new_text(text.to_string(), category);
+        content.copied_at = Utc::now() - chrono::Duration::days(days);
+        content.last_copied_at = content.copied_at;
+        content
+    }
+
+    #[tokio::test]
+    async fn test_history_round_trip() {
+        let storage = memory_storage().await;
+
+        let older = copied_days_ago("older", Category::Text, 2);
+        let newer = copied_days_ago("newer", Category::Url, 1);
+        storage.save_history_entry(&older).await.unwrap();
+        storage.save_history_entry(&newer).await.unwrap();
+
+        // Contents saved only for the whiteboard are not history
+        let pinned = ClipboardContent::new_text("pinned".to_string(), Category::Text);
+        storage.save_clipboard_content(&pinned).await.unwrap();
+
+        let history = storage.load_history(10).await.unwrap();
+        let ids: Vec<Uuid> = history.iter().map(|c| c.id).collect();
+        assert_eq!(ids, vec![newer.id, older.id]);
+        assert_eq!(history[0].data.text(), Some("newer"));
+
+        // Copying an entry again continues it instead of starting a new one
+        let mut again = ClipboardContent::new_text("older".to_string(), Category::Text);
+        assert!(storage.merge_history_entry(&mut again).await.unwrap());
+        assert_eq!((again.id, again.copied_at, again.copy_count), (older.id, older.copied_at, 2));
+        storage.save_history_entry(&again).await.unwrap();
+        assert_eq!(storage.load_history(10).await.unwrap()[0].id, older.id);
+
+        let mut copy = ClipboardContent::new_text("pinned".to_string(), Category::Text);
+        assert!(!storage.merge_history_entry(&mut copy).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_undecryptable_contents_are_not_loaded_blank() {
+        let storage = memory_storage().await;
+        let good = copied_days_ago("good", Category::Text, 2);
+        let bad = copied_days_ago("bad", Category::Text, 1);
+        storage.save_history_entry(&good).await.unwrap();
+        storage.save_history_entry(&bad).await.unwrap();
+        storage.save_whiteboard_item(&WhiteboardItem::new(bad.clone(), Position { x: 0.0, y: 0.0 })).await.unwrap();
+        sqlx::query("UPDATE clipboard_contents SET text_content = 'garbage' WHERE id = ?")
+            .bind(bad.id.to_string())
+            .execute(&storage.pool)
+            .await
+            .unwrap();
+
+        let history = storage.load_history(10).await.unwrap();
+        assert_eq!(history.iter().map(|c| c.id).collect::<Vec<_>>(), vec![good.id]);
+        assert!(storage.load_whiteboard().await.unwrap().items.is_empty());
+        assert!(storage.load_clipboard_content(bad.id).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_expire_history_keeps_whiteboard_items() {
+        let storage = memory_storage().await;
+
+        let expired = copied_days_ago("expired", Category::Text, 40);
+        let fresh = copied_days_ago("fresh", Category::Text, 1);
+        let other_category = copied_days_ago("other", Category::Url, 40);
+        for content in [&expired, &fresh, &other_category] {
+            storage.save_history_entry(content).await.unwrap();
+        }
+
+        let pinned = copied_days_ago("pinned", Category::Text, 40);
+        storage.save_history_entry(&pinned).await.unwrap();
+        let item = WhiteboardItem::new(pinned.clone(), Position { x: 0.0, y: 0.0 });
+        storage.save_whiteboard_item(&item).await.unwrap();
+
+        let cutoff = Utc::now() - chrono::Duration::days(30);
+        let removed = storage.expire_history(&Category::Text, cutoff).await.unwrap();
+        assert_eq!(removed, 1);
+
+        let ids: Vec<Uuid> = storage
+            .load_history(10)
+            .await
+            .unwrap()
+            .iter()
+            .map(|c| c.id)
+            .collect();
+        assert_eq!(ids, vec![fresh.id, other_category.id]);
+
+        // The pinned content survives on the whiteboard
+        let whiteboard = storage.load_whiteboard().await.unwrap();
+        assert_eq!(whiteboard.items[&item.id].content.data.text(), Some("pinned"));
+    }
+
+    fn png_image(width: u32, height: u32) -> ClipboardContent {
+        let img = image::RgbaImage::from_pixel(width, height, image::Rgba([10, 20, 30, 255]));
+        let mut png_bytes = Vec::new();
+        img.write_to(&mut std::io::Cursor::new(&mut png_bytes), image::ImageFormat::Png)
+            .unwrap();
+        ClipboardContent::new_image(STANDARD.encode(&png_bytes), width, height, "png".to_string())
+    }
+
+    fn image_base64(content: &ClipboardContent) -> Option<String> {
+        match &content.data {
+            ClipboardData::Image { base64, .. } => Some(base64.clone()),
+            _ => None,
+        }
+    }
+
+    async fn blob_count(storage: &PersistentStorage) -> i64 {
+        sqlx::query("SELECT COUNT(*) AS n FROM blobs")
+            .fetch_one(&storage.pool)
+            .await
+            .unwrap()
+            .get("n")
+    }
+
+    #[tokio::test]
+    async fn test_images_are_deduplicated_blobs() {
+        let storage = memory_storage().await;
+
+        let first = png_image(600, 300);
+        let mut second = first.clone();
+        second.id = Uuid::new_v4();
+        storage.save_history_entry(&first).await.unwrap();
+        storage.save_history_entry(&second).await.unwrap();
+        assert_eq!(blob_count(&storage).await, 1);
+
+        // Not addressed by a plain hash of the image
+        let png = STANDARD.decode(image_base64(&first).unwrap()).unwrap();
+        let hash: String = sqlx::query("SELECT hash FROM blobs")
+            .fetch_one(&storage.pool)
+            .await
+            .unwrap()
+            .get("hash");
+        assert_ne!(hash, to_hex(&Sha256::digest(&png)));
+        assert_eq!(hash, storage.crypto().await.unwrap().fingerprint(&png));
+
+        let loaded = storage.load_clipboard_content(second.id).await.unwrap().unwrap();
+        assert_eq!(image_base64(&loaded), image_base64(&first));
+
+        // On the whiteboard the original is left out while a preview exists
+        let item = WhiteboardItem::new(loaded, Position { x: 0.0, y: 0.0 });
+        storage.save_whiteboard_item(&item).await.unwrap();
+        let whiteboard = storage.load_whiteboard().await.unwrap();
+        let lazy = whiteboard.items[&item.id].content.clone();
+        assert_eq!(image_base64(&lazy), Some(String::new()));
+
+        // Saving the lazily loaded content keeps the stored image
+        storage.save_clipboard_content(&lazy).await.unwrap();
+        let reloaded = storage.load_clipboard_content(second.id).await.unwrap().unwrap();
+        assert_eq!(image_base64(&reloaded), image_base64(&first));
+
+        // Blobs go away with the last reference
+        storage.expire_history(&Category::Image, Utc::now() + chrono::Duration::days(1)).await.unwrap();
+        assert_eq!(blob_count(&storage).await, 1);
+        let trash_id = storage.delete_whiteboard_item(item.id).await.unwrap().unwrap();
+        assert_eq!(blob_count(&storage).await, 1);
+        storage.delete_from_trash(trash_id).await.unwrap();
+        sqlx::query("UPDATE clipboard_contents SET in_history = 1")
+            .execute(&storage.pool)
+            .await
+            .unwrap();
+        storage.expire_history(&Category::Image, Utc::now() + chrono::Duration::days(1)).await.unwrap();
+        assert_eq!(blob_count(&storage).await, 0);
+    }
+
+    fn group_in(name: &str, parent: Option<&Group>) -> Group {
+        let mut group = Group::new(name.to_string(), Position { x: 0.0, y: 0.0 });
+        group.parent_group = parent.map(|p| p.id);
+        group
+    }
+
+    fn item_in(text: &str, group: &Group) -> WhiteboardItem {
+        let content = ClipboardContent::new_text(text.to_string(), Category::Text);
+        let mut item = WhiteboardItem::new(content, Position { x: 0.0, y: 0.0 });
+        item.parent_group = Some(group.id);
+        item
+    }
+
+    #[tokio::test]
+    async fn test_delete_group_modes() {
+        let storage = memory_storage().await;
+        let top = group_in("top", None);
+        let middle = group_in("middle", Some(&top));
+        let inner = group_in("inner", Some(&middle));
+        let item = item_in("in middle", &middle);
+        let nested = item_in("in inner", &inner);
+        for group in [&top, &middle, &inner] {
+            storage.save_group(group).await.unwrap();
+        }
+        storage.save_whiteboard_item(&item).await.unwrap();
+        storage.save_whiteboard_item(&nested).await.unwrap();
+
+        // Reparent moves the children up to the deleted group's parent
+        storage.delete_group(middle.id, DeleteMode::Reparent).await.unwrap();
+        let whiteboard = storage.load_whiteboard().await.unwrap();
+        assert!(!whiteboard.groups.contains_key(&middle.id));
+        assert_eq!(whiteboard.groups[&inner.id].parent_group, Some(top.id));
+        assert_eq!(whiteboard.items[&item.id].parent_group, Some(top.id));
+
+        // Cascade removes everything below, contents included
+        storage.delete_group(top.id, DeleteMode::Cascade).await.unwrap();
+        let whiteboard = storage.load_whiteboard().await.unwrap();
+        assert!(whiteboard.groups.is_empty());
+        assert!(whiteboard.items.is_empty());
+        assert!(storage.load_clipboard_content(nested.content.id).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_trash_restore_and_purge() {
+        let storage = memory_storage().await;
+        let top = group_in("top", None);
+        let inner = group_in("inner", Some(&top));
+        let mut item = item_in("keep me", &inner);
+        item.shortcut = Some("1".to_string());
+        storage.save_group(&top).await.unwrap();
+        storage.save_group(&inner).await.unwrap();
+        storage.save_whiteboard_item(&item).await.unwrap();
+
+        let trash_id = storage.delete_group(top.id, DeleteMode::Trash).await.unwrap().unwrap();
+        assert!(storage.load_whiteboard().await.unwrap().groups.is_empty());
+        let trash = storage.list_trash().await.unwrap();
+        assert_eq!(trash.len(), 1);
+        assert_eq!((trash[0].kind, trash[0].group_count, trash[0].item_count), (TrashKind::Group, 2, 1));
+        assert_eq!(trash[0].name.as_deref(), Some("top"));
+
+        // A shortcut taken in the meantime stays with the newer item
+        let mut newer = WhiteboardItem::new(
+            ClipboardContent::new_text("newer".to_string(), Category::Text),
+            Position { x: 0.0, y: 0.0 },
+        );
+        newer.shortcut = Some("1".to_string());
+        storage.save_whiteboard_item(&newer).await.unwrap();
+
+        assert!(storage.restore_from_trash(trash_id).await.unwrap());
+        let whiteboard = storage.load_whiteboard().await.unwrap();
+        assert_eq!(whiteboard.groups.len(), 2);
+        assert_eq!(whiteboard.items[&item.id].parent_group, Some(inner.id));
+        assert_eq!(whiteboard.items[&item.id].shortcut, None);
+        assert!(storage.list_trash().await.unwrap().is_empty());
+
+        // Items deleted on their own are restored to the top level once their group is gone
+        let item_trash = storage.delete_whiteboard_item(item.id).await.unwrap().unwrap();
+        storage.delete_group(top.id, DeleteMode::Cascade).await.unwrap();
+        let orphans: i64 = sqlx::query(
+            "SELECT COUNT(*) AS n FROM whiteboard_items WHERE parent_group_id IS NOT NULL AND parent_group_id NOT IN (SELECT id FROM groups)",
+        )
+        .fetch_one(&storage.pool)
+        .await
+        .unwrap()
+        .get("n");
+        assert_eq!(orphans, 0);
+        assert!(storage.restore_from_trash(item_trash).await.unwrap());
+        let whiteboard = storage.load_whiteboard().await.unwrap();
+        assert_eq!(whiteboard.items[&item.id].parent_group, None);
+        assert!(whiteboard.root_items.contains(&item.id));
+
+        // Purging deletes expired entries for good
+        storage.delete_whiteboard_item(item.id).await.unwrap();
+        assert_eq!(storage.purge_trash(Utc::now() - chrono::Duration::days(1)).await.unwrap(), 0);
+        assert_eq!(storage.purge_trash(Utc::now() + chrono::Duration::seconds(1)).await.unwrap(), 1);
+        assert!(storage.list_trash().await.unwrap().is_empty());
+        assert!(storage.load_clipboard_content(item.content.id).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_purge_during_step_is_not_recorded() {
+        let storage = memory_storage().await;
+        let group = group_in("work", None);
+        let item = item_in("old", &group);
+        storage.save_group(&group).await.unwrap();
+        storage.save_whiteboard_item(&item).await.unwrap();
+        storage.delete_whiteboard_item(item.id).await.unwrap();
+        storage.set_setting(TRASH_RETENTION_SETTING, "0").await.unwrap();
+
+        let step = storage.begin_step("Rename group").await.unwrap();
+        let (purged, _) = tokio::join!(storage.purge_expired_trash(), async {
+            tokio::task::yield_now().await;
+            storage.save_group(&Group { name: "home".to_string(), ..group.clone() }).await.unwrap();
+            step.commit().await.unwrap();
+        });
+        assert_eq!(purged.unwrap(), 1);
+
+        // Undoing the step does not bring the purged entry back
+        assert!(storage.undo().await.unwrap());
+        let whiteboard = storage.load_whiteboard().await.unwrap();
+        assert_eq!(whiteboard.groups[&group.id].name, "work");
+        assert!(storage.list_trash().await.unwrap().is_empty());
+        assert!(!storage.undo().await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_undo_and_redo_steps() {
+        let storage = memory_storage().await;
+        let group = group_in("work", None);
+        let item = item_in("note", &group);
+
+        // Several writes make up one step
+        let step = storage.begin_step("Import").await.unwrap();
+        storage.save_group(&group).await.unwrap();
+        storage.save_whiteboard_item(&item).await.unwrap();
+        step.commit().await.unwrap();
+
+        let step = storage.begin_step("Delete group").await.unwrap();
+        storage.delete_group(group.id, DeleteMode::Cascade).await.unwrap();
+        step.commit().await.unwrap();
+        assert!(storage.load_whiteboard().await.unwrap().items.is_empty());
+
+        // The content of the deleted item is kept while the step can be undone
+        let state = storage.undo_state().await.unwrap();
+        assert_eq!((state.undo.as_deref(), state.redo), (Some("Delete group"), None));
+        assert!(storage.undo().await.unwrap());
+        let whiteboard = storage.load_whiteboard().await.unwrap();
+        assert_eq!(whiteboard.items[&item.id].parent_group, Some(group.id));
+        assert_eq!(whiteboard.items[&item.id].content.data.text(), Some("note"));
+
+        assert!(storage.undo().await.unwrap());
+        assert!(!storage.undo().await.unwrap());
+        let whiteboard = storage.load_whiteboard().await.unwrap();
+        assert!(whiteboard.groups.is_empty() && whiteboard.items.is_empty());
+
+        assert!(storage.redo().await.unwrap());
+        assert_eq!(storage.load_whiteboard().await.unwrap().items.len(), 1);
+        assert_eq!(storage.undo_state().await.unwrap().redo.as_deref(), Some("Delete group"));
+
+        // A new step replaces the redo, and old steps fall off beyond the depth
+        let step = storage.begin_step("Rename group").await.unwrap();
+        storage.save_group(&Group { name: "home".to_string(), ..group.clone() }).await.unwrap();
+        step.commit().await.unwrap();
+        assert_eq!(storage.undo_state().await.unwrap().redo, None);
+
+        storage.set_undo_depth(1).await.unwrap();
+        assert!(storage.undo().await.unwrap());
+        assert!(!storage.undo().await.unwrap());
+        assert_eq!(storage.load_whiteboard().await.unwrap().groups[&group.id].name, "work");
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_diff_and_restore() {
+        use crate::storage::snapshot::ChangeKind;
+
+        let storage = memory_storage().await;
+        let top = group_in("top", None);
+        let inner = group_in("inner", Some(&top));
+        let mut item = item_in("keep me", &inner);
+        item.shortcut = Some("1".to_string());
+        storage.save_group(&top).await.unwrap();
+        storage.save_group(&inner).await.unwrap();
+        storage.save_whiteboard_item(&item).await.unwrap();
+
+        let info = storage.create_snapshot(Some("before"), false).await.unwrap().unwrap();
+        assert_eq!((info.group_count, info.item_count), (2, 1));
+        // Nothing changed, so no automatic snapshot
+        assert!(storage.create_snapshot(None, true).await.unwrap().is_none());
+
+        storage.save_group(&Group { name: "renamed".to_string(), ..top.clone() }).await.unwrap();
+        storage.delete_group(inner.id, DeleteMode::Cascade).await.unwrap();
+        let mut newer = item_in("newer", &top);
+        newer.shortcut = Some("1".to_string());
+        storage.save_whiteboard_item(&newer).await.unwrap();
+
+        let snapshot = storage.load_snapshot(info.id).await.unwrap().unwrap();
+        let current = SnapshotData::from_whiteboard(&storage.load_whiteboard().await.unwrap());
+        let diff = snapshot.diff(&current);
+        let kinds = |changes: &[crate::storage::snapshot::EntryChange]| {
+            let mut kinds: Vec<_> = changes.iter().map(|c| (c.name.clone(), c.kind)).collect();
+            kinds.sort_by(|a, b| a.0.cmp(&b.0));
+            kinds
+        };
+        assert_eq!(
+            kinds(&diff.groups),
+            vec![("inner".to_string(), ChangeKind::Removed), ("renamed".to_string(), ChangeKind::Changed)]
+        );
+        assert_eq!(diff.groups.iter().find(|c| c.kind == ChangeKind::Changed).unwrap().fields, vec!["name"]);
+        assert_eq!(
+            kinds(&diff.items),
+            vec![("keep me".to_string(), ChangeKind::Removed), ("newer".to_string(), ChangeKind::Added)]
+        );
+
+        // A single group comes back with its items; the shortcut stays with the newer item
+        assert!(storage.restore_snapshot(info.id, Some(inner.id)).await.unwrap());
+        let whiteboard = storage.load_whiteboard().await.unwrap();
+        assert_eq!(whiteboard.groups[&top.id].name, "renamed");
+        assert_eq!(whiteboard.items[&item.id].content.data.text(), Some("keep me"));
+        assert_eq!(whiteboard.items[&item.id].shortcut, None);
+        assert!(whiteboard.items.contains_key(&newer.id));
+
+        // Restoring everything also drops what was added since
+        assert!(storage.restore_snapshot(info.id, None).await.unwrap());
+        let whiteboard = storage.load_whiteboard().await.unwrap();
+        assert_eq!(whiteboard.groups[&top.id].name, "top");
+        assert_eq!(whiteboard.items[&item.id].shortcut.as_deref(), Some("1"));
+        assert!(!whiteboard.items.contains_key(&newer.id));
+        assert!(!storage.restore_snapshot(info.id, Some(Uuid::new_v4())).await.unwrap());
+
+        // Deleting the snapshot lets go of contents no longer on the whiteboard
+        storage.delete_group(top.id, DeleteMode::Cascade).await.unwrap();
+        assert!(storage.load_clipboard_content(item.content.id).await.unwrap().is_some());
+        storage.delete_snapshot(info.id).await.unwrap();
+        assert!(storage.load_clipboard_content(item.content.id).await.unwrap().is_none());
+        assert!(storage.list_snapshots().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_targeted_item_and_group_updates() {
+        let storage = memory_storage().await;
+        let group = group_in("work", None);
+        let item = item_in("note", &group);
+        storage.save_group(&group).await.unwrap();
+        storage.save_whiteboard_item(&item).await.unwrap();
+
+        let moved = Position { x: 40.0, y: 60.0 };
+        let changes = ItemChanges {
+            position: Some(moved.clone()),
+            size: Some(Size { width: 300.0, height: 90.0 }),
+            parent_group: Some(None),
+            label: Some(Some("todo".to_string())),
+            ..Default::default()
+        };
+        storage.update_item(item.id, &changes).await.unwrap();
+        let changes = GroupChanges {
+            name: Some("home".to_string()),
+            position: Some(moved.clone()),
+            collapsed: Some(true),
+            ..Default::default()
+        };
+        storage.update_group(group.id, &changes).await.unwrap();
+
+        let whiteboard = storage.load_whiteboard().await.unwrap();
+        let loaded = &whiteboard.items[&item.id];
+        assert_eq!(loaded.position, moved);
+        assert_eq!(loaded.size, Size { width: 300.0, height: 90.0 });
+        assert_eq!((loaded.parent_group, loaded.label.as_deref()), (None, Some("todo")));
+        assert_eq!(loaded.content.data.text(), Some("note"));
+        assert!(loaded.updated_at > item.updated_at);
+        assert_eq!(whiteboard.root_items, vec![group.id, item.id]);
+        let loaded = &whiteboard.groups[&group.id];
+        assert_eq!((loaded.name.as_str(), &loaded.position, loaded.collapsed), ("home", &moved, true));
+    }
+
+    #[tokio::test]
+    async fn test_move_group() {
+        let storage = memory_storage().await;
+        let mut top = group_in("top", None);
+        top.shortcut = Some("g1".to_string());
+        let mut inner = group_in("inner", Some(&top));
+        inner.shortcut = Some("g2".to_string());
+        let item = item_in("note", &inner);
+        let mut other = group_in("other", None);
+        other.shortcut = Some("g2".to_string());
+        for group in [&top, &inner, &other] {
+            storage.save_group(group).await.unwrap();
+        }
+        storage.save_whiteboard_item(&item).await.unwrap();
+
+        let whiteboard = storage.load_whiteboard().await.unwrap();
+        assert_eq!(whiteboard.root_items, vec![top.id, other.id]);
+        assert_eq!(whiteboard.groups[&top.id].children, vec![inner.id]);
+        assert_eq!(whiteboard.groups[&inner.id].children, vec![item.id]);
+
+        // Not into itself or below itself
+        assert!(matches!(storage.move_group(top.id, Some(top.id)).await, Err(StorageError::GroupCycle)));
+        assert!(matches!(storage.move_group(top.id, Some(inner.id)).await, Err(StorageError::GroupCycle)));
+        assert!(matches!(
+            storage.move_group(top.id, Some(Uuid::new_v4())).await,
+            Err(StorageError::GroupNotFound(_))
+        ));
+
+        // "g2" is already used at the top level, so the moved group gets a new shortcut
+        assert_eq!(storage.move_group(inner.id, None).await.unwrap().as_deref(), Some("g3"));
+        let whiteboard = storage.load_whiteboard().await.unwrap();
+        assert_eq!(whiteboard.root_items, vec![top.id, inner.id, other.id]);
+        assert!(whiteboard.groups[&top.id].children.is_empty());
+        assert_eq!(whiteboard.items[&item.id].parent_group, Some(inner.id));
+
+        // Into a sibling, where the shortcut is free
+        assert_eq!(storage.move_group(inner.id, Some(other.id)).await.unwrap().as_deref(), Some("g3"));
+        let whiteboard = storage.load_whiteboard().await.unwrap();
+        assert_eq!(whiteboard.groups[&other.id].children, vec![inner.id]);
+        assert_eq!(whiteboard.root_items, vec![top.id, other.id]);
+    }
+
+    #[tokio::test]
+    async fn test_inline_images_are_migrated() {
+        let storage = memory_storage().await;
+        let content = png_image(4, 4);
+        let base64 = image_base64(&content).unwrap();
+
+        // A row as written before blob storage existed
+        sqlx::query(
+            "INSERT INTO clipboard_contents (id, category, data_type, image_base64, image_width, image_height, image_format, copied_at, is_encrypted) VALUES (?, 'image', 'image', ?, 4, 4, 'png', ?, 1)",
+        )
+        .bind(content.id.to_string())
+        .bind(storage.crypto().await.unwrap().encrypt_text(&base64).unwrap())
+        .bind(Utc::now().to_rfc3339())
+        .execute(&storage.pool)
+        .await
+        .unwrap();
+
+        // One that cannot be decrypted anymore is skipped
+        sqlx::query(
+            "INSERT INTO clipboard_contents (id, category, data_type, image_base64, copied_at, is_encrypted) VALUES (?, 'image', 'image', 'garbage', ?, 1)",
+        )
+        .bind(Uuid::new_v4().to_string())
+        .bind(Utc::now().to_rfc3339())
+        .execute(&storage.pool)
+        .await
+        .unwrap();
+
+        storage.migrate_images_to_blobs().await.unwrap();
+        assert_eq!(blob_count(&storage).await, 1);
+        let loaded = storage.load_clipboard_content(content.id).await.unwrap().unwrap();
+        assert_eq!(image_base64(&loaded), Some(base64));
+    }
+
+    #[tokio::test]
+    async fn test_plain_blob_hashes_are_keyed() {
+        let storage = memory_storage().await;
+        let content = png_image(4, 4);
+        storage.save_history_entry(&content).await.unwrap();
+
+        // A blob as stored before hashes were keyed
+        let png = STANDARD.decode(image_base64(&content).unwrap()).unwrap();
+        let plain = to_hex(&Sha256::digest(&png));
+        sqlx::query("UPDATE blobs SET hash = ?, keyed = 0")
+            .bind(&plain)
+            .execute(&storage.pool)
+            .await
+            .unwrap();
+        sqlx::query("UPDATE clipboard_contents SET image_blob = ?")
+            .bind(&plain)
+            .execute(&storage.pool)
+            .await
+            .unwrap();
+
+        storage.key_blob_hashes().await.unwrap();
+        let row = sqlx::query("SELECT b.hash, b.keyed FROM blobs b JOIN clipboard_contents c ON c.image_blob = b.hash")
+            .fetch_one(&storage.pool)
+            .await
+            .unwrap();
+        assert_eq!(row.get::<String, _>("hash"), storage.crypto().await.unwrap().fingerprint(&png));
+        assert!(row.get::<bool, _>("keyed"));
+        let loaded = storage.load_clipboard_content(content.id).await.unwrap().unwrap();
+        assert_eq!(image_base64(&loaded), image_base64(&content));
+    }
+
+    #[tokio::test]
+    async fn test_content_hash_is_stored_and_backfilled() {
+        let storage = memory_storage().await;
+        let content = ClipboardContent::new_text("hello".to_string(), Category::Text);
+        let item = WhiteboardItem::new(content.clone(), Position { x: 0.0, y: 0.0 });
+        storage.save_whiteboard_item(&item).await.unwrap();
+
+        let found = storage.find_whiteboard_item_by_hash(&content.content_hash()).await.unwrap();
+        assert_eq!(found, Some(item.id));
+
+        // The plain hash is stored, except for Secure contents
+        let secret = ClipboardContent::new_text("hunter2".to_string(), Category::Secure);
+        storage.save_clipboard_content(&secret).await.unwrap();
+        let stored = |id: Uuid| {
+            sqlx::query("SELECT content_hash FROM clipboard_contents WHERE id = ?")
+                .bind(id.to_string())
+                .fetch_one(&storage.pool)
+        };
+        assert_eq!(stored(content.id).await.unwrap().get::<String, _>("content_hash"), content.content_hash());
+        assert_ne!(stored(secret.id).await.unwrap().get::<String, _>("content_hash"), secret.content_hash());
+
+        sqlx::query("UPDATE clipboard_contents SET content_hash = NULL")
+            .execute(&storage.pool)
+            .await
+            .unwrap();
+        assert_eq!(storage.find_whiteboard_item_by_hash(&content.content_hash()).await.unwrap(), None);
+        storage.backfill_content_hashes().await.unwrap();
+        assert_eq!(
+            storage.find_whiteboard_item_by_hash(&content.content_hash()).await.unwrap(),
+            Some(item.id)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_switch_to_master_password_keeps_data() {
+        let storage = memory_storage().await;
+        let content = ClipboardContent::new_text("secret".to_string(), Category::Secure);
+        let item = WhiteboardItem::new(content, Position { x: 0.0, y: 0.0 });
+        storage.save_whiteboard_item(&item).await.unwrap();
+        storage.save_clipboard_content(&png_image(4, 4)).await.unwrap();
+        assert_eq!(storage.key_mode().await.unwrap(), KeyMode::Machine);
+
+        storage.set_master_password(None, "correct horse").await.unwrap();
+        assert_eq!(storage.key_mode().await.unwrap(), KeyMode::Password);
+        assert!(storage.verify_master_password("correct horse").await.unwrap());
+        assert!(!storage.verify_master_password("wrong").await.unwrap());
+
+        // Changing it takes the current password
+        for current in [None, Some("wrong")] {
+            assert!(matches!(
+                storage.set_master_password(current, "battery staple").await,
+                Err(StorageError::WrongPassword)
+            ));
+        }
+
+        // A restart starts locked
+        let storage = PersistentStorage::with_pool(storage.pool.clone(), std::env::temp_dir())
+            .await
+            .unwrap();
+        assert!(storage.is_locked().await);
+        assert!(matches!(storage.load_whiteboard().await, Err(StorageError::Locked)));
+        assert!(matches!(storage.unlock("wrong").await, Err(StorageError::WrongPassword)));
+
+        storage.unlock("correct horse").await.unwrap();
+        let whiteboard = storage.load_whiteboard().await.unwrap();
+        assert_eq!(whiteboard.items[&item.id].content.data.text(), Some("secret"));
+
+        storage.lock().await;
+        assert!(matches!(storage.load_history(10).await, Err(StorageError::Locked)));
+    }
+
+    #[tokio::test]
+    async fn test_rotate_key_with_new_params() {
+        let storage = memory_storage().await;
+        let content = ClipboardContent::new_text("secret".to_string(), Category::Secure);
+        let item = WhiteboardItem::new(content, Position { x: 0.0, y: 0.0 });
+        storage.save_whiteboard_item(&item).await.unwrap();
+        let image = png_image(4, 4);
+        storage.save_history_entry(&image).await.unwrap();
+        assert_eq!(storage.kdf_params().await.unwrap(), KdfParams::default());
+        let old_salt = storage.key_config().await.unwrap().salt;
+
+        let kdf = KdfParams {
+            memory_kib: 8192,
+            iterations: 1,
+            parallelism: 1,
+        };
+        let updates = std::sync::Mutex::new(Vec::new());
+        storage
+            .rotate_key(None, kdf, |p| updates.lock().unwrap().push(p))
+            .await
+            .unwrap();
+
+        let updates = updates.into_inner().unwrap();
+        let last = updates.last().unwrap();
+        assert_eq!((last.done, last.total), (3, 3));
+        assert_eq!(storage.kdf_params().await.unwrap(), kdf);
+        assert_ne!(storage.key_config().await.unwrap().salt, old_salt);
+
+        // The stored config derives the same key again after a restart
+        let storage = PersistentStorage::with_pool(storage.pool.clone(), std::env::temp_dir())
+            .await
+            .unwrap();
+        let whiteboard = storage.load_whiteboard().await.unwrap();
+        assert_eq!(whiteboard.items[&item.id].content.data.text(), Some("secret"));
+        assert_eq!(
+            storage.find_whiteboard_item_by_hash(&item.content.content_hash()).await.unwrap(),
+            Some(item.id)
+        );
+        let history = storage.load_history(10).await.unwrap();
+        assert_eq!(history.len(), 1);
+        // Blobs are re-addressed under the new key
+        assert_eq!(image_base64(&history[0]), image_base64(&image));
+
+        // In password mode the current password is required
+        storage.set_master_password(None, "correct horse").await.unwrap();
+        assert!(matches!(
+            storage.rotate_key(Some("wrong"), kdf, |_| {}).await,
+            Err(StorageError::WrongPassword)
+        ));
+        storage
+            .rotate_key(Some("correct horse"), KdfParams::default(), |_| {})
+            .await
+            .unwrap();
+        assert!(storage.verify_master_password("correct horse").await.unwrap());
+    }
+}
@@ -0,0 +1,301 @@
+//! Point-in-time copies of the whiteboard layout, taken periodically or on request
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+use uuid::Uuid;
+
+use crate::types::{ClipboardData, Group, Position, Size, WhiteboardState};
+use crate::APP_STATE;
+
+/// Settings key holding the policy as JSON
+pub const SNAPSHOT_POLICY_SETTING: &str = "whiteboard_snapshots";
+
+/// Contents kept because a snapshot refers to them
+pub const SNAPSHOT_CONTENT_IDS: &str = "SELECT content_id FROM snapshot_contents";
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// When automatic snapshots are taken and how many are kept
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SnapshotPolicy {
+    /// Hours between automatic snapshots, None to only take them on request
+    pub interval_hours: Option<u32>,
+    /// Automatic snapshots kept; ones taken on request are kept until deleted
+    pub keep: u32,
+}
+
+impl Default for SnapshotPolicy {
+    fn default() -> Self {
+        Self {
+            interval_hours: Some(24),
+            keep: 30,
+        }
+    }
+}
+
+/// A whiteboard item as stored in a snapshot; the content itself stays in
+/// `clipboard_contents`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SnapshotItem {
+    pub id: Uuid,
+    pub content_id: Uuid,
+    pub preview: String,
+    pub position: Position,
+    pub size: Size,
+    pub parent_group: Option<Uuid>,
+    pub shortcut: Option<String>,
+    pub label: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// What a snapshot stores, encrypted
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SnapshotData {
+    pub groups: Vec<Group>,
+    pub items: Vec<SnapshotItem>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SnapshotInfo {
+    pub id: Uuid,
+    pub label: Option<String>,
+    pub automatic: bool,
+    pub group_count: u32,
+    pub item_count: u32,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ChangeKind {
+    /// On the whiteboard now but not in the snapshot
+    Added,
+    /// In the snapshot but no longer on the whiteboard
+    Removed,
+    Changed,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EntryChange {
+    pub id: Uuid,
+    /// Group name, or item label or preview
+    pub name: String,
+    pub kind: ChangeKind,
+    /// Fields that differ, for changed entries
+    pub fields: Vec<&'static str>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SnapshotDiff {
+    pub groups: Vec<EntryChange>,
+    pub items: Vec<EntryChange>,
+}
+
+impl SnapshotData {
+    /// Layout of a whiteboard, in a stable order
+    pub fn from_whiteboard(whiteboard: &WhiteboardState) -> Self {
+        let mut groups: Vec<Group> = whiteboard.groups.values().cloned().collect();
+        groups.sort_by_key(|g| g.id);
+        let mut items: Vec<SnapshotItem> = whiteboard
+            .items
+            .values()
+            .map(|item| SnapshotItem {
+                id: item.id,
+                content_id: item.content.id,
+                preview: match &item.content.data {
+                    ClipboardData::Text { preview, .. }
+                    | ClipboardData::Html { preview, .. }
+                    | ClipboardData::Rtf { preview, .. }
+                    | ClipboardData::Files { preview, .. } => preview.clone(),
+                    ClipboardData::Image { .. } => "[image]".to_string(),
+                },
+                position: item.position.clone(),
+                size: item.size.clone(),
+                parent_group: item.parent_group,
+                shortcut: item.shortcut.clone(),
+                label: item.label.clone(),
+                created_at: item.created_at,
+                updated_at: item.updated_at,
+            })
+            .collect();
+        items.sort_by_key(|i| i.id);
+        Self { groups, items }
+    }
+
+    /// A group with everything below it, or None if the snapshot does not have it
+    pub fn subtree(&self, group: Uuid) -> Option<Self> {
+        if !self.groups.iter().any(|g| g.id == group) {
+            return None;
+        }
+        let mut ids = HashSet::from([group]);
+        loop {
+            let before = ids.len();
+            for g in &self.groups {
+                if g.parent_group.is_some_and(|p| ids.contains(&p)) {
+                    ids.insert(g.id);
+                }
+            }
+            if ids.len() == before {
+                break;
+            }
+        }
+        Some(Self {
+            groups: self.groups.iter().filter(|g| ids.contains(&g.id)).cloned().collect(),
+            items: self
+                .items
+                .iter()
+                .filter(|i| i.parent_group.is_some_and(|p| ids.contains(&p)))
+                .cloned()
+                .collect(),
+        })
+    }
+
+    /// What changed from this snapshot to `current`
+    pub fn diff(&self, current: &SnapshotData) -> SnapshotDiff {
+        SnapshotDiff {
+            groups: diff_entries(
+                &self.groups,
+                &current.groups,
+                |g| g.id,
+                |g| g.name.clone(),
+                |a, b| {
+                    let mut fields = Vec::new();
+                    if a.name != b.name {
+                        fields.push("name");
+                    }
+                    if a.position != b.position {
+                        fields.push("position");
+                    }
+                    if a.collapsed != b.collapsed {
+                        fields.push("collapsed");
+                    }
+                    if a.parent_group != b.parent_group {
+                        fields.push("parentGroup");
+                    }
+                    if a.shortcut != b.shortcut {
+                        fields.push("shortcut");
+                    }
+                    if a.color != b.color {
+                        fields.push("color");
+                    }
+                    fields
+                },
+            ),
+            items: diff_entries(
+                &self.items,
+                &current.items,
+                |i| i.id,
+                |i| i.label.clone().unwrap_or_else(|| i.preview.clone()),
+                |a, b| {
+                    let mut fields = Vec::new();
+                    if a.content_id != b.content_id {
+                        fields.push("content");
+                    }
+                    if a.position != b.position {
+                        fields.push("position");
+                    }
+                    if a.size != b.size {
+                        fields.push("size");
+                    }
+                    if a.parent_group != b.parent_group {
+                        fields.push("parentGroup");
+                    }
+                    if a.shortcut != b.shortcut {
+                        fields.push("shortcut");
+                    }
+                    if a.label != b.label {
+                        fields.push("label");
+                    }
+                    fields
+                },
+            ),
+        }
+    }
+}
+
+fn diff_entries<T>(
+    old: &[T],
+    new: &[T],
+    id: impl Fn(&T) -> Uuid,
+    name: impl Fn(&T) -> String,
+    changed_fields: impl Fn(&T, &T) -> Vec<&'static str>,
+) -> Vec<EntryChange> {
+    let new_by_id: HashMap<Uuid, &T> = new.iter().map(|e| (id(e), e)).collect();
+    let old_ids: HashSet<Uuid> = old.iter().map(&id).collect();
+
+    let mut changes = Vec::new();
+    for entry in old {
+        let (kind, fields, shown) = match new_by_id.get(&id(entry)) {
+            None => (ChangeKind::Removed, Vec::new(), entry),
+            Some(current) => {
+                let fields = changed_fields(entry, current);
+                if fields.is_empty() {
+                    continue;
+                }
+                (ChangeKind::Changed, fields, *current)
+            }
+        };
+        changes.push(EntryChange {
+            id: id(entry),
+            name: name(shown),
+            kind,
+            fields,
+        });
+    }
+    for entry in new.iter().filter(|e| !old_ids.contains(&id(e))) {
+        changes.push(EntryChange {
+            id: id(entry),
+            name: name(entry),
+            kind: ChangeKind::Added,
+            fields: Vec::new(),
+        });
+    }
+    changes
+}
+
+/// Take an automatic snapshot when one is due and the whiteboard changed
+async fn run_snapshot() -> Result<(), String> {
+    let state = APP_STATE.get().ok_or("App state not initialized")?;
+    let storage = state.persistent_storage.read().await;
+    let Some(storage) = storage.as_ref() else {
+        return Ok(());
+    };
+    if storage.is_locked().await {
+        return Ok(());
+    }
+
+    let policy = storage.snapshot_policy().await.map_err(|e| e.to_string())?;
+    let Some(hours) = policy.interval_hours else {
+        return Ok(());
+    };
+    let last = storage.last_snapshot_at().await.map_err(|e| e.to_string())?;
+    if last.is_some_and(|last| Utc::now() - last < chrono::Duration::hours(hours as i64)) {
+        return Ok(());
+    }
+
+    storage.create_snapshot(None, true).await.map_err(|e| e.to_string())?;
+    storage.prune_snapshots(policy.keep).await.map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Check for a due snapshot now and then periodically
+pub fn start_snapshot_job() {
+    tauri::async_runtime::spawn(async {
+        let mut interval = tokio::time::interval(CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+            if let Err(e) = run_snapshot().await {
+                eprintln!("Whiteboard snapshot failed: {}", e);
+            }
+        }
+    });
+}
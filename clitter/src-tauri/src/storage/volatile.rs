@@ -1,66 +1,468 @@
-use std::collections::VecDeque;
-use tokio::sync::RwLock;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use tokio::sync::{RwLock, RwLockReadGuard};
 
-use crate::types::{Category, ClipboardContent};
+use uuid::Uuid;
+use zeroize::{Zeroize, Zeroizing};
 
-const MAX_HISTORY_SIZE: usize = 100;
+use crate::crypto::SecureStore;
+use crate::types::{to_hex, Category, ClipboardContent, ClipboardData, ClipboardSummary};
+
+/// Default number of history entries
+pub const MAX_HISTORY_SIZE: usize = 100;
+
+/// Settings key holding the history limits as JSON
+pub const HISTORY_LIMITS_SETTING: &str = "history_limits";
+
+/// Bounds on what the in-memory history holds
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryLimits {
+    pub max_entries: usize,
+    /// Budget for the payloads of all entries together
+    pub max_total_bytes: usize,
+    /// Larger contents are not added to the history
+    pub max_item_bytes: usize,
+}
+
+impl Default for HistoryLimits {
+    fn default() -> Self {
+        Self {
+            max_entries: MAX_HISTORY_SIZE,
+            max_total_bytes: 256 * 1024 * 1024,
+            max_item_bytes: 32 * 1024 * 1024,
+        }
+    }
+}
+
+impl HistoryLimits {
+    /// Clamp to values the history can work with
+    pub fn sanitize(self) -> Self {
+        let max_entries = self.max_entries.max(1);
+        let max_total_bytes = self.max_total_bytes.max(1024);
+        Self {
+            max_entries,
+            max_total_bytes,
+            max_item_bytes: self.max_item_bytes.clamp(1, max_total_bytes),
+        }
+    }
+}
+
+/// Result of adding a content to the history
+#[derive(Debug, Clone)]
+pub struct Added {
+    /// The entry as stored, which is the existing one for re-copied content.
+    /// Carries the full payload even for Secure entries.
+    pub content: ClipboardContent,
+    /// Ids of entries dropped to stay within the limits
+    pub evicted: Vec<Uuid>,
+}
+
+/// Settings key holding the dedupe mode as JSON
+pub const DEDUPE_MODE_SETTING: &str = "dedupe_mode";
+
+/// How history entries are compared when deduplicating
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DedupeMode {
+    /// Same content hash
+    #[default]
+    Exact,
+    /// Texts that only differ in whitespace count as the same entry
+    NormalizeWhitespace,
+}
+
+/// One page of history; pass `next_cursor` to get the following page
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryPage {
+    pub items: Vec<ClipboardSummary>,
+    pub next_cursor: Option<Uuid>,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SourceGroup {
+    pub source: Option<String>,
+    pub items: Vec<ClipboardContent>,
+}
+
+/// Dedupe key of a content under the given mode
+fn dedupe_key(content: &ClipboardContent, mode: DedupeMode) -> String {
+    use sha2::{Digest, Sha256};
+
+    match (&content.data, mode) {
+        (
+            ClipboardData::Text { text, .. }
+            | ClipboardData::Html { text, .. }
+            | ClipboardData::Rtf { text, .. },
+            DedupeMode::NormalizeWhitespace,
+        ) => {
+            let normalized = text.split_whitespace().collect::<Vec<_>>().join(" ");
+            let mut hasher = Sha256::new();
+            hasher.update(b"text\0");
+            hasher.update(normalized.as_bytes());
+            to_hex(&hasher.finalize())
+        }
+        _ => content.content_hash(),
+    }
+}
+
+/// Bytes a content keeps in memory, counting the strings that make up its payload
+fn memory_size(content: &ClipboardContent) -> usize {
+    match &content.data {
+        ClipboardData::Text { text, preview } => text.len() + preview.len(),
+        ClipboardData::Html { html, text, preview } => html.len() + text.len() + preview.len(),
+        ClipboardData::Rtf { rtf, text, preview } => rtf.len() + text.len() + preview.len(),
+        ClipboardData::Files { uris, preview } => uris.iter().map(|u| u.len()).sum::<usize>() + preview.len(),
+        ClipboardData::Image { base64, thumbnail, preview, .. } => {
+            base64.len()
+                + thumbnail.as_ref().map_or(0, |t| t.len())
+                + preview.as_ref().map_or(0, |p| p.len())
+        }
+    }
+}
+
+/// History entries (newest first) with an index from dedupe key to entry id.
+/// Secure entries are kept masked, with their payload encrypted under a key
+/// that only lives as long as the process.
+struct History {
+    entries: VecDeque<ClipboardContent>,
+    index: HashMap<String, Uuid>,
+    mode: DedupeMode,
+    limits: HistoryLimits,
+    total_bytes: usize,
+    session: SecureStore,
+    /// Encrypted payloads of Secure entries by id
+    sealed: HashMap<Uuid, Vec<u8>>,
+}
+
+impl History {
+    fn size_of(&self, content: &ClipboardContent) -> usize {
+        memory_size(content) + self.sealed.get(&content.id).map_or(0, Vec::len)
+    }
+
+    /// Dedupe key, keyed with the session key for Secure contents so that no
+    /// plain hash of a secret is kept
+    fn key_of(&self, content: &ClipboardContent) -> String {
+        let key = dedupe_key(content, self.mode);
+        if content.category == Category::Secure {
+            self.session.fingerprint(key.as_bytes())
+        } else {
+            key
+        }
+    }
+
+    /// Encrypt the payload of a Secure content, leaving it masked
+    fn seal(&mut self, mut content: ClipboardContent) -> ClipboardContent {
+        if content.category != Category::Secure {
+            return content;
+        }
+
+        let sealed = serde_json::to_vec(&content.data)
+            .ok()
+            .map(Zeroizing::new)
+            .and_then(|plain| self.session.encrypt(&plain).ok());
+        match sealed {
+            Some(sealed) => {
+                self.sealed.insert(content.id, sealed);
+            }
+            None => eprintln!("Failed to encrypt a secure history entry"),
+        }
+
+        let masked = content.data.masked();
+        content.data.zeroize();
+        content.data = masked;
+        content
+    }
+
+    /// The entry with its Secure payload decrypted
+    fn reveal(&self, content: &ClipboardContent) -> Option<ClipboardContent> {
+        let Some(sealed) = self.sealed.get(&content.id) else {
+            return (content.category != Category::Secure).then(|| content.clone());
+        };
+        let plain = Zeroizing::new(self.session.decrypt(sealed).ok()?);
+        let data = serde_json::from_slice(&plain).ok()?;
+        Some(ClipboardContent {
+            data,
+            ..content.clone()
+        })
+    }
+
+    fn remove_at(&mut self, pos: usize) -> Option<ClipboardContent> {
+        let content = self.entries.remove(pos)?;
+        self.total_bytes -= self.size_of(&content);
+        self.sealed.remove(&content.id);
+        self.index.retain(|_, id| *id != content.id);
+        Some(content)
+    }
+
+    /// Drop entries until the history fits its limits, returning their ids.
+    /// Over the byte budget the largest image goes first, then the oldest entry;
+    /// the newest entry is always kept.
+    fn enforce_limits(&mut self) -> Vec<Uuid> {
+        let mut evicted = Vec::new();
+
+        while self.entries.len() > self.limits.max_entries {
+            match self.remove_at(self.entries.len() - 1) {
+                Some(content) => evicted.push(content.id),
+                None => break,
+            }
+        }
+
+        while self.total_bytes > self.limits.max_total_bytes && self.entries.len() > 1 {
+            let largest_image = self
+                .entries
+                .iter()
+                .enumerate()
+                .skip(1)
+                .filter(|(_, c)| matches!(c.data, ClipboardData::Image { .. }))
+                .max_by_key(|(_, c)| self.size_of(c))
+                .map(|(pos, _)| pos);
+            let pos = largest_image.unwrap_or(self.entries.len() - 1);
+            match self.remove_at(pos) {
+                Some(content) => evicted.push(content.id),
+                None => break,
+            }
+        }
+
+        evicted
+    }
+
+    fn rebuild_index(&mut self) {
+        let mut index = HashMap::with_capacity(self.entries.len());
+        for content in &self.entries {
+            let key = if content.category == Category::Secure {
+                let Some(full) = self.reveal(content) else {
+                    continue;
+                };
+                let key = self.key_of(&full);
+                full.wipe();
+                key
+            } else {
+                self.key_of(content)
+            };
+            index.entry(key).or_insert(content.id);
+        }
+        self.index = index;
+    }
+}
 
 pub struct VolatileStorage {
-    history: RwLock<VecDeque<ClipboardContent>>,
+    history: RwLock<History>,
 }
 
 impl VolatileStorage {
     pub fn new() -> Self {
         Self {
-            history: RwLock::new(VecDeque::with_capacity(MAX_HISTORY_SIZE)),
+            history: RwLock::new(History {
+                entries: VecDeque::with_capacity(MAX_HISTORY_SIZE),
+                index: HashMap::with_capacity(MAX_HISTORY_SIZE),
+                mode: DedupeMode::default(),
+                limits: HistoryLimits::default(),
+                total_bytes: 0,
+                session: SecureStore::ephemeral(),
+                sealed: HashMap::new(),
+            }),
+        }
+    }
+
+    /// Add a captured content, evicting entries as needed to stay within the limits.
+    /// Content already in the history is moved to the front instead, keeping its id
+    /// and counting the copy. Returns None if the content is over the single-item size.
+    pub async fn add(&self, content: ClipboardContent) -> Option<Added> {
+        let mut history = self.history.write().await;
+        if memory_size(&content) > history.limits.max_item_bytes {
+            return None;
         }
+        let key = history.key_of(&content);
+
+        let existing = history
+            .index
+            .get(&key)
+            .and_then(|id| history.entries.iter().position(|c| c.id == *id))
+            .and_then(|pos| history.remove_at(pos));
+        let content = match existing {
+            Some(mut existing) => {
+                existing.copy_count += 1;
+                existing.last_copied_at = content.last_copied_at;
+                existing.category = content.category;
+                existing.data = content.data;
+                existing.source = content.source;
+                existing.source_title = content.source_title;
+                existing
+            }
+            None => content,
+        };
+
+        let stored = history.seal(content.clone());
+        history.total_bytes += history.size_of(&stored);
+        history.index.insert(key, stored.id);
+        history.entries.push_front(stored);
+        let evicted = history.enforce_limits();
+        Some(Added { content, evicted })
     }
 
-    pub async fn add(&self, content: ClipboardContent) {
+    /// Append older entries (newest first) loaded from disk, as far as the limits allow
+    pub async fn restore(&self, items: Vec<ClipboardContent>) {
         let mut history = self.history.write().await;
 
-        // Check for duplicate (same content hash as the most recent item)
-        if let Some(last) = history.front() {
-            if last.content_hash() == content.content_hash() {
-                return;
+        for content in items {
+            if history.entries.len() >= history.limits.max_entries {
+                break;
             }
+            let size = memory_size(&content);
+            if size > history.limits.max_item_bytes
+                || history.total_bytes + size > history.limits.max_total_bytes
+            {
+                continue;
+            }
+            let key = history.key_of(&content);
+            if history.index.contains_key(&key) || history.entries.iter().any(|c| c.id == content.id) {
+                continue;
+            }
+            let content = history.seal(content);
+            history.total_bytes += history.size_of(&content);
+            history.index.insert(key, content.id);
+            history.entries.push_back(content);
         }
+    }
+
+    pub async fn limits(&self) -> HistoryLimits {
+        self.history.read().await.limits
+    }
+
+    /// Apply new limits right away, returning the ids of evicted entries
+    pub async fn set_limits(&self, limits: HistoryLimits) -> Vec<Uuid> {
+        let mut history = self.history.write().await;
+        history.limits = limits.sanitize();
+        history.enforce_limits()
+    }
+
+    pub async fn dedupe_mode(&self) -> DedupeMode {
+        self.history.read().await.mode
+    }
 
-        // Remove oldest if at capacity
-        if history.len() >= MAX_HISTORY_SIZE {
-            history.pop_back();
+    /// Change how new entries are matched against the history
+    pub async fn set_dedupe_mode(&self, mode: DedupeMode) {
+        let mut history = self.history.write().await;
+        if history.mode != mode {
+            history.mode = mode;
+            history.rebuild_index();
         }
+    }
 
-        history.push_front(content);
+    /// The entries without copying them, newest first. Secure entries are masked;
+    /// holding on to the guard blocks new entries.
+    pub async fn entries(&self) -> RwLockReadGuard<'_, VecDeque<ClipboardContent>> {
+        RwLockReadGuard::map(self.history.read().await, |h| &h.entries)
     }
 
     pub async fn get_all(&self) -> Vec<ClipboardContent> {
         let history = self.history.read().await;
-        history.iter().cloned().collect()
+        history.entries.iter().cloned().collect()
     }
 
     pub async fn get_by_category(&self, category: Category) -> Vec<ClipboardContent> {
         let history = self.history.read().await;
         history
+            .entries
             .iter()
             .filter(|c| c.category == category)
             .cloned()
             .collect()
     }
 
+    pub async fn get_by_source(&self, source: &str) -> Vec<ClipboardContent> {
+        let history = self.history.read().await;
+        history
+            .entries
+            .iter()
+            .filter(|c| c.source.as_deref() == Some(source))
+            .cloned()
+            .collect()
+    }
+
+    /// Group history by source application, most recently used source first
+    pub async fn group_by_source(&self) -> Vec<SourceGroup> {
+        let history = self.history.read().await;
+        let mut groups: Vec<SourceGroup> = Vec::new();
+
+        // History is newest first, so groups are created in order of their latest copy
+        for content in history.entries.iter() {
+            match groups.iter_mut().find(|g| g.source == content.source) {
+                Some(group) => group.items.push(content.clone()),
+                None => groups.push(SourceGroup {
+                    source: content.source.clone(),
+                    items: vec![content.clone()],
+                }),
+            }
+        }
+
+        groups
+    }
+
     pub async fn get_recent(&self, count: usize) -> Vec<ClipboardContent> {
         let history = self.history.read().await;
-        history.iter().take(count).cloned().collect()
+        history.entries.iter().take(count).cloned().collect()
+    }
+
+    /// Entries after `cursor` (the id of the last entry of the previous page) that match `filter`.
+    /// Returns None if the cursor is no longer in the history.
+    pub async fn get_page(
+        &self,
+        cursor: Option<Uuid>,
+        limit: usize,
+        filter: impl Fn(&ClipboardContent) -> bool,
+    ) -> Option<HistoryPage> {
+        let page: Vec<ClipboardContent> = {
+            let history = self.history.read().await;
+            let start = match cursor {
+                Some(cursor) => history.entries.iter().position(|c| c.id == cursor)? + 1,
+                None => 0,
+            };
+
+            // One extra entry tells whether there is a next page
+            history
+                .entries
+                .iter()
+                .skip(start)
+                .filter(|c| filter(c))
+                .take(limit + 1)
+                .cloned()
+                .collect()
+        };
+
+        let has_more = page.len() > limit;
+        let items: Vec<ClipboardSummary> = page.iter().take(limit).map(|c| c.summary()).collect();
+        let next_cursor = if has_more { items.last().map(|s| s.id) } else { None };
+        Some(HistoryPage { items, next_cursor })
+    }
+
+    pub async fn get_by_id(&self, id: Uuid) -> Option<ClipboardContent> {
+        let history = self.history.read().await;
+        history.entries.iter().find(|c| c.id == id).cloned()
+    }
+
+    /// Entry with its Secure payload decrypted, for pasting or showing it.
+    /// Everything else hands out Secure entries masked.
+    pub async fn reveal(&self, id: Uuid) -> Option<ClipboardContent> {
+        let history = self.history.read().await;
+        let content = history.entries.iter().find(|c| c.id == id)?;
+        history.reveal(content)
     }
 
     pub async fn get_by_index(&self, index: usize) -> Option<ClipboardContent> {
         let history = self.history.read().await;
-        history.get(index).cloned()
+        history.entries.get(index).cloned()
     }
 
     pub async fn clear(&self) {
         let mut history = self.history.write().await;
-        history.clear();
+        history.entries.clear();
+        history.index.clear();
+        history.sealed.clear();
+        history.total_bytes = 0;
     }
 }
 
@@ -85,6 +487,16 @@ mod tests {
             },
             copied_at: chrono::Utc::now(),
             source: None,
+            source_title: None,
+            copy_count: 1,
+            last_copied_at: chrono::Utc::now(),
+        }
+    }
+
+    fn create_sourced_content(text: &str, source: &str) -> ClipboardContent {
+        ClipboardContent {
+            source: Some(source.to_string()),
+            ..create_test_content(text)
         }
     }
 
@@ -113,6 +525,70 @@ mod tests {
         assert_eq!(all.len(), 1);
     }
 
+    #[tokio::test]
+    async fn test_recopy_moves_entry_to_front() {
+        let storage = VolatileStorage::new();
+
+        let first = storage.add(create_test_content("a")).await.unwrap().content;
+        storage.add(create_test_content("b")).await;
+        let again = storage.add(create_sourced_content("a", "firefox")).await.unwrap().content;
+
+        assert_eq!(again.id, first.id);
+        assert_eq!(again.copy_count, 2);
+        assert_eq!(again.source.as_deref(), Some("firefox"));
+        assert!(again.last_copied_at >= first.last_copied_at);
+        assert_eq!(again.copied_at, first.copied_at);
+
+        let texts: Vec<_> = storage.get_all().await.iter().map(|c| c.data.text().unwrap().to_string()).collect();
+        assert_eq!(texts, vec!["a", "b"]);
+    }
+
+    #[tokio::test]
+    async fn test_secure_entries_are_sealed() {
+        let storage = VolatileStorage::new();
+        let secret = ClipboardContent {
+            category: Category::Secure,
+            ..create_test_content("hunter2 password")
+        };
+
+        let added = storage.add(secret.clone()).await.unwrap().content;
+        assert_eq!(added.data.text(), Some("hunter2 password"));
+
+        // Everything but reveal hands out the masked entry
+        let stored = storage.get_by_id(secret.id).await.unwrap();
+        assert_eq!(stored.data.text(), Some(""));
+        assert!(matches!(&stored.data, ClipboardData::Text { preview, .. } if preview == crate::types::MASKED_PREVIEW));
+        assert!(!storage.history.read().await.index.contains_key(&secret.content_hash()));
+
+        let revealed = storage.reveal(secret.id).await.unwrap();
+        assert_eq!(revealed.data.text(), Some("hunter2 password"));
+
+        // Re-copies still match the sealed entry, in any dedupe mode
+        storage.set_dedupe_mode(DedupeMode::NormalizeWhitespace).await;
+        let again = ClipboardContent {
+            category: Category::Secure,
+            ..create_test_content("hunter2  password")
+        };
+        let again = storage.add(again).await.unwrap().content;
+        assert_eq!(again.id, secret.id);
+        assert_eq!(storage.get_all().await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_normalize_whitespace_mode() {
+        let storage = VolatileStorage::new();
+
+        storage.add(create_test_content("hello  world\n")).await;
+        storage.add(create_test_content("hello world")).await;
+        assert_eq!(storage.get_all().await.len(), 2);
+
+        storage.set_dedupe_mode(DedupeMode::NormalizeWhitespace).await;
+        let entry = storage.add(create_test_content(" hello\tworld ")).await.unwrap().content;
+        assert_eq!(storage.get_all().await.len(), 2);
+        assert_eq!(entry.copy_count, 2);
+        assert_eq!(entry.data.text(), Some(" hello\tworld "));
+    }
+
     #[tokio::test]
     async fn test_max_capacity() {
         let storage = VolatileStorage::new();
@@ -124,5 +600,107 @@ mod tests {
 
         let all = storage.get_all().await;
         assert_eq!(all.len(), MAX_HISTORY_SIZE);
+
+        // Evicted entries are dropped from the index and come back as new entries
+        let evicted = storage.add(create_test_content("text 0")).await.unwrap();
+        assert_eq!(evicted.content.copy_count, 1);
+        assert_eq!(evicted.evicted.len(), 1);
+    }
+
+    fn create_image_content(bytes: usize) -> ClipboardContent {
+        ClipboardContent {
+            category: Category::Image,
+            data: ClipboardData::Image {
+                base64: "A".repeat(bytes),
+                width: 1,
+                height: 1,
+                format: "png".to_string(),
+                thumbnail: None,
+                preview: None,
+            },
+            ..create_test_content("")
+        }
+    }
+
+    #[tokio::test]
+    async fn test_byte_budget_evicts_large_images_first() {
+        let storage = VolatileStorage::new();
+        storage
+            .set_limits(HistoryLimits {
+                max_entries: 10,
+                max_total_bytes: 10_000,
+                max_item_bytes: 6_000,
+            })
+            .await;
+
+        let small_text = storage.add(create_test_content("old text")).await.unwrap().content;
+        let big_image = storage.add(create_image_content(5_000)).await.unwrap().content;
+        let small_image = storage.add(create_image_content(1_000)).await.unwrap().content;
+        assert!(storage.add(create_image_content(7_000)).await.is_none());
+
+        let added = storage.add(create_image_content(4_500)).await.unwrap();
+        assert_eq!(added.evicted, vec![big_image.id]);
+        let ids: Vec<_> = storage.get_all().await.iter().map(|c| c.id).collect();
+        assert_eq!(ids, vec![added.content.id, small_image.id, small_text.id]);
+
+        // Lowering the limits at runtime evicts right away, oldest first after the images
+        let evicted = storage
+            .set_limits(HistoryLimits {
+                max_entries: 2,
+                ..storage.limits().await
+            })
+            .await;
+        assert_eq!(evicted, vec![small_text.id]);
+    }
+
+    #[tokio::test]
+    async fn test_filter_and_group_by_source() {
+        let storage = VolatileStorage::new();
+
+        storage.add(create_sourced_content("a", "firefox")).await;
+        storage.add(create_sourced_content("b", "Code")).await;
+        storage.add(create_test_content("c")).await;
+        storage.add(create_sourced_content("d", "Firefox")).await;
+        storage.add(create_sourced_content("e", "firefox")).await;
+
+        assert_eq!(storage.get_by_source("firefox").await.len(), 2);
+
+        let groups = storage.group_by_source().await;
+        let summary: Vec<(Option<&str>, usize)> = groups
+            .iter()
+            .map(|g| (g.source.as_deref(), g.items.len()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (Some("firefox"), 2),
+                (Some("Firefox"), 1),
+                (None, 1),
+                (Some("Code"), 1),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_cursor_pagination() {
+        let storage = VolatileStorage::new();
+        for i in 0..5 {
+            storage.add(create_test_content(&format!("text {}", i))).await;
+        }
+
+        let first = storage.get_page(None, 2, |_| true).await.unwrap();
+        let previews: Vec<&str> = first.items.iter().map(|s| s.preview.as_str()).collect();
+        assert_eq!(previews, vec!["text 4", "text 3"]);
+
+        let second = storage.get_page(first.next_cursor, 2, |_| true).await.unwrap();
+        let third = storage.get_page(second.next_cursor, 2, |_| true).await.unwrap();
+        assert_eq!(third.items.len(), 1);
+        assert_eq!(third.items[0].preview, "text 0");
+        assert!(third.next_cursor.is_none());
+
+        // Filtered pages and unknown cursors
+        let filtered = storage.get_page(None, 10, |c| c.data.text() == Some("text 2")).await.unwrap();
+        assert_eq!(filtered.items.len(), 1);
+        assert!(storage.get_page(Some(uuid::Uuid::new_v4()), 2, |_| true).await.is_none());
     }
 }
@@ -0,0 +1,58 @@
+//! The decrypted whiteboard kept in memory, so small edits don't reload and
+//! decrypt every item
+
+use tokio::sync::{RwLock, RwLockReadGuard};
+
+use super::persistent::{PersistentStorage, StorageError};
+use crate::types::WhiteboardState;
+
+pub struct WhiteboardCache {
+    state: RwLock<Option<WhiteboardState>>,
+}
+
+impl WhiteboardCache {
+    pub fn new() -> Self {
+        Self {
+            state: RwLock::new(None),
+        }
+    }
+
+    /// Drop the cached whiteboard, after changes too broad to apply in place or
+    /// when locking
+    pub async fn invalidate(&self) {
+        *self.state.write().await = None;
+    }
+
+    /// The whiteboard, loaded from storage if not cached
+    pub async fn get(&self, storage: &PersistentStorage) -> Result<WhiteboardState, StorageError> {
+        Ok(self.read(storage).await?.clone())
+    }
+
+    /// The cached whiteboard without copying it, loaded from storage if not
+    /// cached. Holding on to the guard blocks changes to the cache.
+    pub async fn read(&self, storage: &PersistentStorage) -> Result<RwLockReadGuard<'_, WhiteboardState>, StorageError> {
+        loop {
+            if let Ok(whiteboard) = RwLockReadGuard::try_map(self.state.read().await, Option::as_ref) {
+                return Ok(whiteboard);
+            }
+            // Another caller may have loaded it in the meantime
+            let mut cached = self.state.write().await;
+            if cached.is_none() {
+                *cached = Some(storage.load_whiteboard().await?);
+            }
+        }
+    }
+
+    /// Apply a change that was written to storage, if the whiteboard is cached
+    pub async fn update(&self, change: impl FnOnce(&mut WhiteboardState)) {
+        if let Some(whiteboard) = self.state.write().await.as_mut() {
+            change(whiteboard);
+        }
+    }
+}
+
+impl Default for WhiteboardCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
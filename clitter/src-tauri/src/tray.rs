@@ -1,30 +1,69 @@
+use std::time::Duration;
 use tauri::{
-    menu::{Menu, MenuItem, PredefinedMenuItem},
+    menu::{CheckMenuItem, Menu, MenuItem, PredefinedMenuItem},
     tray::{MouseButton, MouseButtonState, TrayIconBuilder, TrayIconEvent},
-    Manager,
+    AppHandle, Manager, Wry,
 };
 
-pub fn setup_tray(app: &tauri::App) -> Result<(), Box<dyn std::error::Error>> {
+use crate::clipboard::monitor::{self, MonitorState};
+
+const TRAY_ID: &str = "main";
+const TOOLTIP: &str = "Clitter - クリップボードマネージャー";
+
+// Pause toggle, kept so its check mark can follow the monitor state
+struct PauseMenuItem(CheckMenuItem<Wry>);
+
+// Lock item, disabled while already locked
+struct LockMenuItem(MenuItem<Wry>);
+
+fn load_icon(dimmed: bool) -> Result<tauri::image::Image<'static>, Box<dyn std::error::Error>> {
     // Load tray icon from PNG file
     let icon_bytes = include_bytes!("../icons/32x32.png");
     let img = image::load_from_memory(icon_bytes)?;
-    let rgba = img.to_rgba8();
+    let mut rgba = img.to_rgba8();
+
+    // Paused: draw the icon semi-transparent
+    if dimmed {
+        for pixel in rgba.pixels_mut() {
+            pixel[3] /= 3;
+        }
+    }
+
     let (width, height) = rgba.dimensions();
-    let icon = tauri::image::Image::new_owned(rgba.into_raw(), width, height);
+    Ok(tauri::image::Image::new_owned(rgba.into_raw(), width, height))
+}
+
+pub fn setup_tray(app: &tauri::App) -> Result<(), Box<dyn std::error::Error>> {
+    let icon = load_icon(false)?;
 
     // Create menu items
     let show_item = MenuItem::with_id(app, "show", "表示 (Alt+V)", true, None::<&str>)?;
+    let pause_item = CheckMenuItem::with_id(
+        app,
+        "pause",
+        "キャプチャを一時停止 (Alt+Shift+V)",
+        true,
+        false,
+        None::<&str>,
+    )?;
+    let pause_5m_item = MenuItem::with_id(app, "pause_5m", "5分間一時停止", true, None::<&str>)?;
+    let lock_item = MenuItem::with_id(app, "lock", "ロック (Alt+Shift+L)", true, None::<&str>)?;
     let separator = PredefinedMenuItem::separator(app)?;
     let quit_item = MenuItem::with_id(app, "quit", "終了", true, None::<&str>)?;
 
     // Create menu
-    let menu = Menu::with_items(app, &[&show_item, &separator, &quit_item])?;
+    let menu = Menu::with_items(
+        app,
+        &[&show_item, &pause_item, &pause_5m_item, &lock_item, &separator, &quit_item],
+    )?;
+    app.manage(PauseMenuItem(pause_item));
+    app.manage(LockMenuItem(lock_item));
 
     // Build tray icon
-    let _tray = TrayIconBuilder::new()
+    let _tray = TrayIconBuilder::with_id(TRAY_ID)
         .icon(icon)
         .menu(&menu)
-        .tooltip("Clitter - クリップボードマネージャー")
+        .tooltip(TOOLTIP)
         .on_menu_event(|app, event| match event.id.as_ref() {
             "show" => {
                 if let Some(window) = app.get_webview_window("main") {
@@ -32,6 +71,20 @@ pub fn setup_tray(app: &tauri::App) -> Result<(), Box<dyn std::error::Error>> {
                     let _ = window.set_focus();
                 }
             }
+            "pause" => {
+                monitor::toggle_pause();
+            }
+            "pause_5m" => {
+                monitor::pause_monitoring(Some(Duration::from_secs(5 * 60)));
+            }
+            "lock" => {
+                let app = app.clone();
+                tauri::async_runtime::spawn(async move {
+                    if let Err(e) = crate::lock::lock(&app).await {
+                        eprintln!("Failed to lock: {}", e);
+                    }
+                });
+            }
             "quit" => {
                 app.exit(0);
             }
@@ -56,3 +109,36 @@ pub fn setup_tray(app: &tauri::App) -> Result<(), Box<dyn std::error::Error>> {
     println!("System tray initialized successfully");
     Ok(())
 }
+
+/// Reflect the monitor state in the tray icon, tooltip and pause toggle
+pub fn update_monitor_state(app: &AppHandle, state: MonitorState) {
+    let paused = matches!(state, MonitorState::Paused { .. });
+
+    if let Some(item) = app.try_state::<PauseMenuItem>() {
+        let _ = item.0.set_checked(paused);
+    }
+
+    if let Some(tray) = app.tray_by_id(TRAY_ID) {
+        if let Ok(icon) = load_icon(paused) {
+            let _ = tray.set_icon(Some(icon));
+        }
+
+        let tooltip = match state {
+            MonitorState::Paused { until: Some(until) } => format!(
+                "{} (一時停止中: {}まで)",
+                TOOLTIP,
+                until.with_timezone(&chrono::Local).format("%H:%M")
+            ),
+            MonitorState::Paused { until: None } => format!("{} (一時停止中)", TOOLTIP),
+            _ => TOOLTIP.to_string(),
+        };
+        let _ = tray.set_tooltip(Some(tooltip));
+    }
+}
+
+/// Enable the lock item only while there is something to lock
+pub fn update_lock_state(app: &AppHandle, locked: bool) {
+    if let Some(item) = app.try_state::<LockMenuItem>() {
+        let _ = item.0.set_enabled(!locked);
+    }
+}
@@ -1,6 +1,7 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
+use zeroize::Zeroize;
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(rename_all = "lowercase")]
@@ -48,7 +49,89 @@ pub enum ClipboardData {
         width: u32,
         height: u32,
         format: String,
+        /// Small PNG (base64) for lists, generated at capture time
+        #[serde(default)]
+        thumbnail: Option<String>,
+        /// Downscaled PNG (base64) for larger views, None when the original is small enough
+        #[serde(default)]
+        preview: Option<String>,
     },
+    /// HTML fragment with its plain-text alternative
+    Html { html: String, text: String, preview: String },
+    /// Rich Text Format document with its plain-text alternative
+    Rtf { rtf: String, text: String, preview: String },
+    /// File list as `file://` URIs (text/uri-list)
+    Files { uris: Vec<String>, preview: String },
+}
+
+/// Preview of masked Secure contents
+pub const MASKED_PREVIEW: &str = "••••••••••";
+
+impl ClipboardData {
+    /// Build file list data, previewed as one file name per line
+    pub fn files(uris: Vec<String>) -> Self {
+        let names: Vec<String> = uris
+            .iter()
+            .map(|uri| {
+                url::Url::parse(uri)
+                    .ok()
+                    .and_then(|url| url.to_file_path().ok())
+                    .and_then(|path| path.file_name().map(|n| n.to_string_lossy().into_owned()))
+                    .unwrap_or_else(|| uri.clone())
+            })
+            .collect();
+        let preview = names.join("\n").chars().take(100).collect();
+        ClipboardData::Files { uris, preview }
+    }
+
+    /// Plain-text representation, if the data has one
+    pub fn text(&self) -> Option<&str> {
+        match self {
+            ClipboardData::Text { text, .. }
+            | ClipboardData::Html { text, .. }
+            | ClipboardData::Rtf { text, .. } => Some(text),
+            ClipboardData::Image { .. } | ClipboardData::Files { .. } => None,
+        }
+    }
+
+    /// Stand-in for a secret payload: an empty text with a fixed preview that
+    /// tells nothing about the secret
+    pub fn masked(&self) -> Self {
+        ClipboardData::Text {
+            text: String::new(),
+            preview: MASKED_PREVIEW.to_string(),
+        }
+    }
+}
+
+impl Zeroize for ClipboardData {
+    fn zeroize(&mut self) {
+        match self {
+            ClipboardData::Text { text, preview } => {
+                text.zeroize();
+                preview.zeroize();
+            }
+            ClipboardData::Image { base64, thumbnail, preview, .. } => {
+                base64.zeroize();
+                thumbnail.zeroize();
+                preview.zeroize();
+            }
+            ClipboardData::Html { html, text, preview } => {
+                html.zeroize();
+                text.zeroize();
+                preview.zeroize();
+            }
+            ClipboardData::Rtf { rtf, text, preview } => {
+                rtf.zeroize();
+                text.zeroize();
+                preview.zeroize();
+            }
+            ClipboardData::Files { uris, preview } => {
+                uris.zeroize();
+                preview.zeroize();
+            }
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -58,22 +141,42 @@ pub struct ClipboardContent {
     pub category: Category,
     pub data: ClipboardData,
     pub copied_at: DateTime<Utc>,
+    /// Application the content was copied from
     pub source: Option<String>,
+    /// Window title of the source application at copy time
+    pub source_title: Option<String>,
+    /// How often the content was copied while it was in the history
+    #[serde(default = "default_copy_count")]
+    pub copy_count: u32,
+    /// Time of the most recent copy; `copied_at` is the first one
+    #[serde(default = "Utc::now")]
+    pub last_copied_at: DateTime<Utc>,
+}
+
+fn default_copy_count() -> u32 {
+    1
 }
 
 impl ClipboardContent {
     pub fn new_text(text: String, category: Category) -> Self {
         let preview = text.chars().take(100).collect();
+        let now = Utc::now();
         Self {
             id: Uuid::new_v4(),
             category,
             data: ClipboardData::Text { text, preview },
-            copied_at: Utc::now(),
+            copied_at: now,
             source: None,
+            source_title: None,
+            copy_count: 1,
+            last_copied_at: now,
         }
     }
 
+    /// Image content with its thumbnail and preview generated from the original
     pub fn new_image(base64: String, width: u32, height: u32, format: String) -> Self {
+        let (thumbnail, preview) = crate::clipboard::thumbnail::make_previews(&base64, width, height);
+        let now = Utc::now();
         Self {
             id: Uuid::new_v4(),
             category: Category::Image,
@@ -82,32 +185,154 @@ impl ClipboardContent {
                 width,
                 height,
                 format,
+                thumbnail,
+                preview,
             },
-            copied_at: Utc::now(),
+            copied_at: now,
             source: None,
+            source_title: None,
+            copy_count: 1,
+            last_copied_at: now,
         }
     }
 
-    pub fn content_hash(&self) -> u64 {
-        use std::collections::hash_map::DefaultHasher;
-        use std::hash::{Hash, Hasher};
+    /// SHA-256 of the content (hex), stable across runs and machines. Storage
+    /// keeps it keyed with the database key for Secure contents.
+    pub fn content_hash(&self) -> String {
+        use base64::{engine::general_purpose::STANDARD, Engine};
+        use sha2::{Digest, Sha256};
 
-        let mut hasher = DefaultHasher::new();
+        let mut hasher = Sha256::new();
         match &self.data {
-            ClipboardData::Text { text, .. } => text.hash(&mut hasher),
-            ClipboardData::Image { base64, .. } => base64.hash(&mut hasher),
+            // Rich text is identified by its plain text, so a formatting round trip
+            // through the platform clipboard is not mistaken for a new copy
+            ClipboardData::Text { text, .. }
+            | ClipboardData::Html { text, .. }
+            | ClipboardData::Rtf { text, .. } => {
+                hasher.update(b"text\0");
+                hasher.update(text.as_bytes());
+            }
+            ClipboardData::Image { base64, .. } => {
+                hasher.update(b"image\0");
+                match STANDARD.decode(base64) {
+                    Ok(bytes) => hasher.update(&bytes),
+                    Err(_) => hasher.update(base64.as_bytes()),
+                }
+            }
+            ClipboardData::Files { uris, .. } => {
+                hasher.update(b"files\0");
+                for uri in uris {
+                    hasher.update(uri.as_bytes());
+                    hasher.update(b"\n");
+                }
+            }
         }
-        hasher.finish()
+        to_hex(&hasher.finalize())
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Lightweight projection of `ClipboardContent` for list views, without the full payload
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ClipboardSummary {
+    pub id: Uuid,
+    pub category: Category,
+    /// Same value as the `type` tag of `ClipboardData`
+    pub data_type: &'static str,
+    pub preview: String,
+    /// Small PNG (base64) for images
+    pub thumbnail: Option<String>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    /// Approximate payload size in bytes
+    pub size: usize,
+    pub copied_at: DateTime<Utc>,
+    pub source: Option<String>,
+    pub source_title: Option<String>,
+}
+
+impl ClipboardContent {
+    pub fn summary(&self) -> ClipboardSummary {
+        let (data_type, preview, size) = match &self.data {
+            ClipboardData::Text { text, preview } => ("text", preview.clone(), text.len()),
+            ClipboardData::Html { html, text, preview } => ("html", preview.clone(), html.len() + text.len()),
+            ClipboardData::Rtf { rtf, text, preview } => ("rtf", preview.clone(), rtf.len() + text.len()),
+            ClipboardData::Files { uris, preview } => {
+                ("files", preview.clone(), uris.iter().map(|u| u.len()).sum())
+            }
+            // Decoded size of the base64 payload
+            ClipboardData::Image { base64, .. } => ("image", String::new(), base64.len() / 4 * 3),
+        };
+
+        let (thumbnail, width, height) = match &self.data {
+            ClipboardData::Image { base64, width, height, thumbnail, .. } => (
+                // Contents captured before thumbnails existed get one on the fly
+                thumbnail.clone().or_else(|| {
+                    crate::clipboard::thumbnail::make_thumbnail(
+                        base64,
+                        crate::clipboard::thumbnail::THUMBNAIL_SIZE,
+                    )
+                }),
+                Some(*width),
+                Some(*height),
+            ),
+            _ => (None, None, None),
+        };
+
+        ClipboardSummary {
+            id: self.id,
+            category: self.category.clone(),
+            data_type,
+            preview,
+            thumbnail,
+            width,
+            height,
+            size,
+            copied_at: self.copied_at,
+            source: self.source.clone(),
+            source_title: self.source_title.clone(),
+        }
+    }
+
+    /// Copy to hand to the frontend. Secure payloads are masked; the full
+    /// content is fetched with `get_clipboard_content` when it is needed.
+    pub fn masked(&self) -> Self {
+        Self {
+            id: self.id,
+            category: self.category.clone(),
+            data: if self.category == Category::Secure {
+                self.data.masked()
+            } else {
+                self.data.clone()
+            },
+            copied_at: self.copied_at,
+            source: self.source.clone(),
+            source_title: self.source_title.clone(),
+            copy_count: self.copy_count,
+            last_copied_at: self.last_copied_at,
+        }
+    }
+
+    /// Overwrite a Secure payload before it is dropped
+    pub fn wipe(mut self) {
+        if self.category == Category::Secure {
+            self.data.zeroize();
+        }
+    }
+}
+
+/// Lowercase hex encoding, used for hashes
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Position {
     pub x: f64,
     pub y: f64,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Size {
     pub width: f64,
     pub height: f64,
@@ -187,3 +412,47 @@ pub struct WhiteboardState {
     pub groups: std::collections::HashMap<Uuid, Group>,
     pub root_items: Vec<Uuid>,
 }
+
+impl WhiteboardState {
+    /// Fill every group's `children` and `root_items` (groups and items, oldest
+    /// first) from the parent links. Entries whose parent is missing count as
+    /// top level.
+    pub fn rebuild_children(&mut self) {
+        let mut entries: Vec<(DateTime<Utc>, Uuid, Option<Uuid>)> = self
+            .groups
+            .values()
+            .map(|g| (g.created_at, g.id, g.parent_group))
+            .chain(self.items.values().map(|i| (i.created_at, i.id, i.parent_group)))
+            .collect();
+        entries.sort();
+
+        for group in self.groups.values_mut() {
+            group.children.clear();
+        }
+        self.root_items.clear();
+        for (_, id, parent) in entries {
+            match parent.and_then(|p| self.groups.get_mut(&p)) {
+                Some(group) => group.children.push(id),
+                None => self.root_items.push(id),
+            }
+        }
+    }
+
+    /// Add or replace an item, keeping `children` and `root_items` in sync
+    pub fn insert_item(&mut self, item: WhiteboardItem) {
+        self.items.insert(item.id, item);
+        self.rebuild_children();
+    }
+
+    pub fn remove_item(&mut self, id: Uuid) -> Option<WhiteboardItem> {
+        let item = self.items.remove(&id);
+        self.rebuild_children();
+        item
+    }
+
+    /// Add or replace a group, keeping `children` and `root_items` in sync
+    pub fn insert_group(&mut self, group: Group) {
+        self.groups.insert(group.id, group);
+        self.rebuild_children();
+    }
+}
@@ -19,6 +19,14 @@ static PREVIOUS_WINDOW: Lazy<Mutex<Option<isize>>> = Lazy::new(|| Mutex::new(Non
 #[cfg(target_os = "macos")]
 static PREVIOUS_APP: Lazy<Mutex<Option<String>>> = Lazy::new(|| Mutex::new(None));
 
+/// Application that owns the foreground window
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ForegroundApp {
+    /// WM_CLASS or process name
+    pub name: String,
+    pub title: Option<String>,
+}
+
 /// Save the current foreground window/app before showing Clitter
 pub fn save_previous_window() {
     #[cfg(windows)]
@@ -33,22 +41,150 @@ pub fn save_previous_window() {
 
     #[cfg(target_os = "macos")]
     {
-        // Get the frontmost application using AppleScript
-        let output = std::process::Command::new("osascript")
-            .args(["-e", "tell application \"System Events\" to get name of first application process whose frontmost is true"])
-            .output();
-
-        if let Ok(output) = output {
-            if output.status.success() {
-                let app_name = String::from_utf8_lossy(&output.stdout).trim().to_string();
-                if let Ok(mut prev) = PREVIOUS_APP.lock() {
-                    *prev = Some(app_name);
-                }
+        if let Some(app_name) = frontmost_app_name() {
+            if let Ok(mut prev) = PREVIOUS_APP.lock() {
+                *prev = Some(app_name);
             }
         }
     }
 }
 
+/// Identify the application owning the foreground window
+pub fn foreground_app() -> Option<ForegroundApp> {
+    #[cfg(windows)]
+    {
+        unsafe { windows_foreground_app(GetForegroundWindow()) }
+    }
+
+    #[cfg(target_os = "macos")]
+    {
+        frontmost_app_name().map(|name| ForegroundApp { name, title: None })
+    }
+
+    #[cfg(target_os = "linux")]
+    {
+        // Wayland compositors do not expose the focused window; X11 clients under
+        // XWayland would report a stale window, so only ask a real X session
+        if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+            return None;
+        }
+        x11_foreground_app()
+    }
+
+    #[cfg(not(any(windows, target_os = "macos", target_os = "linux")))]
+    {
+        None
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn frontmost_app_name() -> Option<String> {
+    // Get the frontmost application using AppleScript
+    let output = std::process::Command::new("osascript")
+        .args(["-e", "tell application \"System Events\" to get name of first application process whose frontmost is true"])
+        .output()
+        .ok()?;
+
+    if !output.status.success() {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+#[cfg(windows)]
+unsafe fn windows_foreground_app(hwnd: HWND) -> Option<ForegroundApp> {
+    use windows::core::PWSTR;
+    use windows::Win32::Foundation::CloseHandle;
+    use windows::Win32::System::Threading::{
+        OpenProcess, QueryFullProcessImageNameW, PROCESS_NAME_WIN32,
+        PROCESS_QUERY_LIMITED_INFORMATION,
+    };
+    use windows::Win32::UI::WindowsAndMessaging::{GetWindowTextW, GetWindowThreadProcessId};
+
+    if hwnd.0.is_null() {
+        return None;
+    }
+
+    let mut title_buf = [0u16; 512];
+    let title_len = GetWindowTextW(hwnd, &mut title_buf);
+    let title = (title_len > 0).then(|| String::from_utf16_lossy(&title_buf[..title_len as usize]));
+
+    let mut pid = 0u32;
+    GetWindowThreadProcessId(hwnd, Some(&mut pid as *mut u32));
+    let process = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, pid).ok()?;
+
+    let mut path_buf = [0u16; 1024];
+    let mut path_len = path_buf.len() as u32;
+    let result = QueryFullProcessImageNameW(
+        process,
+        PROCESS_NAME_WIN32,
+        PWSTR(path_buf.as_mut_ptr()),
+        &mut path_len,
+    );
+    let _ = CloseHandle(process);
+    result.ok()?;
+
+    let path = String::from_utf16_lossy(&path_buf[..path_len as usize]);
+    let name = std::path::Path::new(&path)
+        .file_name()?
+        .to_string_lossy()
+        .into_owned();
+
+    Some(ForegroundApp { name, title })
+}
+
+#[cfg(target_os = "linux")]
+fn x11_foreground_app() -> Option<ForegroundApp> {
+    use x11rb::connection::Connection;
+    use x11rb::protocol::xproto::{Atom, AtomEnum, ConnectionExt, Window};
+
+    let (conn, screen_num) = x11rb::connect(None).ok()?;
+    let root = conn.setup().roots[screen_num].root;
+
+    let atom = |name: &[u8]| -> Option<Atom> {
+        Some(conn.intern_atom(false, name).ok()?.reply().ok()?.atom)
+    };
+    let property = |window: Window, property: Atom, ty: Atom| {
+        conn.get_property(false, window, property, ty, 0, 1024)
+            .ok()?
+            .reply()
+            .ok()
+    };
+
+    let active = property(root, atom(b"_NET_ACTIVE_WINDOW")?, AtomEnum::WINDOW.into())?;
+    let window = active.value32()?.next().filter(|&w| w != 0)?;
+
+    // WM_CLASS holds "instance\0class\0"; the class is the application name
+    let class_name = property(window, AtomEnum::WM_CLASS.into(), AtomEnum::STRING.into())
+        .and_then(|reply| {
+            reply
+                .value
+                .split(|&b| b == 0)
+                .rfind(|part| !part.is_empty())
+                .map(|part| String::from_utf8_lossy(part).into_owned())
+        });
+
+    // Fall back to the process name when the window has no class
+    let process_name = || {
+        let pid = property(window, atom(b"_NET_WM_PID")?, AtomEnum::CARDINAL.into())?
+            .value32()?
+            .next()?;
+        let comm = std::fs::read_to_string(format!("/proc/{}/comm", pid)).ok()?;
+        Some(comm.trim().to_string())
+    };
+    let name = class_name.or_else(process_name)?;
+
+    let title = atom(b"UTF8_STRING")
+        .zip(atom(b"_NET_WM_NAME"))
+        .and_then(|(utf8, net_wm_name)| property(window, net_wm_name, utf8))
+        .filter(|reply| !reply.value.is_empty())
+        .or_else(|| property(window, AtomEnum::WM_NAME.into(), AtomEnum::STRING.into()))
+        .map(|reply| String::from_utf8_lossy(&reply.value).into_owned())
+        .filter(|title| !title.is_empty());
+
+    Some(ForegroundApp { name, title })
+}
+
 /// Restore focus to the previous window and simulate paste
 pub fn restore_and_paste() -> Result<(), String> {
     #[cfg(windows)]
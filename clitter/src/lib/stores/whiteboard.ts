@@ -12,6 +12,29 @@ export const whiteboardState = writable<WhiteboardState>({
   rootItems: [],
 });
 
+// Add an item, listing it under its parent group or, without one, at the top level
+export function addItemToState(state: WhiteboardState, item: WhiteboardItem): WhiteboardState {
+  state.items[item.id] = item;
+  const parent = item.parentGroup ? state.groups[item.parentGroup] : undefined;
+  if (parent) {
+    parent.children.push(item.id);
+  } else {
+    state.rootItems.push(item.id);
+  }
+  return state;
+}
+
+// Remove an item along with its entry in rootItems or its parent's children
+export function removeItemFromState(state: WhiteboardState, id: string): WhiteboardState {
+  const parent = state.items[id]?.parentGroup;
+  if (parent && state.groups[parent]) {
+    state.groups[parent].children = state.groups[parent].children.filter((c) => c !== id);
+  }
+  state.rootItems = state.rootItems.filter((c) => c !== id);
+  delete state.items[id];
+  return state;
+}
+
 // Currently dragged item
 export const draggedItem = writable<string | null>(null);
 
@@ -170,7 +193,7 @@ export const exactMatch = derived(
 );
 
 function getItemPreview(item: WhiteboardItem): string {
-  if (item.content.data.type === "text") {
+  if (item.content.data.type !== "image") {
     return item.content.data.preview.substring(0, 30);
   }
   return "[画像]";
@@ -14,9 +14,32 @@ export interface ImageData {
   width: number;
   height: number;
   format: string;
+  // Downscaled PNGs generated at capture time
+  thumbnail?: string | null;
+  preview?: string | null;
 }
 
-export type ClipboardData = TextData | ImageData;
+export interface HtmlData {
+  type: "html";
+  html: string;
+  text: string;
+  preview: string;
+}
+
+export interface RtfData {
+  type: "rtf";
+  rtf: string;
+  text: string;
+  preview: string;
+}
+
+export interface FilesData {
+  type: "files";
+  uris: string[];
+  preview: string;
+}
+
+export type ClipboardData = TextData | ImageData | HtmlData | RtfData | FilesData;
 
 // Clipboard content
 export interface ClipboardContent {
@@ -25,6 +48,10 @@ export interface ClipboardContent {
   data: ClipboardData;
   copiedAt: string;
   source?: string;
+  sourceTitle?: string;
+  // Times copied while in the history; copiedAt is the first copy
+  copyCount: number;
+  lastCopiedAt: string;
 }
 
 // Position and Size
@@ -58,6 +85,7 @@ export interface Group {
   position: Position;
   collapsed: boolean;
   parentGroup: string | null;
+  // Ids of child groups and items, oldest first
   children: string[];
   shortcut: string | null;
   color: string | null; // Hex color for group
@@ -69,6 +97,7 @@ export interface Group {
 export interface WhiteboardState {
   items: Record<string, WhiteboardItem>;
   groups: Record<string, Group>;
+  // Ids of top-level groups and items, oldest first
   rootItems: string[];
 }
 
@@ -97,3 +126,163 @@ export interface ShortcutMatch {
   label: string | null;
   parentGroup: string | null;
 }
+
+// Clipboard monitor state (mirrors monitor::MonitorState)
+export type MonitorState =
+  | { state: "running" }
+  | { state: "paused"; until: string | null }
+  | { state: "shutdown" };
+
+// Persisted history settings (mirrors storage::history::HistoryPolicy)
+export type Retention =
+  | { mode: "never" }
+  | { mode: "days"; days: number }
+  | { mode: "forever" };
+
+export interface HistoryPolicy {
+  enabled: boolean;
+  text: Retention;
+  image: Retention;
+  numeric: Retention;
+  secure: Retention;
+  url: Retention;
+}
+
+// Database key (mirrors storage::persistent::LockState)
+export type KeyMode = "machine" | "password";
+
+export interface LockState {
+  mode: KeyMode;
+  locked: boolean;
+}
+
+// Argon2 cost of the database key (mirrors crypto::KdfParams)
+export interface KdfParams {
+  memoryKib: number;
+  iterations: number;
+  parallelism: number;
+}
+
+// Payload of the key-rotation-progress event
+export interface RekeyProgress {
+  done: number;
+  total: number;
+}
+
+// Automatic locking (mirrors lock::AutoLockPolicy)
+export interface AutoLockPolicy {
+  idleMinutes: number | null;
+  onScreenLock: boolean;
+}
+
+// Next steps of the whiteboard undo journal (mirrors storage::journal::UndoState)
+export interface UndoState {
+  undo: string | null;
+  redo: string | null;
+}
+
+// Whiteboard snapshots (mirrors storage::snapshot)
+export interface SnapshotInfo {
+  id: string;
+  label: string | null;
+  automatic: boolean;
+  groupCount: number;
+  itemCount: number;
+  createdAt: string;
+}
+
+export interface SnapshotPolicy {
+  intervalHours: number | null;
+  keep: number;
+}
+
+export interface EntryChange {
+  id: string;
+  name: string;
+  kind: "added" | "removed" | "changed";
+  fields: string[];
+}
+
+export interface SnapshotDiff {
+  groups: EntryChange[];
+  items: EntryChange[];
+}
+
+// Deleting groups (mirrors storage::persistent::DeleteMode)
+export type DeleteMode = "cascade" | "reparent" | "trash";
+
+// Restorable deleted item or group (mirrors storage::persistent::TrashEntry)
+export interface TrashEntry {
+  id: string;
+  kind: "item" | "group";
+  rootId: string;
+  name: string | null;
+  itemCount: number;
+  groupCount: number;
+  deletedAt: string;
+}
+
+// Clearing secrets from the clipboard (mirrors clipboard::auto_clear::AutoClearPolicy)
+export interface AutoClearPolicy {
+  clearAfterSecs: number | null;
+  restorePrevious: boolean;
+}
+
+// History dedupe (mirrors storage::volatile::DedupeMode)
+export type DedupeMode = "exact" | "normalizeWhitespace";
+
+// In-memory history bounds (mirrors storage::volatile::HistoryLimits)
+export interface HistoryLimits {
+  maxEntries: number;
+  maxTotalBytes: number;
+  maxItemBytes: number;
+}
+
+// Search (mirrors search::SearchQuery / SearchResult)
+export type SearchMode = "substring" | "fuzzy" | "regex";
+
+export interface SearchQuery {
+  query: string;
+  mode?: SearchMode;
+  category?: Category;
+  from?: string;
+  to?: string;
+  source?: string;
+  group?: string;
+  limit?: number;
+}
+
+// Offsets are UTF-16 code units, so they can be used with String.slice
+export interface MatchRange {
+  start: number;
+  end: number;
+}
+
+export interface SearchResult {
+  // Images from the persisted history come without the original (get_clipboard_content)
+  content: ClipboardContent;
+  score: number;
+  matches: MatchRange[];
+  whiteboardItemId: string | null;
+  groupId: string | null;
+}
+
+// History list projection without full payloads (mirrors types::ClipboardSummary)
+export interface ClipboardSummary {
+  id: string;
+  category: Category;
+  dataType: ClipboardData["type"];
+  preview: string;
+  thumbnail: string | null;
+  width: number | null;
+  height: number | null;
+  size: number;
+  copiedAt: string;
+  source: string | null;
+  sourceTitle: string | null;
+}
+
+export interface HistoryPage {
+  items: ClipboardSummary[];
+  nextCursor: string | null;
+}